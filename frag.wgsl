@@ -1,6 +1,7 @@
+// @param feedback = 0.5 [0.0, 1.0]
 @fragment
 fn main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
-    let texcoords = pos.xy/res.xy;
-    return 0.5 * textureSample(videoBuffer, videoSampler, texcoords) + 0.5 * textureSample(backBuffer, backSampler, texcoords);
+    let texcoords = fragCoord(pos)/res.xy;
+    return (1.0 - params.feedback) * textureSample(videoBuffer, videoSampler, texcoords) + params.feedback * textureSample(backBuffer, backSampler, texcoords);
 
 }
@@ -0,0 +1,281 @@
+// The control API: a local HTTP server for scripts and companion UIs, on `[api]` in the project.
+//
+//     POST /shader      source to compile in place of the shader file's, in the same language
+//     GET  /errors      what the last compile reported
+//     GET  /uniforms    param values by name
+//     PUT  /uniforms    a JSON object of param values to set
+//     POST /screenshot  save a screenshot, as F12 does
+//     GET  /frame.png   the last frame rendered
+//     GET  /events      a WebSocket sending the result of every compile as it happens
+//
+// Shaders compile on the server's thread, through the same path as file watching. Everything
+// else needs the App, so those requests wait in a channel for the event loop to answer them.
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, TryIter},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+use crate::{
+    appstate::RenderPipelineContext,
+    shader::{FragShader, Language},
+};
+
+// How long a request waits for the event loop, which answers once a frame
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+// How often event streams look for a new compile, and ping to notice clients that went away
+const POLL: Duration = Duration::from_millis(100);
+const PING_EVERY: u32 = 50;
+
+fn default_bind() -> String {
+    "127.0.0.1".to_owned()
+}
+
+fn default_port() -> u16 {
+    7878
+}
+
+// `[api]` in the project file. The server only runs when the section is there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiSettings {
+    // Anything but loopback lets other machines push shaders
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+// What the event loop is asked to do, with where to send the answer
+pub enum ApiRequest {
+    Uniforms(Sender<BTreeMap<String, f32>>),
+    // Answers with the names the shader doesn't declare
+    SetUniforms(BTreeMap<String, f32>, Sender<Vec<String>>),
+    Screenshot(Sender<io::Result<PathBuf>>),
+    // As a PNG
+    Frame(Sender<io::Result<Vec<u8>>>),
+}
+
+pub struct ApiServer {
+    server: Arc<Server>,
+    addr: SocketAddr,
+    requests: Receiver<ApiRequest>,
+    stop: Arc<AtomicBool>,
+}
+
+// Shared by the request thread and event streams
+struct Handler {
+    rpctx: Arc<RwLock<RenderPipelineContext>>,
+    // Posted shaders resolve includes against it
+    shader_path: PathBuf,
+    requests: Sender<ApiRequest>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ApiServer {
+    pub fn open(
+        settings: &ApiSettings,
+        rpctx: Arc<RwLock<RenderPipelineContext>>,
+        shader_path: PathBuf,
+    ) -> io::Result<Self> {
+        let addr = format!("{}:{}", settings.bind, settings.port);
+        let server = Server::http(&addr).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("Can't serve the API on {addr}: {e}"),
+            )
+        })?;
+        let addr = server.server_addr().to_ip().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "API needs an IP address")
+        })?;
+        let server = Arc::new(server);
+        let (sender, requests) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handler = Handler {
+            rpctx,
+            shader_path,
+            requests: sender,
+            stop: stop.clone(),
+        };
+        let incoming = server.clone();
+        thread::spawn(move || {
+            // Errs once the server is dropped
+            while let Ok(request) = incoming.recv() {
+                let url = request.url().to_owned();
+                if let Err(e) = handler.handle(request) {
+                    println!("API request {url} failed: {e}");
+                }
+            }
+        });
+
+        Ok(Self {
+            server,
+            addr,
+            requests,
+            stop,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // Requests waiting for the event loop
+    pub fn try_iter(&self) -> TryIter<'_, ApiRequest> {
+        self.requests.try_iter()
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.server.unblock();
+    }
+}
+
+fn json_response(status: u16, body: serde_json::Value) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("header names and values are ASCII")
+}
+
+fn error(status: u16, message: impl ToString) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(status, json!({ "error": message.to_string() }))
+}
+
+fn compile_event(rpctx: &RenderPipelineContext) -> serde_json::Value {
+    json!({
+        "event": "compile",
+        "compiles": rpctx.compiles,
+        "ok": rpctx.errors.is_empty(),
+        "errors": rpctx.errors,
+    })
+}
+
+impl Handler {
+    fn handle(&self, mut request: Request) -> io::Result<()> {
+        let path = request.url().split('?').next().unwrap_or("").to_owned();
+        let response = match (request.method(), path.as_str()) {
+            (Method::Post, "/shader") => {
+                let mut body = vec![];
+                request.as_reader().read_to_end(&mut body)?;
+                let frag = match Language::of(&self.shader_path) {
+                    Language::SpirV => FragShader::from_spirv(&self.shader_path, body),
+                    _ => match String::from_utf8(body) {
+                        Ok(source) => FragShader::new(&self.shader_path, source),
+                        Err(e) => return request.respond(error(400, e)),
+                    },
+                };
+                RenderPipelineContext::rebuild(&self.rpctx, frag);
+                let rpctx = self.rpctx.read();
+                let status = if rpctx.errors.is_empty() { 200 } else { 422 };
+                json_response(status, compile_event(&rpctx))
+            }
+            (Method::Get, "/errors") => json_response(200, compile_event(&self.rpctx.read())),
+            (Method::Get, "/uniforms") => match self.ask(ApiRequest::Uniforms) {
+                Some(values) => json_response(200, json!(values)),
+                None => error(503, "the workbench didn't answer"),
+            },
+            (Method::Put, "/uniforms") => {
+                let values: BTreeMap<String, f32> =
+                    match serde_json::from_reader(request.as_reader()) {
+                        Ok(values) => values,
+                        Err(e) => return request.respond(error(400, e)),
+                    };
+                match self.ask(|reply| ApiRequest::SetUniforms(values, reply)) {
+                    Some(unknown) => {
+                        json_response(200, json!({ "ok": unknown.is_empty(), "unknown": unknown }))
+                    }
+                    None => error(503, "the workbench didn't answer"),
+                }
+            }
+            (Method::Post, "/screenshot") => match self.ask(ApiRequest::Screenshot) {
+                Some(Ok(path)) => json_response(200, json!({ "path": path })),
+                Some(Err(e)) => error(500, e),
+                None => error(503, "the workbench didn't answer"),
+            },
+            (Method::Get, "/frame.png") => match self.ask(ApiRequest::Frame) {
+                Some(Ok(png)) => {
+                    return request.respond(
+                        Response::from_data(png).with_header(header("Content-Type", "image/png")),
+                    )
+                }
+                Some(Err(e)) => error(500, e),
+                None => error(503, "the workbench didn't answer"),
+            },
+            (Method::Get, "/events") => return self.events(request),
+            _ => error(404, format!("no {} {path}", request.method())),
+        };
+        request.respond(response)
+    }
+
+    // Hand a request to the event loop and wait for the answer
+    fn ask<T>(&self, request: impl FnOnce(Sender<T>) -> ApiRequest) -> Option<T> {
+        let (reply, answer) = mpsc::channel();
+        self.requests.send(request(reply)).ok()?;
+        answer.recv_timeout(ANSWER_TIMEOUT).ok()
+    }
+
+    // Upgrade to a WebSocket, and send the state of the last compile and every one after it
+    fn events(&self, request: Request) -> io::Result<()> {
+        let key = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+            .map(|h| h.value.to_string());
+        let Some(key) = key else {
+            return request.respond(error(400, "/events is a WebSocket"));
+        };
+        let response = Response::empty(101).with_header(header(
+            "Sec-WebSocket-Accept",
+            &derive_accept_key(key.as_bytes()),
+        ));
+        let stream = request.upgrade("websocket", response);
+
+        let (rpctx, stop) = (self.rpctx.clone(), self.stop.clone());
+        thread::spawn(move || {
+            let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+            let (mut seen, mut polls) = (0, 0);
+            while !stop.load(Ordering::Relaxed) {
+                let event = {
+                    let rpctx = rpctx.read();
+                    (rpctx.compiles != seen).then(|| {
+                        seen = rpctx.compiles;
+                        compile_event(&rpctx)
+                    })
+                };
+                polls += 1;
+                let sent = match event {
+                    Some(event) => socket.send(Message::Text(event.to_string())),
+                    None if polls % PING_EVERY == 0 => socket.send(Message::Ping(vec![])),
+                    None => Ok(()),
+                };
+                if sent.is_err() {
+                    return;
+                }
+                thread::sleep(POLL);
+            }
+            let _ = socket.close(None);
+        });
+        Ok(())
+    }
+}
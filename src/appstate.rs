@@ -1,278 +1,365 @@
 // Much of this code is boilerplate shamelessly stolen from https://sotrh.github.io/learn-wgpu
 
 use parking_lot::RwLock;
-use std::{io, sync::Arc};
-
-use wgpu::{
-    self, include_wgsl, util::DeviceExt, BindGroupLayoutDescriptor, Buffer, Extent3d,
-    ImageCopyTexture, Texture, TextureFormat, TextureView,
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use wgpu::{self, include_wgsl, TextureFormat};
+
 // lib.rs
-use winit::{event::WindowEvent, window::Window};
+use winit::{
+    event::{
+        ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent,
+    },
+    window::Window,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    api::{ApiRequest, ApiServer},
+    debug_print::DebugWindow,
+    session::{Header, Recorder, Replay, Session, Step},
+};
+use crate::{
+    capture,
+    cli::{GpuArgs, PresentMode},
+    clock::Clock,
+    gpu,
+    gui::Gui,
+    input::{InputLayout, InputSource, Inputs, Upload},
+    inspector::Inspector,
+    midi::{MidiControl, MidiPort},
+    osc::{OscCommand, OscServer},
+    param_panel::ParamPanel,
+    params::ParamDecl,
+    poster,
+    preset::{Morph, Preset, PresetBank},
+    project::Project,
+    renderer::Renderer,
+    shader::{self, DebugSite, FragShader},
+    texture_viewer::TextureViewer,
+    timeline::TimelineStrip,
+    timing::{FrameTimer, TimingGraph},
+};
 
 pub struct ValidationError {
-    description: String,
+    pub description: String,
 }
 
 pub struct RenderPipelineContext {
-    pub device: wgpu::Device,
+    pub device: Arc<wgpu::Device>,
     pub pipeline: wgpu::RenderPipeline,
     pub pipeline_layout: wgpu::PipelineLayout,
-    pub surface_config: wgpu::SurfaceConfiguration,
+    pub format: TextureFormat,
     pub validation_errors: Arc<RwLock<Vec<ValidationError>>>,
-}
-
-fn read_frag_shader(path: &str) -> io::Result<String> {
-    let frag_str = std::fs::read_to_string(path)?;
-    const PRELUDE: &str = "
-    @group(0) @binding(0)
-    var<uniform> res: vec2<f32>;
-    @group(0) @binding(1)
-    var<uniform> frame: u32;
-    @group(0) @binding(2)
-    var videoBuffer: texture_2d<f32>;
-    @group(0) @binding(3)
-    var videoSampler: sampler;
-    @group(1) @binding(0)
-    var backBuffer: texture_2d<f32>;
-    @group(1) @binding(1)
-    var backSampler: sampler;";
-
-    Ok([PRELUDE, &frag_str].join("\n"))
+    pub param_decls: Vec<ParamDecl>,
+    // The user's source for the current pipeline, before the prelude is injected
+    pub frag_source: String,
+    pub debug_sites: Vec<DebugSite>,
+    // Files pulled in by the shader, which the file watcher also reloads on
+    pub includes: Vec<PathBuf>,
+    // The input bindings, declared to every shader along with the prelude
+    pub inputs: InputLayout,
+    // What the last compile reported, empty if it went through. Counted so other threads can
+    // tell when there's been another.
+    pub errors: Vec<String>,
+    pub compiles: u64,
 }
 
 impl RenderPipelineContext {
-    pub async fn rebuild_pipeline(lock: Arc<RwLock<Self>>, frag_path: &str) {
-        let read = lock.read();
-        let vert = read
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("Vertex Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("vert_default.wgsl").into()),
-            });
+    // Compile `frag_shader`, falling back to the default shader if there's none or it doesn't validate
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        pipeline_layout: wgpu::PipelineLayout,
+        format: TextureFormat,
+        validation_errors: Arc<RwLock<Vec<ValidationError>>>,
+        frag_shader: Option<FragShader>,
+        inputs: InputLayout,
+    ) -> Self {
+        let default = || {
+            let frag = device.create_shader_module(include_wgsl!("frag_default.wgsl"));
+            create_pipeline(&device, &pipeline_layout, &frag, "fs_main", format)
+        };
 
-        if let Ok(frag_str) = read_frag_shader(frag_path) {
-            let frag = unsafe {
-                read.device
-                    .create_shader_module_unchecked(wgpu::ShaderModuleDescriptor {
-                        label: Some("Fragment Shader"),
-                        source: wgpu::ShaderSource::Wgsl(frag_str.into()),
-                    })
-            };
-
-            if read.validation_errors.read().len() > 0 {
-                let mut ve_wr = read.validation_errors.write();
-                while ve_wr.len() > 0 {
-                    println!("Validation Error: {:}", ve_wr.pop().unwrap().description);
+        let (mut param_decls, mut frag_source, mut includes) = (vec![], String::new(), vec![]);
+        let mut debug_sites = vec![];
+        let mut errors = vec![];
+        let pipeline = match frag_shader {
+            Some(mut frag_shader) => {
+                includes = frag_shader.include_paths();
+                frag_shader.declare(&inputs);
+                for e in &frag_shader.import_errors {
+                    let description = frag_shader.describe(e);
+                    println!("Validation Error: {description}");
+                    errors.push(description);
+                }
+                let pipeline = errors.is_empty().then(|| {
+                    let frag = unsafe {
+                        device.create_shader_module_unchecked(wgpu::ShaderModuleDescriptor {
+                            label: Some("Fragment Shader"),
+                            source: wgpu::ShaderSource::Wgsl(frag_shader.wgsl.as_str().into()),
+                        })
+                    };
+                    create_pipeline(&device, &pipeline_layout, &frag, "main", format)
+                });
+
+                if !validation_errors.read().is_empty() {
+                    let mut ve_wr = validation_errors.write();
+                    while let Some(e) = ve_wr.pop() {
+                        println!("Validation Error: {:}", e.description);
+                        errors.push(e.description);
+                    }
+                }
+                match pipeline {
+                    Some(pipeline) if errors.is_empty() => {
+                        param_decls = frag_shader.param_decls;
+                        frag_source = frag_shader.source;
+                        debug_sites = frag_shader.debug_sites;
+                        pipeline
+                    }
+                    _ => default(),
                 }
-                return;
             }
+            None => default(),
+        };
+
+        Self {
+            device,
+            pipeline,
+            pipeline_layout,
+            format,
+            validation_errors,
+            param_decls,
+            frag_source,
+            debug_sites,
+            includes,
+            inputs,
+            errors,
+            compiles: 1,
+        }
+    }
 
-            let render_pipeline =
-                read.device
-                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                        label: Some("Render Pipeline"),
-                        layout: Some(&read.pipeline_layout),
-                        vertex: wgpu::VertexState {
-                            module: &vert,
-                            entry_point: "vs_main", // 1.
-                            buffers: &[],           // 2.
-                        },
-                        fragment: Some(wgpu::FragmentState {
-                            // 3.
-                            module: &frag,
-                            entry_point: "main",
-                            targets: &[Some(wgpu::ColorTargetState {
-                                // 4.
-                                format: read.surface_config.format,
-                                blend: Some(wgpu::BlendState::REPLACE),
-                                write_mask: wgpu::ColorWrites::ALL,
-                            })],
-                        }),
-                        primitive: wgpu::PrimitiveState {
-                            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                            strip_index_format: None,
-                            front_face: wgpu::FrontFace::Ccw, // 2.
-                            cull_mode: Some(wgpu::Face::Back),
-                            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                            polygon_mode: wgpu::PolygonMode::Fill,
-                            // Requires Features::DEPTH_CLIP_CONTROL
-                            unclipped_depth: false,
-                            // Requires Features::CONSERVATIVE_RASTERIZATION
-                            conservative: false,
-                        },
-                        depth_stencil: None, // 1.
-                        multisample: wgpu::MultisampleState {
-                            count: 1,                         // 2.
-                            mask: !0,                         // 3.
-                            alpha_to_coverage_enabled: false, // 4.
-                        },
-                        multiview: None, // 5.
-                    });
+    pub async fn rebuild_pipeline(lock: Arc<RwLock<Self>>, frag_path: &str) {
+        if let Ok(frag_shader) = shader::read_frag_shader(Path::new(frag_path)) {
+            Self::rebuild(&lock, frag_shader);
+        }
+    }
+
+    // Swap in a new shader, printing what's wrong with it if it doesn't validate. File watching
+    // and the control API both come through here.
+    pub fn rebuild(lock: &RwLock<Self>, frag_shader: FragShader) {
+        if let Err(errors) = Self::set_shader(lock, frag_shader) {
+            for e in errors {
+                println!("Validation Error: {}", e.description);
+            }
+        }
+    }
 
+    // Swap in a new shader, keeping the current pipeline if it doesn't validate
+    pub fn set_shader(
+        lock: &RwLock<Self>,
+        mut frag_shader: FragShader,
+    ) -> Result<(), Vec<ValidationError>> {
+        let read = lock.read();
+        let includes = frag_shader.include_paths();
+        frag_shader.declare(&read.inputs);
+        if !frag_shader.import_errors.is_empty() {
+            let errors: Vec<ValidationError> = frag_shader
+                .import_errors
+                .iter()
+                .map(|e| ValidationError {
+                    description: frag_shader.describe(e),
+                })
+                .collect();
             drop(read);
             let mut write = lock.write();
-            write.pipeline = render_pipeline;
+            write.includes = includes;
+            write.errors = errors.iter().map(|e| e.description.clone()).collect();
+            write.compiles += 1;
+            return Err(errors);
         }
+        let frag = unsafe {
+            read.device
+                .create_shader_module_unchecked(wgpu::ShaderModuleDescriptor {
+                    label: Some("Fragment Shader"),
+                    source: wgpu::ShaderSource::Wgsl(frag_shader.wgsl.into()),
+                })
+        };
+        let render_pipeline = create_pipeline(
+            &read.device,
+            &read.pipeline_layout,
+            &frag,
+            "main",
+            read.format,
+        );
+
+        let errors: Vec<ValidationError> = read.validation_errors.write().drain(..).collect();
+        drop(read);
+        let mut write = lock.write();
+        // Still watch new includes, the fix might be in one of them
+        write.includes = includes;
+        write.errors = errors.iter().map(|e| e.description.clone()).collect();
+        write.compiles += 1;
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        write.pipeline = render_pipeline;
+        write.param_decls = frag_shader.param_decls;
+        write.frag_source = frag_shader.source;
+        write.debug_sites = frag_shader.debug_sites;
+        Ok(())
     }
 }
 
-pub struct BackBuffer {
-    bind_group: wgpu::BindGroup,
-    bind_group_layout: wgpu::BindGroupLayout,
-    sample_texture: Texture,
-    sample_texture_view: TextureView,
+// The fullscreen quad vertex shader paired with `frag`
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    frag: &wgpu::ShaderModule,
+    entry_point: &str,
     format: TextureFormat,
+) -> wgpu::RenderPipeline {
+    let vert = device.create_shader_module(include_wgsl!("vert_default.wgsl"));
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &vert,
+            entry_point: "vs_main", // 1.
+            buffers: &[],           // 2.
+        },
+        fragment: Some(wgpu::FragmentState {
+            // 3.
+            module: frag,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                // 4.
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw, // 2.
+            cull_mode: Some(wgpu::Face::Back),
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: None, // 1.
+        multisample: wgpu::MultisampleState {
+            count: 1,                         // 2.
+            mask: !0,                         // 3.
+            alpha_to_coverage_enabled: false, // 4.
+        },
+        multiview: None, // 5.
+    })
 }
 
-impl BackBuffer {}
-
 pub struct App {
     pub surface: wgpu::Surface,
-    pub queue: wgpu::Queue,
-    pub rpcontext: Arc<RwLock<RenderPipelineContext>>,
+    pub surface_config: wgpu::SurfaceConfiguration,
+    pub renderer: Renderer,
     pub size: winit::dpi::PhysicalSize<u32>,
     pub window: Window,
-    pub unif_bind_group: wgpu::BindGroup,
-    pub backbuffer: BackBuffer,
-    pub res_buffer_unif: Buffer,
-    pub frame_unif: Buffer,
-    pub frame: u32,
-    pub camera_texture: Texture,
-    pub camera_dims: (u32, u32),
+    pub clock: Clock,
+    pub presets: PresetBank,
+    pub morph: Option<Morph>,
+    pub modifiers: ModifiersState,
+    pub project: Project,
+    pub gui: Gui,
+    pub timeline_strip: TimelineStrip,
+    pub param_panel: ParamPanel,
+    pub inspector: Inspector,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub debug_window: DebugWindow,
+    pub timing_graph: TimingGraph,
+    timer: FrameTimer,
+    pub texture_viewer: TextureViewer,
+    // In window pixels, None while it's outside
+    pub cursor: Option<(f32, f32)>,
+    // Feeds videoBuffer
+    video: Option<Box<dyn InputSource>>,
+    inputs: Inputs,
+    osc: Option<OscServer>,
+    midi: Option<MidiPort>,
+    #[cfg(not(target_arch = "wasm32"))]
+    api: Option<ApiServer>,
+    #[cfg(not(target_arch = "wasm32"))]
+    session: Option<Session>,
 }
 
 impl App {
-    fn bb_refresh(&mut self, size: (u32, u32)) {
-        let bb_texture_size = wgpu::Extent3d {
-            width: size.0,
-            height: size.1,
-            depth_or_array_layers: 1,
+    // Snapshot the current params and clock time into a preset slot, and write the bank to disk
+    pub fn save_preset(&mut self, slot: u8) {
+        let name = match self.presets.get(slot) {
+            Some(p) => p.name.clone(),
+            None => format!("Preset {slot}"),
         };
+        self.presets.store(Preset {
+            name,
+            slot,
+            time: self.clock.time(),
+            values: self.renderer.params.values(),
+        });
 
-        let bb_sample_texture =
-            self.rpcontext
-                .write()
-                .device
-                .create_texture(&wgpu::TextureDescriptor {
-                    size: bb_texture_size,
-                    mip_level_count: 1,
-                    sample_count: 1,
-                    dimension: wgpu::TextureDimension::D2,
-                    format: self.backbuffer.format,
-                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                    label: Some("Back-Buffer Sample Texture"),
-                    view_formats: &[],
-                });
-
-        let bb_sample_texture_view =
-            bb_sample_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let bb_sampler = self
-            .rpcontext
-            .write()
-            .device
-            .create_sampler(&wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
-            });
-
-        let bb_bind_group =
-            self.rpcontext
-                .write()
-                .device
-                .create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &self.backbuffer.bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&bb_sample_texture_view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&bb_sampler),
-                        },
-                    ],
-                    label: Some("bb_bind_group"),
-                });
-
-        self.backbuffer.bind_group = bb_bind_group;
-        self.backbuffer.sample_texture = bb_sample_texture;
-        self.backbuffer.sample_texture_view = bb_sample_texture_view;
+        match self.presets.save() {
+            Ok(_) => println!("Saved preset {slot}"),
+            Err(e) => println!("Failed to save presets: {e}"),
+        }
     }
 
-    pub fn update_camera(&mut self, pix: &[u8]) {
-        let image_cpy = ImageCopyTexture {
-            texture: &self.camera_texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
+    // Jump to a preset's clock time, and either snap or morph its param values in
+    pub fn recall_preset(&mut self, slot: u8, morph: bool) {
+        let Some(preset) = self.presets.get(slot) else {
+            println!("No preset in slot {slot}");
+            return;
         };
-        self.queue.write_texture(
-            image_cpy,
-            pix,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * self.camera_dims.0),
-                rows_per_image: Some(self.camera_dims.1),
-            },
-            Extent3d {
-                width: self.camera_dims.0,
-                height: self.camera_dims.1,
-                depth_or_array_layers: 1,
-            },
-        )
+        println!("Recalling preset {slot}: {}", preset.name);
+
+        self.clock.set_time(preset.time);
+        if morph {
+            self.morph = Some(Morph::new(
+                self.renderer.params.values(),
+                preset.values.clone(),
+                self.presets.morph_seconds,
+            ));
+        } else {
+            self.renderer.params.apply(&preset.values);
+            self.morph = None;
+        }
     }
 
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: Window, camera_dim: (u32, u32), frag_file: &String) -> Self {
+    pub async fn new(
+        window: Window,
+        video: Option<Box<dyn InputSource>>,
+        project: Project,
+        gpu: &GpuArgs,
+        present_mode: PresentMode,
+    ) -> io::Result<Self> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
+        let instance = gpu::instance(gpu);
 
         // # Safety
         //
         // The surface needs to live as long as the window that created it.
         // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+        let surface = unsafe { instance.create_surface(&window) }.map_err(io::Error::other)?;
+
+        let adapter = gpu::adapter(&instance, gpu, Some(&surface)).await?;
+        let (device, queue) = gpu::device(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
         // Shader code in this tutorial assumes an sRGB surface texture. Using a different
@@ -284,332 +371,547 @@ impl App {
             .copied()
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
+        // The Auto modes always work, anything else has to be supported by the surface
+        let present_mode = match present_mode.into() {
+            wgpu::PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            wgpu::PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            mode if surface_caps.present_modes.contains(&mode) => mode,
+            mode => {
+                println!("Present mode {mode:?} isn't supported, using vsync");
+                wgpu::PresentMode::AutoVsync
+            }
+        };
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
         surface.configure(&device, &config);
 
-        // Set up camera texture
+        let shader_path = project.shader_path();
+        let target_size = project.resolution.target_size((size.width, size.height));
+        let gui = Gui::new(&window, &device, surface_format);
+        let timer = FrameTimer::new(&device, &queue);
+        let texture_viewer = TextureViewer::new(&device);
+        let mut inputs = Inputs::open(&project)?;
+        // Sources that follow the cursor need the window size before the first resize
+        inputs.window_event(&WindowEvent::Resized(size));
+        let renderer = Renderer::new(
+            device,
+            queue,
+            surface_format,
+            target_size,
+            &inputs.layout(),
+            &shader_path,
+            shader::read_frag_shader(&shader_path).ok(),
+        );
 
-        let camera_texture_size = wgpu::Extent3d {
-            width: camera_dim.0,
-            height: camera_dim.1,
-            depth_or_array_layers: 1,
-        };
+        let osc = project.osc.as_ref().map(OscServer::open).transpose()?;
+        if let Some(osc) = &osc {
+            println!("Listening for OSC on {}", osc.local_addr());
+        }
 
-        let camera_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: camera_texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("Camera Texture"),
-            view_formats: &[],
-        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let api = project
+            .api
+            .as_ref()
+            .map(|api| ApiServer::open(api, renderer.rpcontext.clone(), shader_path.clone()))
+            .transpose()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(api) = &api {
+            println!("Serving the control API on http://{}", api.local_addr());
+        }
 
-        let camera_texture_view =
-            camera_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let camera_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
+        // A missing controller shouldn't stop the show
+        let midi = project.midi.as_ref().and_then(|midi| {
+            MidiPort::open(midi.port.as_deref())
+                .map_err(|e| println!("Failed to open MIDI input: {e}"))
+                .ok()
         });
 
-        // Set up back buffer texture
-
-        let bb_sample_texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: surface_format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("Back-Buffer Texture"),
-            view_formats: &[],
+        let presets = PresetBank::load(&shader_path).unwrap_or_else(|e| {
+            println!("Failed to load presets: {e}");
+            PresetBank::new(&shader_path)
         });
 
-        let bb_sample_texture_view =
-            bb_sample_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let bb_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
+        Ok(Self {
+            window,
+            surface,
+            surface_config: config,
+            renderer,
+            size,
+            clock: Clock::new(),
+            presets,
+            morph: None,
+            modifiers: ModifiersState::empty(),
+            project,
+            gui,
+            timeline_strip: TimelineStrip::default(),
+            param_panel: ParamPanel::default(),
+            inspector: Inspector::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            debug_window: DebugWindow::default(),
+            timing_graph: TimingGraph::default(),
+            timer,
+            texture_viewer,
+            cursor: None,
+            video,
+            inputs,
+            osc,
+            midi,
+            #[cfg(not(target_arch = "wasm32"))]
+            api,
+            #[cfg(not(target_arch = "wasm32"))]
+            session: None,
+        })
+    }
 
-        // Set up uniforms (resolution, framecount, etc)
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
 
-        let res_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Resolution Uniform"),
-            contents: bytemuck::cast_slice(&[0f32; 2]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.surface_config.width = new_size.width;
+            self.surface_config.height = new_size.height;
+            self.surface
+                .configure(&self.renderer.rpcontext.read().device, &self.surface_config);
+
+            let policy = &self.project.resolution;
+            self.renderer.resize(
+                policy.target_size((new_size.width, new_size.height)),
+                policy.preserve_feedback,
+            );
+        }
+    }
 
-        let frame_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Frame Count Uniform"),
-            contents: bytemuck::cast_slice(&[0u32, 1]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        match &mut self.session {
+            Some(Session::Recording(recorder)) => recorder.event(event),
+            // Live input would throw the replay off, but the window can still be closed
+            Some(Session::Replaying(_)) => {
+                return !matches!(
+                    event,
+                    WindowEvent::CloseRequested
+                        | WindowEvent::KeyboardInput {
+                            input: KeyboardInput {
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                            ..
+                        }
+                );
+            }
+            None => {}
+        }
+        self.handle_input(event)
+    }
 
-        let unif_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
+    fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        self.inputs.window_event(event);
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some((position.x as f32, position.y as f32));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            _ => {}
+        }
+        if self.gui.on_event(event) {
+            return true;
+        }
+
+        match event {
+            #[cfg(not(target_arch = "wasm32"))]
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.modifiers.ctrl() && !self.is_replaying() => {
+                self.pick_debug_pixel();
+                true
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                false
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
                     },
-                ],
-                label: Some("unif_bind_group_layout"),
+                ..
+            } => {
+                // Replays show what happened without writing screenshots, posters, the project or
+                // presets again
+                let writes_files = *key == VirtualKeyCode::F12
+                    || self.modifiers.ctrl()
+                        && (*key == VirtualKeyCode::S || preset_slot(*key).is_some());
+                if writes_files && self.is_replaying() {
+                    return true;
+                }
+                match key {
+                    VirtualKeyCode::Tab => {
+                        self.gui.visible = !self.gui.visible;
+                        return true;
+                    }
+                    VirtualKeyCode::I if self.modifiers.ctrl() => {
+                        self.inspector.open = !self.inspector.open;
+                        self.gui.visible |= self.inspector.open;
+                        return true;
+                    }
+                    VirtualKeyCode::T if self.modifiers.ctrl() => {
+                        self.timing_graph.open = !self.timing_graph.open;
+                        self.gui.visible |= self.timing_graph.open;
+                        return true;
+                    }
+                    VirtualKeyCode::R if self.modifiers.ctrl() => {
+                        self.texture_viewer.open = !self.texture_viewer.open;
+                        self.gui.visible |= self.texture_viewer.open;
+                        return true;
+                    }
+                    VirtualKeyCode::Space => {
+                        self.clock.toggle_pause();
+                        return true;
+                    }
+                    VirtualKeyCode::F12 if self.modifiers.ctrl() => {
+                        self.poster();
+                        return true;
+                    }
+                    VirtualKeyCode::F12 => {
+                        self.screenshot();
+                        return true;
+                    }
+                    VirtualKeyCode::S if self.modifiers.ctrl() => {
+                        match self.project.save() {
+                            Ok(_) => println!("Saved project {}", self.project.path().display()),
+                            Err(e) => println!("Failed to save project: {e}"),
+                        }
+                        return true;
+                    }
+                    _ => {}
+                }
+
+                let Some(slot) = preset_slot(*key) else {
+                    return false;
+                };
+                // Ctrl+N saves, Shift+N recalls instantly, N morphs to the preset
+                if self.modifiers.ctrl() {
+                    self.save_preset(slot);
+                } else {
+                    self.recall_preset(slot, !self.modifiers.shift());
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn update(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let step = self.replay_step();
+        if let Some(video) = &mut self.video {
+            video.poll();
+            if let Some(Upload::Texture {
+                width,
+                height,
+                rgba,
+            }) = video.upload(0)
+            {
+                self.renderer.upload_video(width, height, rgba);
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(Session::Recording(recorder)) = &mut self.session {
+                    recorder.camera(rgba);
+                }
+            }
+        }
+        self.inputs.update(&mut self.renderer);
+
+        self.renderer.sync_params();
+        self.apply_osc();
+        self.apply_midi();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.apply_api();
+        if self.gui.visible && !self.gui.ctx.is_pointer_over_area() {
+            let window = (self.size.width, self.size.height);
+            let pixel = self.cursor.and_then(|cursor| {
+                let policy = &self.project.resolution;
+                policy.window_to_target(self.renderer.size(), window, cursor)
             });
+            self.inspector.update(&self.renderer, pixel);
+        }
+        let params = &mut self.renderer.params;
 
-        let unif_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &unif_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: res_unif.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: frame_unif.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&camera_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&camera_sampler),
-                },
-            ],
-            label: Some("unif_bind_group"),
-        });
+        if let Some(morph) = &self.morph {
+            params.apply(&morph.sample());
+            if morph.is_done() {
+                self.morph = None;
+            }
+        }
 
-        let bb_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("bb_bind_group_layout"),
+        let rpctx = self.renderer.rpcontext.read();
+        let (decls, debug_sites) = (rpctx.param_decls.clone(), rpctx.debug_sites.clone());
+        drop(rpctx);
+        let midi = self.project.midi.as_mut().map(|midi| &mut midi.map);
+        self.gui.run(&self.window, |ctx| {
+            self.timeline_strip
+                .show(ctx, &mut self.project.timeline, &mut self.clock, params);
+            self.param_panel.show(ctx, &decls, params, midi);
+            self.inspector.show(ctx);
+            self.timing_graph.show(ctx);
+            self.texture_viewer.show(ctx);
+            #[cfg(not(target_arch = "wasm32"))]
+            self.debug_window
+                .show(ctx, &mut self.renderer.debug, &debug_sites);
         });
 
-        let bb_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bb_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&bb_sample_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&bb_sampler),
-                },
-            ],
-            label: Some("bb_bind_group"),
-        });
+        // Animated params follow the timeline, overriding presets and manual edits
+        self.renderer.time = self.clock.time();
+        let animated = self.project.timeline.sample(self.renderer.time);
+        self.renderer.params.apply(&animated);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.end_session_frame(step);
+    }
 
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&unif_bind_group_layout, &bb_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+    // Record this session to `path`, from the next frame on
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn record(&mut self, path: &Path) -> io::Result<()> {
+        let window = (self.size.width, self.size.height);
+        let source = self.renderer.rpcontext.read().frag_source.clone();
+        let header = Header::new(&self.project, window, &source);
+        self.session = Some(Session::Recording(Recorder::create(path, &header)?));
+        Ok(())
+    }
 
-        let validation_errors = Arc::new(RwLock::new(vec![]));
-        let c_validation_errors = validation_errors.clone();
+    // Drive the App from a recorded session instead of live input, until it runs out
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn replay(&mut self, replay: Replay) {
+        replay.check_shader(&self.renderer.rpcontext.read().frag_source);
+        self.session = Some(Session::Replaying(replay));
+    }
 
-        device.on_uncaptured_error(Box::new(move |e| match e {
-            wgpu::Error::OutOfMemory { .. } => panic!("Device out of memory!"),
-            wgpu::Error::Validation { description, .. } => {
-                println!("validation error! {:}", description);
-                c_validation_errors
-                    .write()
-                    .push(ValidationError { description });
-            }
-        }));
-
-        let frag = match read_frag_shader(&frag_file) {
-            Ok(s) => {
-                let frag = unsafe {
-                    device.create_shader_module_unchecked(wgpu::ShaderModuleDescriptor {
-                        label: Some("Fragment Shader"),
-                        source: wgpu::ShaderSource::Wgsl(s.into()),
-                    })
-                };
+    #[cfg(not(target_arch = "wasm32"))]
+    fn is_replaying(&self) -> bool {
+        matches!(self.session, Some(Session::Replaying(_)))
+    }
 
-                if validation_errors.read().len() > 0 {
-                    let mut ve_wr = validation_errors.write();
-                    while ve_wr.len() > 0 {
-                        println!("Validation Error: {:}", ve_wr.pop().unwrap().description);
-                    }
-                    device.create_shader_module(include_wgsl!("frag_default.wgsl"))
-                } else {
-                    frag
+    #[cfg(target_arch = "wasm32")]
+    fn is_replaying(&self) -> bool {
+        false
+    }
+
+    // Play the next replayed step's events, before anything else in the frame
+    #[cfg(not(target_arch = "wasm32"))]
+    fn replay_step(&mut self) -> Option<Step> {
+        let Some(Session::Replaying(replay)) = &mut self.session else {
+            return None;
+        };
+        let Some(step) = replay.step() else {
+            println!("Replay finished, back to live input");
+            self.session = None;
+            return None;
+        };
+        for event in &step.events {
+            if !self.handle_input(event) {
+                if let WindowEvent::Resized(size) = event {
+                    self.window.set_inner_size(*size);
+                    self.resize(*size);
                 }
             }
-            Err(_) => device.create_shader_module(include_wgsl!("frag_default.wgsl")),
-        };
+        }
+        // So the timeline strip shows the recorded time too
+        self.clock.set_time(step.time);
+        Some(step)
+    }
 
-        let vert = device.create_shader_module(include_wgsl!("vert_default.wgsl"));
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vert,
-                entry_point: "vs_main", // 1.
-                buffers: &[],           // 2.
-            },
-            fragment: Some(wgpu::FragmentState {
-                // 3.
-                module: &frag,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    // 4.
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,                         // 2.
-                mask: !0,                         // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-        });
+    // Write the frame to the session being recorded, or pin it to the replayed step's time and
+    // params, whatever live OSC, MIDI or morphs did to them
+    #[cfg(not(target_arch = "wasm32"))]
+    fn end_session_frame(&mut self, step: Option<Step>) {
+        if let Some(step) = step {
+            self.renderer.time = step.time;
+            self.renderer.params.apply(&step.params);
+        }
+        let Some(Session::Recording(recorder)) = &mut self.session else {
+            return;
+        };
+        let params = self.renderer.params.values();
+        if let Err(e) = recorder.frame(self.renderer.time, params) {
+            println!("Failed to record session, stopped recording: {e}");
+            self.session = None;
+        }
+    }
 
-        let rpctx = Arc::new(RwLock::new(RenderPipelineContext {
-            device,
-            pipeline: render_pipeline,
-            pipeline_layout: render_pipeline_layout,
-            surface_config: config,
-            validation_errors,
-        }));
+    // Commands the OSC thread received since the last frame
+    fn apply_osc(&mut self) {
+        let Some(osc) = &self.osc else {
+            return;
+        };
+        let commands: Vec<OscCommand> = osc.try_iter().collect();
+        for command in commands {
+            match command {
+                OscCommand::SetParam { name, value } => {
+                    if !self.renderer.params.set(&name, value) {
+                        println!("OSC: no param {name}");
+                    }
+                }
+                OscCommand::SetParamNormalized { name, value } => {
+                    if !self.renderer.params.set_normalized(&name, value) {
+                        println!("OSC: no param {name}");
+                    }
+                }
+                OscCommand::RecallPreset { slot, morph } => self.recall_preset(slot, morph),
+                OscCommand::SavePreset { slot } => self.save_preset(slot),
+                OscCommand::Pause if !self.clock.is_paused() => self.clock.toggle_pause(),
+                OscCommand::Play if self.clock.is_paused() => self.clock.toggle_pause(),
+                OscCommand::Pause | OscCommand::Play => {}
+                OscCommand::TogglePause => self.clock.toggle_pause(),
+                OscCommand::SetTime(time) => self.clock.set_time(time),
+            }
+        }
+    }
 
-        Self {
-            window,
-            surface,
-            queue,
-            size,
-            unif_bind_group,
-            res_buffer_unif: res_unif,
-            frame_unif,
-            frame: 0,
-            camera_texture,
-            camera_dims: camera_dim,
-            rpcontext: rpctx,
-            backbuffer: BackBuffer {
-                bind_group: bb_bind_group,
-                bind_group_layout: bb_bind_group_layout,
-                sample_texture: bb_sample_texture,
-                sample_texture_view: bb_sample_texture_view,
-                format: surface_format,
-            },
+    // Move mapped params with their controls, or map the control that moved to the param
+    // waiting in the panel
+    fn apply_midi(&mut self) {
+        let (Some(port), Some(settings)) = (&self.midi, &mut self.project.midi) else {
+            return;
+        };
+        for (_, message) in port.messages() {
+            if let Some(name) = &self.param_panel.learning {
+                if let Some(control) = MidiControl::learn(&message) {
+                    println!("Mapped {name} to {control}, Ctrl+S to keep it");
+                    // One control per param, and one param per control
+                    settings.map.retain(|_, c| *c != control);
+                    settings.map.insert(name.clone(), control);
+                    self.param_panel.learning = None;
+                    continue;
+                }
+            }
+            for (name, control) in &settings.map {
+                if let Some(value) = control.value(&message) {
+                    self.renderer.params.set_normalized(name, value);
+                }
+            }
         }
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    // Control API requests that need the App. Answers are dropped if the client gave up.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn apply_api(&mut self) {
+        let Some(api) = &self.api else {
+            return;
+        };
+        let requests: Vec<ApiRequest> = api.try_iter().collect();
+        for request in requests {
+            match request {
+                ApiRequest::Uniforms(reply) => {
+                    let _ = reply.send(self.renderer.params.values());
+                }
+                ApiRequest::SetUniforms(values, reply) => {
+                    let unknown = values
+                        .into_iter()
+                        .filter(|(name, value)| !self.renderer.params.set(name, *value))
+                        .map(|(name, _)| name)
+                        .collect();
+                    let _ = reply.send(unknown);
+                }
+                ApiRequest::Screenshot(reply) => {
+                    let _ = reply.send(self.save_screenshot());
+                }
+                ApiRequest::Frame(reply) => {
+                    let (width, height) = self.renderer.size();
+                    let png = self
+                        .renderer
+                        .read_target()
+                        .and_then(|rgba| capture::encode_png(width, height, &rgba));
+                    let _ = reply.send(png);
+                }
+            }
+        }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.rpcontext.write().surface_config.width = new_size.width;
-            self.rpcontext.write().surface_config.height = new_size.height;
-            self.surface.configure(
-                &self.rpcontext.read().device,
-                &self.rpcontext.read().surface_config,
-            );
+    // Log the pixel under the cursor with debug_print, showing the gui to see it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pick_debug_pixel(&mut self) {
+        let window = (self.size.width, self.size.height);
+        let policy = &self.project.resolution;
+        let Some(pixel) = self
+            .cursor
+            .and_then(|cursor| policy.window_to_target(self.renderer.size(), window, cursor))
+        else {
+            return;
+        };
+        self.renderer.debug.pixel = Some(pixel);
+        self.gui.visible = true;
+    }
 
-            self.bb_refresh((new_size.width, new_size.height));
+    pub fn poster(&mut self) {
+        let settings = &self.project.poster;
+        let (target_width, target_height) = self.renderer.size();
+        let width = settings.width.unwrap_or(target_width * settings.scale);
+        let height = settings.height.unwrap_or(target_height * settings.scale);
+        let max = self
+            .renderer
+            .rpcontext
+            .read()
+            .device
+            .limits()
+            .max_texture_dimension_2d;
+        let tile = settings.tile.clamp(1, max);
+
+        let dir = self.project.resolve(&self.project.capture.dir);
+        let path = capture::timestamped_path(&dir, "poster");
+        println!("Rendering {width}x{height} poster in {tile}px tiles...");
+        match fs::create_dir_all(&dir)
+            .and_then(|_| poster::render(&mut self.renderer, (width, height), tile, &path))
+        {
+            Ok(_) => println!("Saved poster {}", path.display()),
+            Err(e) => println!("Failed to save poster: {e}"),
         }
     }
 
-    pub fn input(&self, _event: &WindowEvent) -> bool {
-        false
+    pub fn screenshot(&mut self) {
+        match self.save_screenshot() {
+            Ok(path) => println!("Saved screenshot {}", path.display()),
+            Err(e) => println!("Failed to save screenshot: {e}"),
+        }
     }
 
-    pub fn update(&mut self) {}
+    // Screenshots are a multiple of the render target's size, not the window's
+    pub fn save_screenshot(&mut self) -> io::Result<PathBuf> {
+        let scale = self.project.capture.scale.max(1);
+        let (target_width, target_height) = self.renderer.size();
+        let (width, height) = (target_width * scale, target_height * scale);
+        let max = self
+            .renderer
+            .rpcontext
+            .read()
+            .device
+            .limits()
+            .max_texture_dimension_2d;
+        if width > max || height > max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{width}x{height} is over the {max}px texture limit"),
+            ));
+        }
+
+        let dir = self.project.resolve(&self.project.capture.dir);
+        let path = capture::timestamped_path(&dir, "screenshot");
+        let text = self.renderer.capture_metadata((width, height));
+        fs::create_dir_all(&dir)?;
+        let rgba = self.renderer.render_offscreen(width, height)?;
+        capture::write_png(&path, width, height, &rgba, &text)?;
+        Ok(path)
+    }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
@@ -617,72 +919,72 @@ impl App {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let mut encoder =
-            self.rpcontext
-                .read()
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
-                });
-
-        self.queue.write_buffer(
-            &self.res_buffer_unif,
-            0,
-            bytemuck::cast_slice(&[self.size.width as f32, self.size.height as f32]),
-        );
-
-        self.queue
-            .write_buffer(&self.frame_unif, 0, bytemuck::cast_slice(&[self.frame]));
-
-        self.frame += 1;
-
-        let rpctx = self.rpcontext.read();
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 1.0,
-                            g: 1.0,
-                            b: 1.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
+        let mut encoder = self
+            .renderer
+            .rpcontext
+            .read()
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
             });
 
-            render_pass.set_pipeline(&rpctx.pipeline);
-            render_pass.set_bind_group(0, &self.unif_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.backbuffer.bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
+        let window = (self.size.width, self.size.height);
+        self.timer.begin(&mut encoder);
+        // A frozen inspector keeps the last frame in the target
+        let frozen = self.gui.visible && self.inspector.is_frozen();
+        if !frozen {
+            self.renderer.render(&mut encoder);
+            self.timer.mark(&mut encoder, "Shader");
         }
-
-        encoder.copy_texture_to_texture(
-            ImageCopyTexture {
-                texture: &output.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            ImageCopyTexture {
-                texture: &self.backbuffer.sample_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            output.texture.size(),
-        );
+        self.renderer
+            .present(&mut encoder, &view, window, &self.project.resolution);
+        self.timer.mark(&mut encoder, "Present");
+
+        let device = self.renderer.rpcontext.read().device.clone();
+        if self.gui.visible {
+            self.texture_viewer.render(
+                &device,
+                &self.renderer.queue,
+                &mut encoder,
+                &self.renderer,
+                &mut self.gui,
+            );
+        }
+        self.gui
+            .paint(&device, &self.renderer.queue, &mut encoder, &view, window);
+        self.timer.mark(&mut encoder, "Gui");
+        self.timer.resolve(&mut encoder);
 
         // submit will accept anything that implements IntoIter
-        self.queue.submit([encoder.finish()]);
+        self.timer.submit();
+        self.renderer.queue.submit([encoder.finish()]);
         output.present();
 
+        if let Some(sample) = self.timer.collect(&device, false) {
+            self.timing_graph.push(sample);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if !frozen {
+            self.debug_window.update(&device, &self.renderer.debug);
+        }
+
         Ok(())
     }
 }
+
+fn preset_slot(key: VirtualKeyCode) -> Option<u8> {
+    use VirtualKeyCode::*;
+    let slot = match key {
+        Key1 => 1,
+        Key2 => 2,
+        Key3 => 3,
+        Key4 => 4,
+        Key5 => 5,
+        Key6 => 6,
+        Key7 => 7,
+        Key8 => 8,
+        Key9 => 9,
+        _ => return None,
+    };
+    Some(slot)
+}
@@ -1,37 +1,146 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-
-pub fn start_audio_capture() {
-    let host = cpal::default_host();
-
-    let device = host.default_input_device().unwrap();
-
-    let mut supported_configs_range = device
-        .supported_output_configs()
-        .expect("error while querying configs");
-    let supported_config = supported_configs_range
-        .next()
-        .expect("no supported config?!");
-    let sample_format = supported_config.sample_format();
-    let config: cpal::StreamConfig = supported_config
-        .with_sample_rate(cpal::SampleRate(48000))
-        .into();
-
-    let stream = match sample_format {
-        cpal::SampleFormat::I8 => device
-            .build_input_stream(
-                &config,
-                |data: &[i8], _: &cpal::InputCallbackInfo| {
-                    println!("{:?}", data);
-                    println!("{:?}", data.len());
-                },
-                |err| {
-                    println!("{:?}", err);
-                },
-                None,
-            )
-            .expect("failed to create input stream"),
-        _ => todo!(),
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SizedSample,
+};
+use parking_lot::Mutex;
+use std::{io, sync::Arc};
+
+use crate::input::{Binding, InputSource, Upload};
+
+// Samples in the waveform binding
+const WINDOW: usize = 256;
+
+// An audio input, the default one unless `device` names another. Binds the latest samples of
+// the first channel as `<name>` and their RMS and peak as `<name>Level`.
+pub struct AudioSource {
+    device: Option<String>,
+    stream: Option<cpal::Stream>,
+    failed: bool,
+    // Filled by the stream's callback, newest last
+    samples: Arc<Mutex<Vec<f32>>>,
+    waveform: Vec<f32>,
+    level: [f32; 2],
+}
+
+impl AudioSource {
+    pub fn new(device: Option<String>) -> Self {
+        Self {
+            device,
+            stream: None,
+            failed: false,
+            samples: Arc::new(Mutex::new(vec![])),
+            waveform: vec![0.0; WINDOW],
+            level: [0.0; 2],
+        }
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let host = cpal::default_host();
+        let device = match &self.device {
+            Some(name) => host
+                .input_devices()
+                .map_err(io::Error::other)?
+                .find(|d| d.name().is_ok_and(|n| n == *name)),
+            None => host.default_input_device(),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such audio input"))?;
+
+        let supported = device.default_input_config().map_err(io::Error::other)?;
+        let config = supported.config();
+        let samples = self.samples.clone();
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => capture::<f32>(&device, &config, samples),
+            cpal::SampleFormat::I16 => capture::<i16>(&device, &config, samples),
+            cpal::SampleFormat::U16 => capture::<u16>(&device, &config, samples),
+            cpal::SampleFormat::I8 => capture::<i8>(&device, &config, samples),
+            cpal::SampleFormat::I32 => capture::<i32>(&device, &config, samples),
+            format => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unsupported sample format {format}"),
+                ))
+            }
+        }
+        .map_err(io::Error::other)?;
+        stream.play().map_err(io::Error::other)?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+// Keeps the last WINDOW samples of the first channel
+fn capture<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: Arc<Mutex<Vec<f32>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut samples = samples.lock();
+            samples.extend(
+                data.chunks(channels)
+                    .map(|frame| frame[0].to_sample::<f32>()),
+            );
+            let excess = samples.len().saturating_sub(WINDOW);
+            samples.drain(..excess);
+        },
+        |e| println!("Audio input error: {e}"),
+        None,
+    )
+}
+
+impl InputSource for AudioSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![
+            Binding::uniform("", WINDOW as u32),
+            Binding::uniform("Level", 2),
+        ]
+    }
+
+    fn poll(&mut self) {
+        if self.stream.is_none() && !self.failed {
+            if let Err(e) = self.open() {
+                println!("Failed to open audio input: {e}");
+                self.failed = true;
+            }
+        }
+
+        let samples = self.samples.lock();
+        // Right-aligned, so the newest sample is always last
+        let start = WINDOW - samples.len();
+        self.waveform[..start].fill(0.0);
+        self.waveform[start..].copy_from_slice(&samples);
+        drop(samples);
+
+        let sum: f32 = self.waveform.iter().map(|s| s * s).sum();
+        let rms = (sum / WINDOW as f32).sqrt();
+        let peak = self.waveform.iter().fold(0f32, |m, s| m.max(s.abs()));
+        self.level = [rms, peak];
+    }
+
+    fn upload(&mut self, index: usize) -> Option<Upload<'_>> {
+        match index {
+            0 => Some(Upload::Uniform(&self.waveform)),
+            _ => Some(Upload::Uniform(&self.level)),
+        }
+    }
+}
+
+pub fn list_input_devices() {
+    println!("Audio inputs:");
+    match cpal::default_host().input_devices() {
+        Ok(devices) => {
+            for (i, device) in devices.enumerate() {
+                let name = device.name().unwrap_or_else(|e| format!("<{e}>"));
+                println!("  {i}: {name}");
+            }
+        }
+        Err(e) => println!("  Failed to query audio inputs: {e}"),
     }
-    .play()
-    .unwrap();
 }
@@ -0,0 +1,13 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::ExitCode;
+
+#[cfg(not(target_arch = "wasm32"))]
+use wgsl_workbench::run_lsp;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> ExitCode {
+    run_lsp()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
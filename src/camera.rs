@@ -0,0 +1,79 @@
+use std::io;
+
+use nokhwa::{
+    pixel_format::RgbAFormat,
+    utils::{CameraIndex, RequestedFormat, RequestedFormatType},
+    Camera,
+};
+
+use crate::input::{Binding, InputSource, Upload};
+
+// A camera by index in list-devices, at its highest frame rate
+pub struct CameraSource {
+    index: u32,
+    camera: Option<Camera>,
+    // Don't retry a camera that failed to open every frame
+    failed: bool,
+    frame: Option<(u32, u32, Vec<u8>)>,
+}
+
+impl CameraSource {
+    pub fn new(index: u32) -> Self {
+        Self {
+            index,
+            camera: None,
+            failed: false,
+            frame: None,
+        }
+    }
+
+    // Normally done by the first poll, for callers that want to fail early
+    pub fn open(&mut self) -> io::Result<()> {
+        let format =
+            RequestedFormat::new::<RgbAFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
+
+        nokhwa::nokhwa_initialize(|_| {});
+        while !nokhwa::nokhwa_check() {}
+
+        let mut camera = Camera::new(CameraIndex::Index(self.index), format).map_err(|e| {
+            self.failed = true;
+            io::Error::other(format!("Failed to open camera {}: {e}", self.index))
+        })?;
+        let _ = camera.open_stream();
+        self.camera = Some(camera);
+        Ok(())
+    }
+}
+
+impl InputSource for CameraSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![Binding::texture("")]
+    }
+
+    fn poll(&mut self) {
+        if self.camera.is_none() && !self.failed {
+            if let Err(e) = self.open() {
+                println!("{e}");
+            }
+        }
+        let Some(camera) = &mut self.camera else {
+            return;
+        };
+        match camera
+            .frame()
+            .and_then(|frame| frame.decode_image::<RgbAFormat>())
+        {
+            Ok(image) => self.frame = Some((image.width(), image.height(), image.into_raw())),
+            Err(e) => println!("Failed to read camera {}: {e}", self.index),
+        }
+    }
+
+    fn upload(&mut self, _index: usize) -> Option<Upload<'_>> {
+        let (width, height, rgba) = self.frame.as_ref()?;
+        Some(Upload::Texture {
+            width: *width,
+            height: *height,
+            rgba,
+        })
+    }
+}
@@ -0,0 +1,312 @@
+// Reading rendered frames back from the GPU and writing them out as PNGs
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use wgpu::{Extent3d, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout};
+
+fn default_scale() -> u32 {
+    1
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaptureSettings {
+    // Screenshots render at this multiple of the render resolution
+    #[serde(default = "default_scale")]
+    pub scale: u32,
+    // Relative to the project file
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            scale: default_scale(),
+            dir: default_dir(),
+        }
+    }
+}
+
+// Copy a whole texture into a buffer and map it. Returns tightly packed RGBA8 rows.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> io::Result<Vec<u8>> {
+    let swizzle = match texture.format() {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        f => return Err(unsupported(f)),
+    };
+
+    let size = (texture.width(), texture.height());
+    let mut pixels = read_bytes(device, queue, texture, (0, 0), size, 4)?;
+
+    // The bytes are already sRGB encoded, either by the hardware for an *Srgb format or because
+    // the shader's output goes to the display as-is otherwise. Only the channel order differs.
+    if swizzle {
+        for px in pixels.chunks_exact_mut(4) {
+            px.swap(0, 2);
+        }
+    }
+    Ok(pixels)
+}
+
+// Read back `size` texels at `origin` as the RGBA values the shader wrote, undoing sRGB encoding.
+// 8-bit values are quantised, float targets come back exactly.
+pub fn read_region(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    origin: (u32, u32),
+    size: (u32, u32),
+) -> io::Result<Vec<[f32; 4]>> {
+    use wgpu::TextureFormat::*;
+
+    let format = texture.format();
+    let texel_bytes = match format {
+        Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb => 4,
+        Rgba16Float => 8,
+        Rgba32Float => 16,
+        f => return Err(unsupported(f)),
+    };
+    let bytes = read_bytes(device, queue, texture, origin, size, texel_bytes)?;
+
+    let unorm = |b: u8| b as f32 / 255.0;
+    let srgb = |b: u8| match unorm(b) {
+        c if c <= 0.04045 => c / 12.92,
+        c => ((c + 0.055) / 1.055).powf(2.4),
+    };
+    let texels = bytes
+        .chunks_exact(texel_bytes as usize)
+        .map(|t| match format {
+            Rgba8Unorm => [unorm(t[0]), unorm(t[1]), unorm(t[2]), unorm(t[3])],
+            Bgra8Unorm => [unorm(t[2]), unorm(t[1]), unorm(t[0]), unorm(t[3])],
+            Rgba8UnormSrgb => [srgb(t[0]), srgb(t[1]), srgb(t[2]), unorm(t[3])],
+            Bgra8UnormSrgb => [srgb(t[2]), srgb(t[1]), srgb(t[0]), unorm(t[3])],
+            Rgba16Float => [0, 2, 4, 6].map(|i| f16_to_f32(u16::from_le_bytes([t[i], t[i + 1]]))),
+            _ => bytemuck::pod_read_unaligned(t),
+        });
+    Ok(texels.collect())
+}
+
+fn unsupported(format: wgpu::TextureFormat) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Can't read back texture format {format:?}"),
+    )
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = (bits >> 10 & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Copy `size` texels at `origin` into a buffer and map it. Returns tightly packed rows.
+fn read_bytes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    origin: (u32, u32),
+    (width, height): (u32, u32),
+    texel_bytes: u32,
+) -> io::Result<Vec<u8>> {
+    let row_bytes = texel_bytes * width;
+    // Buffer copies need every row to start on a 256 byte boundary
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row_bytes = row_bytes.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_row_bytes * height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: origin.0,
+                y: origin.1,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(height),
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |r| {
+        let _ = tx.send(r);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()
+        .map_err(io::Error::other)?
+        .map_err(io::Error::other)?;
+
+    let mut bytes = Vec::with_capacity((row_bytes * height) as usize);
+    {
+        let mapped = slice.get_mapped_range();
+        for row in mapped.chunks(padded_row_bytes as usize) {
+            bytes.extend_from_slice(&row[..row_bytes as usize]);
+        }
+    }
+    buffer.unmap();
+    Ok(bytes)
+}
+
+// An sRGB RGBA8 PNG encoder with `text` stored as tEXt chunks, ready for image data
+pub fn png_writer(
+    path: &Path,
+    width: u32,
+    height: u32,
+    text: &[(String, String)],
+) -> io::Result<png::Writer<BufWriter<File>>> {
+    png_header(BufWriter::new(File::create(path)?), width, height, text)
+}
+
+fn png_header<W: Write>(
+    out: W,
+    width: u32,
+    height: u32,
+    text: &[(String, String)],
+) -> io::Result<png::Writer<W>> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+    for (key, value) in text {
+        encoder.add_text_chunk(key.clone(), value.clone())?;
+    }
+    Ok(encoder.write_header()?)
+}
+
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    text: &[(String, String)],
+) -> io::Result<()> {
+    let mut writer = png_writer(path, width, height, text)?;
+    writer.write_image_data(rgba)?;
+    Ok(writer.finish()?)
+}
+
+// A PNG in memory, for sending rather than saving
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> io::Result<Vec<u8>> {
+    let mut png = vec![];
+    let mut writer = png_header(&mut png, width, height, &[])?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+// Any PNG as RGBA8
+pub fn read_png(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(io::Error::other)?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "indexed PNG wasn't expanded",
+            ))
+        }
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+// sha256, as hex
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+// `<dir>/<prefix>-YYYYMMDD-HHMMSS.png`, in UTC
+pub fn timestamped_path(dir: &Path, prefix: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Days since the epoch to a civil date, from Howard Hinnant's date algorithms
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let stamp = format!(
+        "{prefix}-{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+
+    let mut path = dir.join(format!("{stamp}.png"));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{stamp}-{n}.png"));
+        n += 1;
+    }
+    path
+}
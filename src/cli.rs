@@ -0,0 +1,331 @@
+// Command line parsing. `wgsl_workbench <file>` still works as shorthand for `run <file>`.
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::{env, ffi::OsString, path::PathBuf};
+
+#[derive(Parser)]
+#[command(version, about = "Live-coding playground for WGSL fragment shaders")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open a shader or project in a window, recompiling it whenever it's saved
+    Run(RunArgs),
+    /// Render frames offline to numbered PNGs
+    Render(RenderArgs),
+    /// Check shaders compile without a GPU. Exits 1 if any don't, 2 if any can't be read.
+    Validate(ValidateArgs),
+    /// Render an animation to a GIF, or any other format through ffmpeg
+    Export(ExportArgs),
+    /// Compare renders against reference PNGs. Exits 1 if any differ, 2 if any can't be rendered.
+    Test(TestArgs),
+    /// Run WGSL functions from .test.toml specs and check what they return. Exits like test.
+    Unit(UnitArgs),
+    /// Import a Shadertoy JSON export as a GLSL shader and project, or export one as Shadertoy JSON
+    #[command(subcommand)]
+    Shadertoy(ShadertoyCommand),
+    /// Write a shader as GLSL, HLSL, MSL or SPIR-V, and list where its resources are bound
+    Transpile(TranspileArgs),
+    /// Replay a session recorded with `run --record` in a window, or render it to a video. Camera
+    /// frames aren't recorded, so sessions that used the camera need --no-camera.
+    Replay(ReplayArgs),
+    /// List cameras, audio and MIDI inputs, and GPU adapters
+    ListDevices(GpuArgs),
+}
+
+impl Cli {
+    pub fn parse_env() -> Self {
+        let mut args: Vec<OsString> = env::args_os().collect();
+        let is_command = |arg: &OsString| {
+            let arg = arg.to_string_lossy();
+            arg.starts_with('-')
+                || arg == "help"
+                || Self::command()
+                    .get_subcommands()
+                    .any(|c| c.get_name() == arg)
+        };
+        if args.get(1).is_some_and(|arg| !is_command(arg)) {
+            args.insert(1, "run".into());
+        }
+        Self::parse_from(args)
+    }
+}
+
+#[derive(Args, Clone, Default)]
+pub struct GpuArgs {
+    /// GPU adapter to use, as its index in list-devices or part of its name
+    #[arg(long)]
+    pub adapter: Option<String>,
+    /// Graphics API to use
+    #[arg(long, value_enum, default_value_t)]
+    pub backend: Backend,
+    /// Use a software adapter, for output that doesn't depend on the GPU
+    #[arg(long, conflicts_with = "adapter")]
+    pub software: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum Backend {
+    #[default]
+    All,
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    pub fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::All => wgpu::Backends::all(),
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum PresentMode {
+    #[default]
+    Auto,
+    AutoNoVsync,
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Auto => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// WGSL, GLSL or SPIR-V file, or a .toml project
+    pub path: PathBuf,
+    #[command(flatten)]
+    pub gpu: GpuArgs,
+    /// Window size, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    #[arg(long, value_enum, default_value_t)]
+    pub present_mode: PresentMode,
+    /// Camera to feed into videoBuffer, by index in list-devices
+    #[arg(long, default_value_t = 0)]
+    pub camera: u32,
+    /// Don't open a camera. videoBuffer stays black.
+    #[arg(long, conflicts_with = "camera")]
+    pub no_camera: bool,
+    /// Don't recompile the shader when it changes on disk
+    #[arg(long)]
+    pub no_watch: bool,
+    /// Record input, param changes, clock time and camera frame hashes to a session file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    /// Session file written by `run --record`
+    pub session: PathBuf,
+    #[command(flatten)]
+    pub gpu: GpuArgs,
+    /// Steps per second of recorded time. Each frame drawn is one step, whatever the display's
+    /// rate.
+    #[arg(long, default_value_t = 60.0)]
+    pub fps: f32,
+    /// Render to this file instead of a window. A .gif is encoded directly, anything else is piped
+    /// to ffmpeg.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+    /// Frame size for --out, as WIDTHxHEIGHT. Defaults to the recorded window's render size.
+    #[arg(long, value_parser = parse_size, requires = "out")]
+    pub size: Option<(u32, u32)>,
+    #[arg(long, value_enum, default_value_t)]
+    pub present_mode: PresentMode,
+    /// Replay a session that used the camera anyway. videoBuffer stays black, so it may not look
+    /// the same.
+    #[arg(long)]
+    pub no_camera: bool,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// WGSL, GLSL or SPIR-V file, or a .toml project
+    pub path: PathBuf,
+    #[command(flatten)]
+    pub gpu: GpuArgs,
+    /// Frame size, as WIDTHxHEIGHT. Defaults to the project's fixed resolution, or 1280x720.
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    /// Number of frames to render
+    #[arg(long, default_value_t = 1)]
+    pub frames: u32,
+    /// Frames per second of shader time
+    #[arg(long, default_value_t = 60.0)]
+    pub fps: f32,
+    /// Shader time of the first frame, in seconds
+    #[arg(long, default_value_t = 0.0)]
+    pub start: f32,
+    /// Directory for the frame-NNNN.png files
+    #[arg(short, long, default_value = "frames")]
+    pub out: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// WGSL, GLSL or SPIR-V files, or .toml projects
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: DiagnosticFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum DiagnosticFormat {
+    /// Source snippets with the problem underlined
+    #[default]
+    Human,
+    /// One JSON document covering every file
+    Json,
+    /// `file:line:col: error: message`, one per line
+    Gcc,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// WGSL, GLSL or SPIR-V file, or a .toml project
+    pub path: PathBuf,
+    #[command(flatten)]
+    pub gpu: GpuArgs,
+    /// Output file. A .gif is encoded directly, anything else is piped to ffmpeg.
+    #[arg(short, long)]
+    pub out: PathBuf,
+    /// Frame size, as WIDTHxHEIGHT. Defaults to the project's fixed resolution, or 1280x720.
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    #[arg(long, default_value_t = 30.0)]
+    pub fps: f32,
+    /// Shader time of the first frame, in seconds
+    #[arg(long, default_value_t = 0.0)]
+    pub start: f32,
+    /// Length in seconds. Defaults to the project's timeline length.
+    #[arg(long)]
+    pub duration: Option<f32>,
+}
+
+#[derive(Args)]
+pub struct TestArgs {
+    /// WGSL, GLSL or SPIR-V files, or .toml projects
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Renders on the software adapter unless --adapter picks another
+    #[command(flatten)]
+    pub gpu: GpuArgs,
+    /// Directory of references named <shader>.png. Defaults to <shader>.golden.png beside each.
+    #[arg(long)]
+    pub references: Option<PathBuf>,
+    /// Render size, as WIDTHxHEIGHT. Defaults to the reference's size.
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    /// Shader time, in seconds
+    #[arg(long, default_value_t = 0.0)]
+    pub time: f32,
+    /// Frame count the shader sees
+    #[arg(long, default_value_t = 0)]
+    pub frame: u32,
+    /// Largest difference in any channel, out of 255, that still counts as the same pixel
+    #[arg(long, default_value_t = 2)]
+    pub tolerance: u8,
+    /// Fraction of pixels allowed to differ by more than the tolerance
+    #[arg(long, default_value_t = 0.0)]
+    pub max_failing: f64,
+    /// Lowest structural similarity (SSIM, up to 1) that passes
+    #[arg(long, default_value_t = 0.99)]
+    pub min_ssim: f64,
+    /// Write the renders as the new references instead of comparing
+    #[arg(long)]
+    pub update: bool,
+}
+
+#[derive(Args)]
+pub struct UnitArgs {
+    /// .test.toml specs
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Runs on the software adapter unless --adapter picks another
+    #[command(flatten)]
+    pub gpu: GpuArgs,
+}
+
+#[derive(Subcommand)]
+pub enum ShadertoyCommand {
+    /// Write the shader in a Shadertoy JSON file as GLSL, with a project binding its channels
+    Import(ShadertoyImportArgs),
+    /// Write a shader as Shadertoy JSON, translating WGSL to GLSL
+    Export(ShadertoyExportArgs),
+}
+
+#[derive(Args)]
+pub struct ShadertoyImportArgs {
+    /// JSON from Shadertoy's API or its site
+    pub path: PathBuf,
+    /// Directory to write the shader and project to. Defaults to the JSON's.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ShadertoyExportArgs {
+    /// WGSL, GLSL or SPIR-V file, or a .toml project
+    pub path: PathBuf,
+    /// JSON file to write
+    #[arg(short, long)]
+    pub out: PathBuf,
+}
+
+#[derive(Args)]
+pub struct TranspileArgs {
+    /// WGSL, GLSL or SPIR-V file, or a .toml project
+    pub path: PathBuf,
+    #[arg(long, value_enum)]
+    pub target: Target,
+    /// Output file
+    #[arg(short, long)]
+    pub out: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Desktop GLSL 4.50
+    Glsl,
+    /// HLSL for shader model 5.1
+    Hlsl,
+    /// Metal Shading Language 2.0
+    Msl,
+    /// SPIR-V binary
+    Spv,
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {s}"))?;
+    let parse = |n: &str| match n.trim().parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid size {s}")),
+    };
+    Ok((parse(w)?, parse(h)?))
+}
@@ -0,0 +1,52 @@
+// std's Instant panics in the browser
+use web_time::Instant;
+
+// Workbench clock, in seconds. Can be paused and scrubbed without touching the frame counter.
+pub struct Clock {
+    start: Instant,
+    offset: f32,
+    paused_at: Option<f32>,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            offset: 0.0,
+            paused_at: None,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        match self.paused_at {
+            Some(t) => t,
+            None => self.offset + self.start.elapsed().as_secs_f32(),
+        }
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        if self.paused_at.is_some() {
+            self.paused_at = Some(time);
+        } else {
+            self.start = Instant::now();
+            self.offset = time;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn toggle_pause(&mut self) {
+        match self.paused_at.take() {
+            Some(t) => self.set_time(t),
+            None => self.paused_at = Some(self.time()),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,201 @@
+// printf for WGSL: the prelude's debug_print functions write to a storage buffer in bind group 3,
+// but only for the pixel picked with Ctrl+click. The buffer is a header of the picked pixel and a
+// call count, then an entry per call of its site number, component count and value bits:
+//
+//     [x, y, calls, _, site, size, _, _, value.x, value.y, value.z, value.w, site, ...]
+//
+// It's copied out after each frame and shown in a window next to the lines that logged it.
+
+use std::io;
+
+use crate::shader::DebugSite;
+
+// Matches debug_log in the prelude
+const MAX_ENTRIES: u32 = 64;
+const HEADER_WORDS: u32 = 4;
+const ENTRY_WORDS: u32 = 8;
+const SIZE: u64 = ((HEADER_WORDS + MAX_ENTRIES * ENTRY_WORDS) * 4) as u64;
+
+pub struct DebugEntry {
+    pub site: usize,
+    pub value: Vec<f32>,
+}
+
+pub struct DebugLog {
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    // In render target pixels. Nothing is logged without one.
+    pub pixel: Option<(u32, u32)>,
+}
+
+impl DebugLog {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("debug_bind_group_layout"),
+        });
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Log"),
+            size: SIZE,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Log Readback"),
+            size: SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("debug_bind_group"),
+        });
+
+        Self {
+            layout,
+            bind_group,
+            buffer,
+            readback,
+            pixel: None,
+        }
+    }
+
+    // Empty the log before a draw. `log` off matches no pixel, for renders that aren't the
+    // window's.
+    pub fn reset(&self, queue: &wgpu::Queue, log: bool) {
+        let (x, y) = self.pixel.filter(|_| log).unwrap_or((u32::MAX, u32::MAX));
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[x, y, 0, 0]));
+    }
+
+    // Queue a copy of what the draw logged, for read
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.pixel.is_some() {
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback, 0, SIZE);
+        }
+    }
+
+    // The entries copied by the last submitted copy, and how many calls there were in all
+    pub fn read(&self, device: &wgpu::Device) -> io::Result<(Vec<DebugEntry>, u32)> {
+        let slice = self.readback.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv()
+            .map_err(io::Error::other)?
+            .map_err(io::Error::other)?;
+
+        let words: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        self.readback.unmap();
+
+        let calls = words[2];
+        let entries = words[HEADER_WORDS as usize..]
+            .chunks_exact(ENTRY_WORDS as usize)
+            .take(calls.min(MAX_ENTRIES) as usize)
+            .map(|entry| DebugEntry {
+                site: entry[0] as usize,
+                value: entry[4..4 + (entry[1] as usize).min(4)]
+                    .iter()
+                    .map(|bits| f32::from_bits(*bits))
+                    .collect(),
+            })
+            .collect();
+        Ok((entries, calls))
+    }
+}
+
+// What the picked pixel logged last frame
+#[derive(Default)]
+pub struct DebugWindow {
+    entries: Vec<DebugEntry>,
+    calls: u32,
+    error: Option<String>,
+}
+
+impl DebugWindow {
+    pub fn update(&mut self, device: &wgpu::Device, log: &DebugLog) {
+        if log.pixel.is_none() {
+            return;
+        }
+        match log.read(device) {
+            Ok((entries, calls)) => {
+                (self.entries, self.calls) = (entries, calls);
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    // Closing the window stops logging
+    pub fn show(&mut self, ctx: &egui::Context, log: &mut DebugLog, sites: &[DebugSite]) {
+        let Some((x, y)) = log.pixel else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("debug_print")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!("Pixel {x}, {y}, Ctrl+click to pick another"));
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                if self.calls == 0 {
+                    ui.label("Nothing logged, call `debug_print(pos.xy, value)` in the shader");
+                }
+
+                egui::Grid::new("debug_entries")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for entry in &self.entries {
+                            let value: Vec<String> =
+                                entry.value.iter().map(|v| v.to_string()).collect();
+                            // Sites from before a reload may be gone
+                            match sites.get(entry.site) {
+                                Some(site) => {
+                                    let file = site.path.file_name().unwrap_or_default();
+                                    ui.weak(format!("{}:{}", file.to_string_lossy(), site.line));
+                                    ui.monospace(value.join(", "));
+                                    ui.monospace(&site.text);
+                                }
+                                None => {
+                                    ui.weak("?");
+                                    ui.monospace(value.join(", "));
+                                    ui.label("");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                if self.calls > MAX_ENTRIES {
+                    let more = self.calls - MAX_ENTRIES;
+                    let color = ui.visuals().warn_fg_color;
+                    ui.colored_label(color, format!("{more} more calls not shown"));
+                }
+            });
+        if !open {
+            log.pixel = None;
+            *self = Self::default();
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Shadertoy-style GLSL, translated to WGSL by naga's GLSL front-end. The user's file defines
+// `mainImage(out vec4 fragColor, in vec2 fragCoord)`, and gets wrapped in a header declaring the
+// workbench's bindings under Shadertoy's names and a `main` that calls it:
+//
+//     iResolution   vec3(res, 1.0)
+//     iTime         time
+//     iFrame        frame
+//     iMouse        an input named iMouse from the `mouse` source, in pixels. Zero without one.
+//     iChannel0-3   texture inputs with those names. Without one, iChannel0 is the camera
+//                   (videoBuffer) and iChannel1 the previous frame (backBuffer).
+//
+// Params and every other input are there under the same names as in WGSL, and `WORKBENCH` is
+// defined for code that should only run on Shadertoy. Shadertoy's origin is the bottom left and
+// ours the top left, so fragCoord is flipped, and texture lookups with it to keep images upright
+// and feedback through iChannel1 in place.
+
+use std::ops::Range;
+
+use naga::{
+    back::wgsl::WriterFlags,
+    front::glsl::{Frontend, Options},
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage,
+};
+
+use crate::{
+    input::{BindingKind, InputLayout},
+    params::ParamDecl,
+    shader::error_chain,
+};
+
+// Inputs picked up by name, textures for the channels and a mouse for iMouse
+pub const CHANNELS: [&str; 4] = ["iChannel0", "iChannel1", "iChannel2", "iChannel3"];
+pub const MOUSE_INPUT: &str = "iMouse";
+
+// What channels are without an input, by the prelude items they sample
+const DEFAULT_CHANNELS: [Option<(&str, &str)>; 4] = [
+    Some(("videoBuffer", "videoSampler")),
+    Some(("backBuffer", "backSampler")),
+    None,
+    None,
+];
+
+// Shadertoy uniforms with nothing behind them here
+const UNSUPPORTED: &[(&str, &str)] = &[
+    ("iTimeDelta", "use the difference between frames' `iTime`"),
+    ("iFrameRate", "there's no fixed frame rate"),
+    ("iDate", "use a param or `iTime`"),
+    ("iSampleRate", "sound shaders aren't supported"),
+    ("iChannelTime", "channels have no playback time"),
+    ("iChannelResolution", "use `textureSize(iChannelN, 0)`"),
+];
+
+const FOOTER: &str = "
+layout(location = 0) out vec4 workbench_color;
+
+void main() {
+    vec2 pos = gl_FragCoord.xy + viewport.xy;
+    vec4 color = vec4(0.0);
+    mainImage(color, vec2(pos.x, res.y - pos.y));
+    workbench_color = color;
+}
+";
+
+// A problem with the user's GLSL, as a byte range into it if there's one to point at
+pub type Problem = (Option<Range<usize>>, String);
+
+// The GLSL naga compiles, with the offset `source` starts at
+pub fn compose(source: &str, decls: &[ParamDecl], inputs: &InputLayout) -> (String, usize) {
+    let mut lines = vec![
+        "#version 450".to_owned(),
+        "#define WORKBENCH".to_owned(),
+        "layout(set = 0, binding = 0) uniform WorkbenchRes { vec2 res; };".to_owned(),
+        "layout(set = 0, binding = 1) uniform WorkbenchFrame { uint frame; };".to_owned(),
+        "layout(set = 0, binding = 2) uniform texture2D videoBuffer;".to_owned(),
+        "layout(set = 0, binding = 3) uniform sampler videoSampler;".to_owned(),
+        "layout(set = 0, binding = 4) uniform WorkbenchTime { float time; };".to_owned(),
+        "layout(set = 0, binding = 6) uniform WorkbenchViewport { vec4 viewport; };".to_owned(),
+        "layout(set = 1, binding = 0) uniform texture2D backBuffer;".to_owned(),
+        "layout(set = 1, binding = 1) uniform sampler backSampler;".to_owned(),
+    ];
+    if !decls.is_empty() {
+        let fields: Vec<String> = decls.iter().map(|d| format!("float {};", d.name)).collect();
+        lines.push(format!(
+            "layout(set = 0, binding = 5) uniform Params {{ {} }} params;",
+            fields.join(" ")
+        ));
+    }
+    lines.extend(inputs.glsl());
+
+    lines.extend([
+        "#define iResolution vec3(res, 1.0)".to_owned(),
+        "#define iTime time".to_owned(),
+        "#define iFrame int(frame)".to_owned(),
+    ]);
+    for (name, default) in CHANNELS.into_iter().zip(DEFAULT_CHANNELS) {
+        if inputs.kind(name) == Some(BindingKind::Texture) {
+            lines.push(format!("#define {name} sampler2D({name}, {name}Sampler)"));
+        } else if let Some((texture, sampler)) = default {
+            lines.push(format!("#define {name} sampler2D({texture}, {sampler})"));
+        }
+    }
+    // The mouse source is 0-1 from the top left with a bitmask of buttons. Shadertoy's is pixels
+    // from the bottom left, with zw negative while the button is up.
+    match inputs.kind(MOUSE_INPUT) {
+        Some(BindingKind::Uniform { .. }) => lines.extend([
+            "vec4 workbench_mouse() {".to_owned(),
+            "    vec2 p = vec2(iMouse.x, 1.0 - iMouse.y) * res;".to_owned(),
+            "    return vec4(p, mod(iMouse.z, 2.0) >= 1.0 ? p : -p);".to_owned(),
+            "}".to_owned(),
+            "#define iMouse workbench_mouse()".to_owned(),
+        ]),
+        _ => lines.push("#define iMouse vec4(0.0)".to_owned()),
+    }
+
+    lines.extend([
+        "vec2 workbench_flip(vec2 uv) { return vec2(uv.x, 1.0 - uv.y); }".to_owned(),
+        "ivec2 workbench_flip(ivec2 p, ivec2 size) { return ivec2(p.x, size.y - 1 - p.y); }"
+            .to_owned(),
+        "#define texture(s, uv) texture(s, workbench_flip(uv))".to_owned(),
+        "#define textureLod(s, uv, lod) textureLod(s, workbench_flip(uv), lod)".to_owned(),
+        "#define texelFetch(s, p, lod) texelFetch(s, workbench_flip(p, textureSize(s, lod)), lod)"
+            .to_owned(),
+        String::new(),
+    ]);
+
+    let header = lines.join("\n");
+    let offset = header.len();
+    (header + source + FOOTER, offset)
+}
+
+// What the workbench can't run, before naga gets to give a less helpful error about it
+pub fn check(source: &str, inputs: &InputLayout) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut has_main_image = false;
+    let (mut previous, mut previous_start) = ("", 0);
+    for (range, word) in words(source) {
+        let start = range.start;
+        let problem = match word {
+            "mainImage" => {
+                has_main_image = true;
+                None
+            }
+            "main" if previous == "void" => Some(
+                "define `mainImage(out vec4 fragColor, in vec2 fragCoord)` rather than `main`, \
+                 the workbench generates it"
+                    .to_owned(),
+            ),
+            "version" if previous == "#" => {
+                problems.push((
+                    Some(previous_start..range.end),
+                    "leave out `#version`, the workbench sets it".to_owned(),
+                ));
+                None
+            }
+            "samplerCube" | "sampler3D" => Some(format!(
+                "`{word}` isn't supported, channels are all 2D textures"
+            )),
+            _ if is_unbound_channel(word, inputs) => Some(format!(
+                "`{word}` isn't bound, add a texture input named {word} to the project"
+            )),
+            _ => UNSUPPORTED
+                .iter()
+                .find(|(name, _)| *name == word)
+                .map(|(_, hint)| format!("`{word}` isn't supported by the workbench, {hint}")),
+        };
+        if let Some(message) = problem {
+            problems.push((Some(range), message));
+        }
+        (previous, previous_start) = (word, start);
+    }
+    if !has_main_image {
+        problems.push((
+            None,
+            "no `mainImage(out vec4 fragColor, in vec2 fragCoord)` function".to_owned(),
+        ));
+    }
+    problems
+}
+
+fn is_unbound_channel(word: &str, inputs: &InputLayout) -> bool {
+    let Some(i) = CHANNELS.iter().position(|c| *c == word) else {
+        return false;
+    };
+    DEFAULT_CHANNELS[i].is_none() && inputs.kind(word) != Some(BindingKind::Texture)
+}
+
+// Whether `name` appears in the code, outside of comments
+pub fn uses(source: &str, name: &str) -> bool {
+    words(source).iter().any(|(_, word)| *word == name)
+}
+
+// Every use of `a` as `b` and the other way round
+pub fn swap_words(source: &str, a: &str, b: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for (range, word) in words(source) {
+        let other = match word {
+            w if w == a => b,
+            w if w == b => a,
+            _ => continue,
+        };
+        out.push_str(&source[last..range.start]);
+        out.push_str(other);
+        last = range.end;
+    }
+    out.push_str(&source[last..]);
+    out
+}
+
+// Identifiers and `#`s outside of comments, with where they are
+fn words(source: &str) -> Vec<(Range<usize>, &str)> {
+    let bytes = source.as_bytes();
+    let mut words = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &source[i..];
+        if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += rest.find("*/").map_or(rest.len(), |end| end + 2);
+        } else if bytes[i] == b'#' {
+            words.push((i..i + 1, "#"));
+            i += 1;
+        } else if bytes[i].is_ascii_alphabetic() || bytes[i] == b'_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            words.push((i..i + len, &rest[..len]));
+            i += len;
+        } else if bytes[i].is_ascii_digit() {
+            // So suffixes like the `u` in `1u` aren't taken for identifiers
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '.')
+                .unwrap_or(rest.len());
+            i += len;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    words
+}
+
+// WGSL for composed GLSL, or what naga didn't like about it. Ranges are into `glsl`.
+pub fn translate(glsl: &str) -> Result<String, Vec<Problem>> {
+    let module = Frontend::default()
+        .parse(&Options::from(ShaderStage::Fragment), glsl)
+        .map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|e| {
+                    let message = match e.kind.to_string() {
+                        m if m.ends_with("DefineArguments") => format!(
+                            "{m}: `texture`, `textureLod` and `texelFetch` are macros here, to \
+                             flip lookups, and don't take a bias"
+                        ),
+                        m => m,
+                    };
+                    (e.meta.to_range(), message)
+                })
+                .collect::<Vec<_>>()
+        })?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| {
+            let range = e.spans().find_map(|(span, _)| span.to_range());
+            vec![(range, error_chain(&e))]
+        })?;
+    naga::back::wgsl::write_string(&module, &info, WriterFlags::empty())
+        .map_err(|e| vec![(None, format!("Can't write WGSL: {e}"))])
+}
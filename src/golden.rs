@@ -0,0 +1,398 @@
+// Golden-image tests: render a shader at a fixed time, frame and size on the software adapter and
+// compare it with a reference PNG. GoldenTest is the `cargo test` side, `run` the CLI's `test`.
+// Either writes the references instead of comparing when WORKBENCH_UPDATE_GOLDEN is set.
+
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    capture,
+    cli::{GpuArgs, TestArgs},
+    offline::Offline,
+};
+
+/// Set to anything but `0` to write references instead of comparing against them.
+pub const UPDATE_ENV: &str = "WORKBENCH_UPDATE_GOLDEN";
+
+// For references that don't exist yet
+const DEFAULT_SIZE: (u32, u32) = (256, 256);
+
+// SSIM window size, and the usual constants for 8-bit values
+const WINDOW: u32 = 8;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+const EXIT_FAILED: u8 = 1;
+const EXIT_UNREADABLE: u8 = 2;
+
+/// One render of a shader compared against a reference PNG.
+///
+/// ```no_run
+/// wgsl_workbench::GoldenTest::new("shaders/plasma.wgsl", "tests/golden/plasma.png")
+///     .time(1.5)
+///     .tolerance(4)
+///     .assert();
+/// ```
+#[derive(Clone, Debug)]
+pub struct GoldenTest {
+    shader: PathBuf,
+    reference: PathBuf,
+    time: f32,
+    frame: u32,
+    size: Option<(u32, u32)>,
+    tolerance: u8,
+    max_failing: f64,
+    min_ssim: f64,
+}
+
+/// How a render compared with its reference.
+#[derive(Clone, Debug)]
+pub struct GoldenReport {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels with a channel further than the tolerance from the reference.
+    pub failing_pixels: usize,
+    /// The largest difference in any channel.
+    pub max_difference: u8,
+    /// Mean structural similarity of the luminance, 1 for identical images.
+    pub ssim: f64,
+    pub passed: bool,
+    /// The reference was written rather than compared against.
+    pub updated: bool,
+    /// Written on failure: the render, and the reference dimmed with failing pixels in red.
+    pub actual: Option<PathBuf>,
+    pub diff: Option<PathBuf>,
+}
+
+impl GoldenTest {
+    /// Defaults to time and frame 0, at the reference's size, with a tolerance of 2, no failing
+    /// pixels allowed and an SSIM of at least 0.99.
+    pub fn new(shader: impl Into<PathBuf>, reference: impl Into<PathBuf>) -> Self {
+        Self {
+            shader: shader.into(),
+            reference: reference.into(),
+            time: 0.0,
+            frame: 0,
+            size: None,
+            tolerance: 2,
+            max_failing: 0.0,
+            min_ssim: 0.99,
+        }
+    }
+
+    /// Shader time, in seconds.
+    pub fn time(mut self, time: f32) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// The frame count the shader sees.
+    pub fn frame(mut self, frame: u32) -> Self {
+        self.frame = frame;
+        self
+    }
+
+    /// Render size. Without it, the reference's size, or 256x256 when writing a new one.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// Largest difference in any channel, out of 255, that still counts as the same pixel.
+    pub fn tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Fraction of pixels allowed to be further than the tolerance.
+    pub fn max_failing(mut self, fraction: f64) -> Self {
+        self.max_failing = fraction;
+        self
+    }
+
+    /// Lowest SSIM that passes.
+    pub fn min_ssim(mut self, ssim: f64) -> Self {
+        self.min_ssim = ssim;
+        self
+    }
+
+    /// Render and compare on the software adapter.
+    pub fn run(&self) -> io::Result<GoldenReport> {
+        pollster::block_on(self.run_with(&GpuArgs::default(), false))
+    }
+
+    /// Run, and panic with the report unless it passed.
+    #[track_caller]
+    pub fn assert(&self) {
+        match self.run() {
+            Ok(report) if report.passed => {}
+            Ok(report) => panic!("{}: {report}", self.shader.display()),
+            Err(e) => panic!("{}: {e}", self.shader.display()),
+        }
+    }
+
+    async fn run_with(&self, gpu: &GpuArgs, update: bool) -> io::Result<GoldenReport> {
+        let update = update || env::var_os(UPDATE_ENV).is_some_and(|v| v != "0");
+        let reference = match update {
+            true => None,
+            false => Some(capture::read_png(&self.reference).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Can't read reference {}: {e}. Pass --update, or set {UPDATE_ENV}=1, \
+                         to write it.",
+                        self.reference.display()
+                    ),
+                )
+            })?),
+        };
+        let size = self
+            .size
+            .or(reference.as_ref().map(|(w, h, _)| (*w, *h)))
+            .unwrap_or(DEFAULT_SIZE);
+
+        // Software unless an adapter was picked, so references hold on other machines
+        let mut gpu = gpu.clone();
+        gpu.software |= gpu.adapter.is_none();
+        let mut offline = Offline::open(&self.shader, &gpu, Some(size)).await?;
+        offline.renderer.frame = self.frame;
+        let rgba = offline.frame(self.time)?;
+        let (width, height) = offline.renderer.size();
+
+        let Some((ref_width, ref_height, expected)) = reference else {
+            if let Some(dir) = self.reference.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            capture::write_png(&self.reference, width, height, &rgba, &[])?;
+            return Ok(GoldenReport {
+                width,
+                height,
+                failing_pixels: 0,
+                max_difference: 0,
+                ssim: 1.0,
+                passed: true,
+                updated: true,
+                actual: None,
+                diff: None,
+            });
+        };
+        if (ref_width, ref_height) != (width, height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Reference is {ref_width}x{ref_height}, the render is {width}x{height}"),
+            ));
+        }
+
+        let (failing_pixels, max_difference, diff) = compare(&rgba, &expected, self.tolerance);
+        let ssim = ssim(&rgba, &expected, width, height);
+        let total = (width * height) as f64;
+        let passed = failing_pixels as f64 / total <= self.max_failing && ssim >= self.min_ssim;
+
+        let actual_path = self.reference.with_extension("actual.png");
+        let diff_path = self.reference.with_extension("diff.png");
+        let (actual, diff) = if passed {
+            // Don't leave the last failure's images around to confuse anyone
+            let _ = fs::remove_file(&actual_path);
+            let _ = fs::remove_file(&diff_path);
+            (None, None)
+        } else {
+            capture::write_png(&actual_path, width, height, &rgba, &[])?;
+            capture::write_png(&diff_path, width, height, &diff, &[])?;
+            (Some(actual_path), Some(diff_path))
+        };
+
+        Ok(GoldenReport {
+            width,
+            height,
+            failing_pixels,
+            max_difference,
+            ssim,
+            passed,
+            updated: false,
+            actual,
+            diff,
+        })
+    }
+}
+
+impl fmt::Display for GoldenReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.updated {
+            return write!(f, "wrote {}x{} reference", self.width, self.height);
+        }
+        write!(
+            f,
+            "{} of {} pixels differ, by up to {}, SSIM {:.4}",
+            self.failing_pixels,
+            self.width * self.height,
+            self.max_difference,
+            self.ssim
+        )?;
+        if let Some(diff) = &self.diff {
+            write!(f, ", diff in {}", diff.display())?;
+        }
+        Ok(())
+    }
+}
+
+// Failing pixel count, largest channel difference, and the diff image
+fn compare(actual: &[u8], expected: &[u8], tolerance: u8) -> (usize, u8, Vec<u8>) {
+    let (mut failing, mut max) = (0, 0);
+    let mut diff = Vec::with_capacity(expected.len());
+    for (a, e) in actual.chunks(4).zip(expected.chunks(4)) {
+        let d = a
+            .iter()
+            .zip(e)
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        max = max.max(d);
+        if d > tolerance {
+            failing += 1;
+            diff.extend([128 + d / 2, 0, 0, 255]);
+        } else {
+            let grey = (luma(e) / 4.0) as u8;
+            diff.extend([grey, grey, grey, 255]);
+        }
+    }
+    (failing, max, diff)
+}
+
+fn luma(px: &[u8]) -> f64 {
+    0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64
+}
+
+// Mean SSIM over WINDOW-sized tiles of the luminance, with smaller tiles at the edges
+fn ssim(a: &[u8], b: &[u8], width: u32, height: u32) -> f64 {
+    let (a, b): (Vec<f64>, Vec<f64>) = (
+        a.chunks(4).map(luma).collect(),
+        b.chunks(4).map(luma).collect(),
+    );
+    let (mut sum, mut windows) = (0.0, 0);
+    for y0 in (0..height).step_by(WINDOW as usize) {
+        for x0 in (0..width).step_by(WINDOW as usize) {
+            let indices: Vec<usize> = (y0..(y0 + WINDOW).min(height))
+                .flat_map(|y| (x0..(x0 + WINDOW).min(width)).map(move |x| (y * width + x) as usize))
+                .collect();
+            let n = indices.len() as f64;
+            let mean = |v: &[f64]| indices.iter().map(|&i| v[i]).sum::<f64>() / n;
+            let (mean_a, mean_b) = (mean(&a), mean(&b));
+            let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+            for &i in &indices {
+                let (da, db) = (a[i] - mean_a, b[i] - mean_b);
+                var_a += da * da;
+                var_b += db * db;
+                cov += da * db;
+            }
+            let (var_a, var_b, cov) = (var_a / n, var_b / n, cov / n);
+
+            sum += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    sum / windows.max(1) as f64
+}
+
+// Where the CLI keeps a shader's reference
+fn reference_path(shader: &Path, references: Option<&Path>) -> PathBuf {
+    let name = shader.file_stem().unwrap_or_default().to_string_lossy();
+    match references {
+        Some(dir) => dir.join(format!("{name}.png")),
+        None => shader.with_file_name(format!("{name}.golden.png")),
+    }
+}
+
+pub async fn run(args: &TestArgs) -> ExitCode {
+    let (mut failed, mut unreadable) = (0, false);
+    for path in &args.paths {
+        let mut test = GoldenTest::new(path, reference_path(path, args.references.as_deref()))
+            .time(args.time)
+            .frame(args.frame)
+            .tolerance(args.tolerance)
+            .max_failing(args.max_failing)
+            .min_ssim(args.min_ssim);
+        if let Some((width, height)) = args.size {
+            test = test.size(width, height);
+        }
+
+        match test.run_with(&args.gpu, args.update).await {
+            Ok(report) if report.passed => println!("ok {}: {report}", path.display()),
+            Ok(report) => {
+                failed += 1;
+                println!("FAILED {}: {report}", path.display());
+            }
+            Err(e) => {
+                failed += 1;
+                unreadable = true;
+                println!("error {}: {e}", path.display());
+            }
+        }
+    }
+
+    match failed {
+        0 => println!("{} shaders ok", args.paths.len()),
+        _ => println!("{failed} of {} shaders failed", args.paths.len()),
+    }
+    if unreadable {
+        ExitCode::from(EXIT_UNREADABLE)
+    } else if failed > 0 {
+        ExitCode::from(EXIT_FAILED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 16;
+
+    fn gradient() -> Vec<u8> {
+        (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let (x, y) = ((i % SIZE) as u8, (i / SIZE) as u8);
+                [x * 16, y * 16, 128, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = gradient();
+        let (failing, max, diff) = compare(&image, &image, 0);
+        assert_eq!((failing, max), (0, 0));
+        assert_eq!(diff.len(), image.len());
+        assert!((ssim(&image, &image, SIZE, SIZE) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tolerance_decides_whether_a_pixel_fails() {
+        let expected = gradient();
+        let mut actual = expected.clone();
+        let pixel = (5 * SIZE + 7) as usize * 4;
+        actual[pixel + 1] += 10;
+
+        let (failing, max, diff) = compare(&actual, &expected, 0);
+        assert_eq!((failing, max), (1, 10));
+        assert_eq!(&diff[pixel..pixel + 4], &[133, 0, 0, 255]);
+
+        assert_eq!(compare(&actual, &expected, 9).0, 1);
+        assert_eq!(compare(&actual, &expected, 10).0, 0);
+        let similarity = ssim(&actual, &expected, SIZE, SIZE);
+        assert!(similarity < 1.0 && similarity > 0.99, "{similarity}");
+    }
+
+    #[test]
+    fn ssim_falls_for_different_structure() {
+        let expected = gradient();
+        // The same colours, flipped, over the edge tiles too
+        let actual: Vec<u8> = expected.chunks(4).rev().flatten().copied().collect();
+        assert!(ssim(&actual, &expected, SIZE, SIZE) < 0.5);
+        assert!(ssim(&actual[..12 * 4], &expected[..12 * 4], 3, 4) < 1.0);
+    }
+}
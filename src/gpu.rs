@@ -0,0 +1,101 @@
+// Picking an adapter and opening a device, with or without a window to present to
+
+use std::{io, sync::Arc};
+
+use crate::cli::GpuArgs;
+
+pub fn instance(gpu: &GpuArgs) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: gpu.backend.backends(),
+        dx12_shader_compiler: Default::default(),
+    })
+}
+
+// The adapter named by `--adapter`, or the most powerful one that can present to `surface`.
+// `--software` asks for the fallback adapter instead, like llvmpipe or WARP.
+pub async fn adapter(
+    instance: &wgpu::Instance,
+    gpu: &GpuArgs,
+    surface: Option<&wgpu::Surface>,
+) -> io::Result<wgpu::Adapter> {
+    let Some(wanted) = &gpu.adapter else {
+        return instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: surface,
+                force_fallback_adapter: gpu.software,
+            })
+            .await
+            .ok_or_else(|| {
+                let message = match gpu.software {
+                    true => "No software adapter",
+                    false => "No suitable GPU adapter",
+                };
+                io::Error::new(io::ErrorKind::NotFound, message)
+            });
+    };
+
+    let wanted_lower = wanted.to_lowercase();
+    instance
+        .enumerate_adapters(gpu.backend.backends())
+        .enumerate()
+        .find(|(i, adapter)| {
+            let name = adapter.get_info().name.to_lowercase();
+            (wanted.parse() == Ok(*i) || name.contains(&wanted_lower))
+                && surface.is_none_or(|s| adapter.is_surface_supported(s))
+        })
+        .map(|(_, adapter)| adapter)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No usable GPU adapter matching {wanted}"),
+            )
+        })
+}
+
+// Features the workbench uses when the adapter has them, and does without otherwise
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY;
+
+// Shared, so an embedding app can keep using them alongside the workbench
+pub async fn device(adapter: &wgpu::Adapter) -> io::Result<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: adapter.features() & OPTIONAL_FEATURES,
+                // WebGL doesn't support all of wgpu's features, so if
+                // we're building for the web we'll have to disable some.
+                limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::default()
+                },
+                label: None,
+            },
+            None, // Trace path
+        )
+        .await
+        .map(|(device, queue)| (Arc::new(device), Arc::new(queue)))
+        .map_err(io::Error::other)
+}
+
+// A device for rendering without a window
+pub async fn headless(gpu: &GpuArgs) -> io::Result<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+    let instance = instance(gpu);
+    let adapter = adapter(&instance, gpu, None).await?;
+    device(&adapter).await
+}
+
+pub fn list_adapters(gpu: &GpuArgs) {
+    let instance = instance(gpu);
+    println!("GPU adapters:");
+    for (i, adapter) in instance
+        .enumerate_adapters(gpu.backend.backends())
+        .enumerate()
+    {
+        let info = adapter.get_info();
+        println!(
+            "  {i}: {} ({:?}, {:?})",
+            info.name, info.backend, info.device_type
+        );
+    }
+}
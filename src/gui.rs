@@ -0,0 +1,455 @@
+// A minimal egui integration: egui-winit for input, plus a small wgpu painter for the output.
+
+use std::collections::HashMap;
+
+use egui::{epaint::Primitive, ClippedPrimitive, ImageData, TextureFilter, TextureId};
+use parking_lot::Mutex;
+use wgpu::{util::DeviceExt, Buffer, Extent3d, ImageCopyTexture};
+use winit::{event::WindowEvent, window::Window};
+
+pub struct Gui {
+    pub ctx: egui::Context,
+    pub visible: bool,
+    // Behind a mutex only because its clipboard isn't Sync, and App is shared through an RwLock
+    state: Mutex<egui_winit::State>,
+    painter: Painter,
+}
+
+impl Gui {
+    pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let mut state = egui_winit::State::new(window);
+        state.set_pixels_per_point(egui_winit::native_pixels_per_point(window));
+        state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+
+        Self {
+            ctx: egui::Context::default(),
+            visible: false,
+            state: Mutex::new(state),
+            painter: Painter::new(device, format),
+        }
+    }
+
+    // Returns true if egui wants the event for itself
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        let response = self.state.get_mut().on_event(&self.ctx, event);
+        self.visible && response.consumed
+    }
+
+    // Run one frame of UI. Nothing is drawn while the gui is hidden, but input is still drained.
+    pub fn run(&mut self, window: &Window, ui: impl FnOnce(&egui::Context)) {
+        let state = self.state.get_mut();
+        let input = state.take_egui_input(window);
+        let visible = self.visible;
+        let output = self.ctx.run(input, |ctx| {
+            if visible {
+                ui(ctx)
+            }
+        });
+
+        state.handle_platform_output(window, &self.ctx, output.platform_output);
+        self.painter.textures_delta.append(output.textures_delta);
+        self.painter.primitives = self.ctx.tessellate(output.shapes);
+    }
+
+    // Show a texture drawn outside egui, like the texture viewer's thumbnails. It's sampled
+    // nearest, so small textures stay sharp.
+    pub fn register_texture(
+        &mut self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+    ) -> TextureId {
+        let painter = &mut self.painter;
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        });
+        let bind_group = painter.texture_bind_group(device, view, &sampler);
+        let id = TextureId::User(painter.next_user_texture);
+        painter.next_user_texture += 1;
+        painter.user_textures.insert(id, bind_group);
+        id
+    }
+
+    pub fn free_texture(&mut self, id: TextureId) {
+        self.painter.user_textures.remove(&id);
+    }
+
+    pub fn paint(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        self.painter.paint(
+            device,
+            queue,
+            encoder,
+            view,
+            size,
+            self.ctx.pixels_per_point(),
+        );
+    }
+}
+
+struct GuiTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+struct Painter {
+    pipeline: wgpu::RenderPipeline,
+    screen_unif: Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    textures: HashMap<TextureId, GuiTexture>,
+    // Registered with register_texture, and drawn by something else
+    user_textures: HashMap<TextureId, wgpu::BindGroup>,
+    next_user_texture: u64,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    textures_delta: egui::TexturesDelta,
+    primitives: Vec<ClippedPrimitive>,
+}
+
+impl Painter {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("gui.wgsl"));
+
+        let screen_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gui Screen Size Uniform"),
+            contents: bytemuck::cast_slice(&[0f32; 2]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let screen_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("gui_screen_bind_group_layout"),
+        });
+
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &screen_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_unif.as_entire_binding(),
+            }],
+            label: Some("gui_screen_bind_group"),
+        });
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("gui_texture_bind_group_layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gui Pipeline Layout"),
+            bind_group_layouts: &[&screen_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Gui Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<egui::epaint::Vertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    // egui outputs premultiplied alpha
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let vertex_buffer = Self::create_buffer(device, wgpu::BufferUsages::VERTEX, 1 << 16);
+        let index_buffer = Self::create_buffer(device, wgpu::BufferUsages::INDEX, 1 << 16);
+
+        Self {
+            pipeline,
+            screen_unif,
+            screen_bind_group,
+            texture_layout,
+            textures: HashMap::new(),
+            user_textures: HashMap::new(),
+            next_user_texture: 0,
+            vertex_buffer,
+            index_buffer,
+            textures_delta: Default::default(),
+            primitives: vec![],
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, usage: wgpu::BufferUsages, size: u64) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gui Buffer"),
+            size,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn texture_bind_group(
+        &self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("gui_texture_bind_group"),
+        })
+    }
+
+    fn update_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) {
+        let pixels: Vec<egui::Color32> = match &delta.image {
+            ImageData::Color(image) => image.pixels.clone(),
+            ImageData::Font(image) => image.srgba_pixels(None).collect(),
+        };
+        let [width, height] = delta.image.size();
+        let size = Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+
+        // A delta without a position replaces the whole texture
+        if delta.pos.is_none() {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                label: Some("Gui Texture"),
+                view_formats: &[],
+            });
+
+            let filter = |f: TextureFilter| match f {
+                TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+                TextureFilter::Linear => wgpu::FilterMode::Linear,
+            };
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: filter(delta.options.magnification),
+                min_filter: filter(delta.options.minification),
+                ..Default::default()
+            });
+
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = self.texture_bind_group(device, &view, &sampler);
+
+            self.textures.insert(
+                id,
+                GuiTexture {
+                    texture,
+                    bind_group,
+                },
+            );
+        }
+
+        let Some(gui_texture) = self.textures.get(&id) else {
+            return;
+        };
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &gui_texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: x as u32,
+                    y: y as u32,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    fn paint(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: (u32, u32),
+        pixels_per_point: f32,
+    ) {
+        let delta = std::mem::take(&mut self.textures_delta);
+        for (id, image_delta) in &delta.set {
+            self.update_texture(device, queue, *id, image_delta);
+        }
+
+        let meshes: Vec<_> = self
+            .primitives
+            .iter()
+            .filter_map(|p| match &p.primitive {
+                Primitive::Mesh(mesh) if !mesh.indices.is_empty() => Some((p.clip_rect, mesh)),
+                _ => None,
+            })
+            .collect();
+
+        if !meshes.is_empty() {
+            let vertices: Vec<egui::epaint::Vertex> = meshes
+                .iter()
+                .flat_map(|(_, m)| m.vertices.iter().copied())
+                .collect();
+            let indices: Vec<u32> = meshes
+                .iter()
+                .flat_map(|(_, m)| m.indices.iter().copied())
+                .collect();
+            let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertices);
+            let index_bytes: &[u8] = bytemuck::cast_slice(&indices);
+
+            if self.vertex_buffer.size() < vertex_bytes.len() as u64 {
+                self.vertex_buffer = Self::create_buffer(
+                    device,
+                    wgpu::BufferUsages::VERTEX,
+                    (vertex_bytes.len() as u64).next_power_of_two(),
+                );
+            }
+            if self.index_buffer.size() < index_bytes.len() as u64 {
+                self.index_buffer = Self::create_buffer(
+                    device,
+                    wgpu::BufferUsages::INDEX,
+                    (index_bytes.len() as u64).next_power_of_two(),
+                );
+            }
+            queue.write_buffer(&self.vertex_buffer, 0, vertex_bytes);
+            queue.write_buffer(&self.index_buffer, 0, index_bytes);
+            queue.write_buffer(
+                &self.screen_unif,
+                0,
+                bytemuck::cast_slice(&[
+                    size.0 as f32 / pixels_per_point,
+                    size.1 as f32 / pixels_per_point,
+                ]),
+            );
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Gui Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            let (mut first_index, mut base_vertex) = (0u32, 0i32);
+            for (clip_rect, mesh) in &meshes {
+                let index_count = mesh.indices.len() as u32;
+                let vertex_count = mesh.vertices.len() as i32;
+
+                // Clip rects are in points, scissor rects in physical pixels
+                let min_x = ((clip_rect.min.x * pixels_per_point).round() as u32).min(size.0);
+                let min_y = ((clip_rect.min.y * pixels_per_point).round() as u32).min(size.1);
+                let max_x = ((clip_rect.max.x * pixels_per_point).round() as u32).min(size.0);
+                let max_y = ((clip_rect.max.y * pixels_per_point).round() as u32).min(size.1);
+
+                let bind_group = match self.textures.get(&mesh.texture_id) {
+                    Some(texture) => Some(&texture.bind_group),
+                    None => self.user_textures.get(&mesh.texture_id),
+                };
+                if let Some(bind_group) = bind_group {
+                    if max_x > min_x && max_y > min_y {
+                        render_pass.set_scissor_rect(min_x, min_y, max_x - min_x, max_y - min_y);
+                        render_pass.set_bind_group(1, bind_group, &[]);
+                        render_pass.draw_indexed(
+                            first_index..first_index + index_count,
+                            base_vertex,
+                            0..1,
+                        );
+                    }
+                }
+
+                first_index += index_count;
+                base_vertex += vertex_count;
+            }
+        }
+
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> screen_size: vec2<f32>;
+@group(1) @binding(0)
+var guiTexture: texture_2d<f32>;
+@group(1) @binding(1)
+var guiSampler: sampler;
+
+// egui hands us sRGB vertex colours, but we render to an sRGB surface which expects linear output
+fn linear_from_srgb(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(10.31475);
+    let lower = srgb / vec3<f32>(3294.6);
+    let higher = pow((srgb + vec3<f32>(14.025)) / vec3<f32>(269.025), vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs_main(
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.pos = vec4<f32>(2.0 * pos.x / screen_size.x - 1.0, 1.0 - 2.0 * pos.y / screen_size.y, 0.0, 1.0);
+    out.uv = uv;
+    let c = unpack4x8unorm(color);
+    out.color = vec4<f32>(linear_from_srgb(c.rgb * 255.0), c.a);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * textureSample(guiTexture, guiSampler, in.uv);
+}
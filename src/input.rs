@@ -0,0 +1,508 @@
+// Everything a shader reads besides the built-in uniforms: cameras, files, audio, the mouse and
+// keyboard, or a source registered with register_input_source. Projects list them by name,
+//
+//     [inputs.mic]
+//     source = "audio"
+//
+// and each binding a source describes shows up in the prelude under that name, in group 2.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use wgpu::{Extent3d, TextureView};
+use winit::event::WindowEvent;
+
+use crate::{project::Project, renderer::Renderer, resize::ResizePolicy, sources};
+
+/// What a binding holds, which decides how it's declared to the shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    /// An RGBA8 `texture_2d<f32>`, with a linear clamp-to-edge sampler named `<name>Sampler`.
+    /// Takes the size of whatever was last uploaded.
+    Texture,
+    /// `len` floats as a uniform: a `vec4<f32>` for up to four, an `array<vec4<f32>, N>` past
+    /// that.
+    Uniform { len: u32 },
+}
+
+/// One binding of an [`InputSource`]. The shader sees it as the input's name followed by `name`,
+/// so a source with a single binding usually leaves `name` empty.
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+}
+
+impl Binding {
+    pub fn texture(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: BindingKind::Texture,
+        }
+    }
+
+    pub fn uniform(name: &str, len: u32) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: BindingKind::Uniform { len },
+        }
+    }
+}
+
+/// New contents for a binding, matching its [`BindingKind`].
+pub enum Upload<'a> {
+    Texture {
+        width: u32,
+        height: u32,
+        rgba: &'a [u8],
+    },
+    /// Zero padded, or cut off, to the binding's length.
+    Uniform(&'a [f32]),
+}
+
+/// Something that feeds data to the shader every frame.
+///
+/// Sources are made before anything is rendered, and also just to read their bindings when
+/// validating a shader, so opening devices or files belongs in the first [`InputSource::poll`].
+pub trait InputSource {
+    /// The bindings this source fills. Must not change over the source's life.
+    fn describe_bindings(&self) -> Vec<Binding>;
+
+    /// Called once a frame before rendering, to pick up new data.
+    fn poll(&mut self);
+
+    /// The contents for the binding at `index` in [`InputSource::describe_bindings`], or None
+    /// to keep what was uploaded last.
+    fn upload(&mut self, index: usize) -> Option<Upload<'_>>;
+
+    /// Window events, for sources that follow the mouse or keyboard.
+    fn window_event(&mut self, _event: &WindowEvent) {}
+}
+
+/// An input's table in the project file. `source` picks the kind of input, everything else is
+/// left for the source to read.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputConfig {
+    pub source: String,
+    #[serde(flatten)]
+    pub options: toml::Table,
+    // The project's directory, which paths in options are relative to
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(skip)]
+    resolution: ResizePolicy,
+}
+
+impl InputConfig {
+    /// A `source` input with `options`, as if read from a project file in the current directory.
+    pub fn new(source: &str, options: toml::Table) -> Self {
+        Self {
+            source: source.to_owned(),
+            options,
+            dir: PathBuf::new(),
+            resolution: ResizePolicy::default(),
+        }
+    }
+
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.options.get(key)?.as_str()
+    }
+
+    pub fn int(&self, key: &str) -> Option<i64> {
+        self.options.get(key)?.as_integer()
+    }
+
+    /// A path option, resolved against the project's directory.
+    pub fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let path = self.str(key).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} input needs a `{key}` path", self.source),
+            )
+        })?;
+        Ok(self.dir.join(path))
+    }
+
+    /// How the project's render target fits in the window, for sources that follow the cursor.
+    pub fn resolution(&self) -> &ResizePolicy {
+        &self.resolution
+    }
+}
+
+type Factory = Box<dyn Fn(&InputConfig) -> io::Result<Box<dyn InputSource>> + Send>;
+
+static REGISTRY: Mutex<BTreeMap<String, Factory>> = parking_lot::const_mutex(BTreeMap::new());
+
+/// Make `source = "<name>"` in a project's inputs build a source with `factory`. Takes
+/// precedence over the built-in sources of the same name.
+pub fn register_input_source(
+    name: &str,
+    factory: impl Fn(&InputConfig) -> io::Result<Box<dyn InputSource>> + Send + 'static,
+) {
+    REGISTRY.lock().insert(name.to_owned(), Box::new(factory));
+}
+
+fn create(config: &InputConfig) -> io::Result<Box<dyn InputSource>> {
+    if let Some(factory) = REGISTRY.lock().get(&config.source) {
+        return factory(config);
+    }
+    sources::builtin(config)
+}
+
+// The sources of a project or embedding app, in binding order
+#[derive(Default)]
+pub struct Inputs {
+    sources: Vec<(String, Box<dyn InputSource>)>,
+}
+
+impl Inputs {
+    pub fn open(project: &Project) -> io::Result<Self> {
+        let dir = project.resolve(Path::new(""));
+        let mut inputs = Self::default();
+        for (name, config) in &project.inputs {
+            let config = InputConfig {
+                dir: dir.clone(),
+                resolution: project.resolution.clone(),
+                ..config.clone()
+            };
+            let source = create(&config)
+                .map_err(|e| io::Error::new(e.kind(), format!("Input {name}: {e}")))?;
+            inputs.push(name, source);
+        }
+        Ok(inputs)
+    }
+
+    pub fn push(&mut self, name: &str, source: Box<dyn InputSource>) {
+        self.sources.push((name.to_owned(), source));
+    }
+
+    pub fn layout(&self) -> InputLayout {
+        let mut bindings = vec![];
+        for (name, source) in &self.sources {
+            for binding in source.describe_bindings() {
+                bindings.push((format!("{name}{}", binding.name), binding.kind));
+            }
+        }
+        InputLayout { bindings }
+    }
+
+    // Poll every source and upload whatever changed
+    pub fn update(&mut self, renderer: &mut Renderer) {
+        let mut index = 0;
+        for (_, source) in &mut self.sources {
+            source.poll();
+            for binding in 0..source.describe_bindings().len() {
+                if let Some(upload) = source.upload(binding) {
+                    renderer.upload_input(index, upload);
+                }
+                index += 1;
+            }
+        }
+    }
+
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        for (_, source) in &mut self.sources {
+            source.window_event(event);
+        }
+    }
+}
+
+// Every binding of every input, flattened and named the way the shader sees them
+#[derive(Clone, Debug, Default)]
+pub struct InputLayout {
+    bindings: Vec<(String, BindingKind)>,
+}
+
+impl InputLayout {
+    // The prelude declarations. Textures take two slots, the second for their sampler.
+    pub fn wgsl(&self) -> String {
+        let mut lines = vec![];
+        let mut slot = 0;
+        for (name, kind) in &self.bindings {
+            match kind {
+                BindingKind::Texture => {
+                    lines.push(format!(
+                        "@group(2) @binding({slot}) var {name}: texture_2d<f32>;"
+                    ));
+                    lines.push(format!(
+                        "@group(2) @binding({}) var {name}Sampler: sampler;",
+                        slot + 1
+                    ));
+                    slot += 2;
+                }
+                BindingKind::Uniform { len } => {
+                    let ty = match vec4s(*len) {
+                        1 => "vec4<f32>".to_owned(),
+                        n => format!("array<vec4<f32>, {n}>"),
+                    };
+                    lines.push(format!(
+                        "@group(2) @binding({slot}) var<uniform> {name}: {ty};"
+                    ));
+                    slot += 1;
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    // The same for GLSL shaders. Uniforms are wrapped in blocks, whose members GLSL makes global.
+    pub fn glsl(&self) -> Vec<String> {
+        let mut lines = vec![];
+        let mut slot = 0;
+        for (name, kind) in &self.bindings {
+            match kind {
+                BindingKind::Texture => {
+                    lines.push(format!(
+                        "layout(set = 2, binding = {slot}) uniform texture2D {name};"
+                    ));
+                    lines.push(format!(
+                        "layout(set = 2, binding = {}) uniform sampler {name}Sampler;",
+                        slot + 1
+                    ));
+                    slot += 2;
+                }
+                BindingKind::Uniform { len } => {
+                    let member = match vec4s(*len) {
+                        1 => format!("vec4 {name};"),
+                        n => format!("vec4 {name}[{n}];"),
+                    };
+                    lines.push(format!(
+                        "layout(set = 2, binding = {slot}) uniform Input_{name} {{ {member} }};"
+                    ));
+                    slot += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    pub fn kind(&self, name: &str) -> Option<BindingKind> {
+        self.bindings
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, kind)| *kind)
+    }
+}
+
+fn vec4s(len: u32) -> u32 {
+    len.div_ceil(4).max(1)
+}
+
+enum Resource {
+    Texture {
+        name: String,
+        texture: wgpu::Texture,
+        view: TextureView,
+    },
+    Uniform(wgpu::Buffer),
+}
+
+// The GPU side of an InputLayout: bind group 2
+pub struct InputBindings {
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    resources: Vec<Resource>,
+    sampler: wgpu::Sampler,
+}
+
+impl InputBindings {
+    pub fn new(device: &wgpu::Device, layout: &InputLayout) -> Self {
+        let mut entries = vec![];
+        let mut resources = vec![];
+        for (name, kind) in &layout.bindings {
+            let binding = entries.len() as u32;
+            match kind {
+                BindingKind::Texture => {
+                    entries.push(wgpu::BindGroupLayoutEntry {
+                        binding,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    });
+                    entries.push(wgpu::BindGroupLayoutEntry {
+                        binding: binding + 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    });
+                    // A black pixel until the source uploads something
+                    let (texture, view) = create_texture(device, (1, 1));
+                    resources.push(Resource::Texture {
+                        name: name.clone(),
+                        texture,
+                        view,
+                    });
+                }
+                BindingKind::Uniform { len } => {
+                    entries.push(wgpu::BindGroupLayoutEntry {
+                        binding,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    });
+                    resources.push(Resource::Uniform(device.create_buffer(
+                        &wgpu::BufferDescriptor {
+                            label: Some("Input Uniform"),
+                            size: vec4s(*len) as u64 * 16,
+                            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        },
+                    )));
+                }
+            }
+        }
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("input_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = create_bind_group(device, &layout, &resources, &sampler);
+
+        Self {
+            layout,
+            bind_group,
+            resources,
+            sampler,
+        }
+    }
+
+    // Textures are recreated when the upload's size changes, which means a new bind group
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        upload: Upload,
+    ) {
+        match (&mut self.resources[index], upload) {
+            (
+                Resource::Texture { texture, view, .. },
+                Upload::Texture {
+                    width,
+                    height,
+                    rgba,
+                },
+            ) => {
+                if rgba.len() < (width * height * 4) as usize || width == 0 || height == 0 {
+                    println!(
+                        "Input upload of {} bytes is too short for {width}x{height}",
+                        rgba.len()
+                    );
+                    return;
+                }
+                if (texture.width(), texture.height()) != (width, height) {
+                    (*texture, *view) = create_texture(device, (width, height));
+                    self.bind_group =
+                        create_bind_group(device, &self.layout, &self.resources, &self.sampler);
+                }
+                let Resource::Texture { texture, .. } = &self.resources[index] else {
+                    unreachable!()
+                };
+                write_texture(queue, texture, rgba);
+            }
+            (Resource::Uniform(buffer), Upload::Uniform(values)) => {
+                let mut padded = vec![0f32; buffer.size() as usize / 4];
+                let len = values.len().min(padded.len());
+                padded[..len].copy_from_slice(&values[..len]);
+                queue.write_buffer(buffer, 0, bytemuck::cast_slice(&padded));
+            }
+            _ => println!("Input upload doesn't match binding {index}"),
+        }
+    }
+
+    // The texture bindings, by the names the shader sees them as
+    pub fn textures(&self) -> impl Iterator<Item = (&str, &wgpu::Texture)> {
+        self.resources.iter().filter_map(|resource| match resource {
+            Resource::Texture { name, texture, .. } => Some((name.as_str(), texture)),
+            Resource::Uniform(_) => None,
+        })
+    }
+}
+
+// RGBA8 sRGB, like the camera's videoBuffer
+pub fn create_texture(device: &wgpu::Device, size: (u32, u32)) -> (wgpu::Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: Some("Input Texture"),
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+pub fn write_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, rgba: &[u8]) {
+    queue.write_texture(
+        texture.as_image_copy(),
+        rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * texture.width()),
+            rows_per_image: Some(texture.height()),
+        },
+        texture.size(),
+    );
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    resources: &[Resource],
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    let mut entries = vec![];
+    for resource in resources {
+        let binding = entries.len() as u32;
+        match resource {
+            Resource::Texture { view, .. } => {
+                entries.push(wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(view),
+                });
+                entries.push(wgpu::BindGroupEntry {
+                    binding: binding + 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                });
+            }
+            Resource::Uniform(buffer) => entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            }),
+        }
+    }
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some("input_bind_group"),
+    })
+}
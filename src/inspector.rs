@@ -0,0 +1,162 @@
+use egui::{Color32, ColorImage, Stroke, TextureHandle, TextureOptions};
+
+use crate::renderer::{Renderer, Stage};
+
+// Texels read around the cursor, and how much bigger the loupe draws them
+const LOUPE_SIZE: u32 = 15;
+const LOUPE_ZOOM: f32 = 10.0;
+
+// The egui pixel inspector: the exact values under the cursor, read back from the render target
+// or back buffer, with a magnified loupe around them. Freezing stops new frames being drawn, so
+// the values hold still while the cursor moves.
+#[derive(Default)]
+pub struct Inspector {
+    pub open: bool,
+    frozen: bool,
+    stage: Stage,
+    region: Option<Region>,
+    error: Option<String>,
+    loupe: Option<TextureHandle>,
+}
+
+// Texels around the inspected pixel, in rows
+struct Region {
+    pixel: (u32, u32),
+    origin: (u32, u32),
+    size: (u32, u32),
+    texels: Vec<[f32; 4]>,
+    // Whether the values are linear, rather than going to the display as they are
+    linear: bool,
+}
+
+impl Region {
+    fn texel(&self, (x, y): (u32, u32)) -> [f32; 4] {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        self.texels[(y * self.size.0 + x) as usize]
+    }
+
+    fn color(&self, [r, g, b, _]: [f32; 4]) -> Color32 {
+        if self.linear {
+            egui::Rgba::from_rgb(r, g, b).into()
+        } else {
+            let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+            Color32::from_rgb(byte(r), byte(g), byte(b))
+        }
+    }
+}
+
+impl Inspector {
+    // Whether the App should keep showing the last frame rather than drawing new ones
+    pub fn is_frozen(&self) -> bool {
+        self.open && self.frozen
+    }
+
+    // Read back the texels around `pixel`, the render target pixel under the cursor. The App
+    // leaves the last ones while the cursor is on a panel, so it can reach the controls.
+    pub fn update(&mut self, renderer: &Renderer, pixel: Option<(u32, u32)>) {
+        let Some(pixel) = pixel.filter(|_| self.open) else {
+            return;
+        };
+
+        let size = renderer.size();
+        // Centred on the pixel, but kept inside the texture near its edges
+        let region_size = (LOUPE_SIZE.min(size.0), LOUPE_SIZE.min(size.1));
+        let origin = (
+            pixel
+                .0
+                .saturating_sub(LOUPE_SIZE / 2)
+                .min(size.0 - region_size.0),
+            pixel
+                .1
+                .saturating_sub(LOUPE_SIZE / 2)
+                .min(size.1 - region_size.1),
+        );
+        match renderer.read_region(self.stage, origin, region_size) {
+            Ok(texels) => {
+                self.region = Some(Region {
+                    pixel,
+                    origin,
+                    size: region_size,
+                    texels,
+                    linear: renderer.format().is_srgb(),
+                });
+                self.error = None;
+            }
+            Err(e) => {
+                self.region = None;
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Inspector")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("inspector_stage")
+                        .selected_text(self.stage.name())
+                        .show_ui(ui, |ui| {
+                            for stage in Stage::ALL {
+                                ui.selectable_value(&mut self.stage, stage, stage.name());
+                            }
+                        });
+                    ui.checkbox(&mut self.frozen, "Freeze frame");
+                });
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                let Some(region) = &self.region else {
+                    ui.label("Move the cursor over the image");
+                    return;
+                };
+
+                let (x, y) = region.pixel;
+                ui.label(format!("Pixel {x}, {y}, at pos.xy {x}.5, {y}.5"));
+                let texel = region.texel(region.pixel);
+                egui::Grid::new("inspector_values").show(ui, |ui| {
+                    for (channel, value) in ["R", "G", "B", "A"].iter().zip(texel) {
+                        ui.label(*channel);
+                        ui.monospace(value.to_string());
+                        ui.end_row();
+                    }
+                });
+
+                let image = ColorImage {
+                    size: [region.size.0 as usize, region.size.1 as usize],
+                    pixels: region.texels.iter().map(|t| region.color(*t)).collect(),
+                };
+                let loupe = match &mut self.loupe {
+                    Some(loupe) => {
+                        loupe.set(image, TextureOptions::NEAREST);
+                        loupe
+                    }
+                    None => self.loupe.insert(ctx.load_texture(
+                        "inspector_loupe",
+                        image,
+                        TextureOptions::NEAREST,
+                    )),
+                };
+                let size = egui::vec2(region.size.0 as f32, region.size.1 as f32) * LOUPE_ZOOM;
+                let response = ui.image(loupe.id(), size);
+
+                // Outline the inspected texel
+                let offset = egui::vec2((x - region.origin.0) as f32, (y - region.origin.1) as f32)
+                    * LOUPE_ZOOM;
+                let min = response.rect.min + offset;
+                let rect = egui::Rect::from_min_size(min, egui::Vec2::splat(LOUPE_ZOOM));
+                let [r, g, b, _] = texel;
+                let stroke = if r + g + b > 1.5 {
+                    Color32::BLACK
+                } else {
+                    Color32::WHITE
+                };
+                ui.painter()
+                    .rect_stroke(rect, 0.0, Stroke::new(1.0, stroke));
+            });
+        self.open = open;
+    }
+}
@@ -1,132 +1,306 @@
-use audio::start_audio_capture;
-use nokhwa::{
-    pixel_format::RgbAFormat,
-    utils::{CameraIndex, RequestedFormat},
-};
 use parking_lot::RwLock;
-use std::{env, path::Path, sync::Arc};
 
 use appstate::App;
-use hotwatch::{EventKind, Hotwatch};
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    event_loop::ControlFlow,
 };
 
-use crate::appstate::RenderPipelineContext;
+#[cfg(not(target_arch = "wasm32"))]
+use hotwatch::{EventKind, Hotwatch};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io, path::Path, process::ExitCode, rc::Rc};
+#[cfg(not(target_arch = "wasm32"))]
+use winit::{dpi::PhysicalSize, event_loop::EventLoop, window::WindowBuilder};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    appstate::RenderPipelineContext,
+    cli::{Cli, Command, GpuArgs, ReplayArgs, RunArgs},
+    project::Project,
+    session::Replay,
+};
 
+#[cfg(not(target_arch = "wasm32"))]
+mod api;
 mod appstate;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
 mod audio;
+#[cfg(all(feature = "camera", not(target_arch = "wasm32")))]
+mod camera;
+mod capture;
+mod cli;
+mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+mod debug_print;
+mod glsl;
+#[cfg(not(target_arch = "wasm32"))]
+mod golden;
+mod gpu;
+mod gui;
+mod input;
+mod inspector;
+#[cfg(not(target_arch = "wasm32"))]
+mod lsp;
+// Mappings in project files survive builds without MIDI, even though nothing reads them
+#[cfg_attr(
+    not(all(feature = "midi", not(target_arch = "wasm32"))),
+    allow(dead_code)
+)]
+mod midi;
+#[cfg(not(target_arch = "wasm32"))]
+mod offline;
+mod osc;
+mod param_panel;
+mod params;
+mod poster;
+mod preset;
+mod project;
+mod renderer;
+mod resize;
+#[cfg(not(target_arch = "wasm32"))]
+mod session;
+mod shader;
+#[cfg(not(target_arch = "wasm32"))]
+mod shadertoy;
+mod sources;
+mod spirv;
+mod texture_viewer;
+mod timeline;
+mod timing;
+#[cfg(not(target_arch = "wasm32"))]
+mod transpile;
+#[cfg(not(target_arch = "wasm32"))]
+mod unit;
+#[cfg(not(target_arch = "wasm32"))]
+mod validate;
+#[cfg(target_arch = "wasm32")]
+mod web;
+mod workbench;
 
-pub async fn run() {
-    let index = CameraIndex::Index(0);
-    let frame_fmt = RequestedFormat::new::<RgbAFormat>(
-        nokhwa::utils::RequestedFormatType::AbsoluteHighestFrameRate,
-    );
+#[cfg(not(target_arch = "wasm32"))]
+pub use golden::{GoldenReport, GoldenTest};
+pub use input::{register_input_source, Binding, BindingKind, InputConfig, InputSource, Upload};
+pub use osc::{OscCommand, OscServer};
+pub use workbench::{Output, Workbench, WorkbenchBuilder};
 
-    nokhwa::nokhwa_initialize(|_| {});
-    while !nokhwa::nokhwa_check() {}
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn run() -> ExitCode {
+    let result = match Cli::parse_env().command {
+        Command::Run(args) => run_window(args).await,
+        Command::Render(args) => offline::render(&args).await,
+        Command::Validate(args) => return validate::run(&args),
+        Command::Export(args) => offline::export(&args).await,
+        Command::Test(args) => return golden::run(&args).await,
+        Command::Unit(args) => return unit::run(&args).await,
+        Command::Shadertoy(command) => shadertoy::run(&command),
+        Command::Transpile(args) => transpile::run(&args),
+        Command::Replay(args) => match &args.out {
+            Some(out) => session::render(&args, out).await,
+            None => run_replay(args).await,
+        },
+        Command::ListDevices(gpu) => {
+            list_devices(&gpu);
+            Ok(())
+        }
+    };
 
-    let mut camera = nokhwa::Camera::new(index, frame_fmt).expect("Failed to open camera!");
-    let _ = camera.open_stream();
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let camera_dim = (camera.resolution().x(), camera.resolution().y());
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_lsp() -> ExitCode {
+    lsp::run()
+}
 
-    start_audio_capture();
+#[cfg(not(target_arch = "wasm32"))]
+fn list_devices(gpu: &GpuArgs) {
+    #[cfg(feature = "camera")]
+    {
+        println!("Cameras:");
+        match nokhwa::query(nokhwa::utils::ApiBackend::Auto) {
+            Ok(cameras) => {
+                for camera in cameras {
+                    println!("  {}: {}", camera.index(), camera.human_name());
+                }
+            }
+            Err(e) => println!("  Failed to query cameras: {e}"),
+        }
+    }
 
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    #[cfg(feature = "audio")]
+    audio::list_input_devices();
+    #[cfg(feature = "midi")]
+    midi::list_input_ports();
+    gpu::list_adapters(gpu);
+}
 
-    let args: Vec<String> = env::args().collect();
-    let file = args
-        .get(1)
-        .expect("Put a WGSL file to watch as the first argument!");
+// The camera feeding videoBuffer, opened up front so a missing one is an error
+#[cfg(all(feature = "camera", not(target_arch = "wasm32")))]
+fn open_camera(args: &RunArgs) -> io::Result<Option<Box<dyn input::InputSource>>> {
+    if args.no_camera {
+        return Ok(None);
+    }
+    let mut camera = camera::CameraSource::new(args.camera);
+    camera
+        .open()
+        .map_err(|e| io::Error::other(format!("{e}. Pass --no-camera to run without one.")))?;
+    Ok(Some(Box::new(camera)))
+}
 
-    let app = Arc::new(RwLock::new(App::new(window, camera_dim, file).await));
-    let rpctx = app.read().rpcontext.clone();
+#[cfg(not(target_arch = "wasm32"))]
+async fn run_window(args: RunArgs) -> io::Result<()> {
+    let project = Project::open(&args.path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Could not open project {}: {e}", args.path.display()),
+        )
+    })?;
+    let file = project.shader_path().to_string_lossy().into_owned();
 
-    if !Path::new(file).exists() {
-        println!("Could not file file: {file}");
-        return;
+    if !Path::new(&file).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Could not find file: {file}"),
+        ));
     }
 
-    println!("Watching file: {file}");
-    let mut watch = Hotwatch::new().expect("Hotwatch failed to init!");
-    let fcln = file.clone();
-    watch
-        .watch(file, move |event: hotwatch::notify::Event| {
-            if let EventKind::Modify(_) = event.kind {
-                println!("File Changed, recompiling...");
-                pollster::block_on(RenderPipelineContext::rebuild_pipeline(
-                    rpctx.clone(),
-                    &fcln,
-                ));
-            }
-        })
-        .expect("Failed to start watching file!");
-
-    event_loop.run(move |event, _, control_flow| {
-        let read = app.read();
-        match event {
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } if window_id == read.window().id() => {
-                if !read.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(size) => {
-                            drop(read);
-                            let mut write = app.write();
-                            write.resize(*size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            drop(read);
-                            let mut write = app.write();
-                            write.resize(**new_inner_size);
-                        }
-                        _ => {}
-                    }
+    #[cfg(feature = "camera")]
+    let video = open_camera(&args)?;
+    // videoBuffer is a single black pixel without a camera
+    #[cfg(not(feature = "camera"))]
+    let video = None;
+
+    let event_loop = EventLoop::new();
+    let mut builder = WindowBuilder::new();
+    if let Some((width, height)) = args.size {
+        builder = builder.with_inner_size(PhysicalSize::new(width, height));
+    }
+    let window = builder.build(&event_loop).map_err(io::Error::other)?;
+
+    let mut app = App::new(window, video, project, &args.gpu, args.present_mode).await?;
+    if let Some(path) = &args.record {
+        app.record(path)?;
+        println!("Recording session to {}", path.display());
+    }
+    let app = Rc::new(RwLock::new(app));
+
+    // Kept alive for as long as the event loop runs
+    let _watch = if args.no_watch {
+        None
+    } else {
+        let rpctx = app.read().renderer.rpcontext.clone();
+        println!("Watching file: {file}");
+        let mut watch = Hotwatch::new().expect("Hotwatch failed to init!");
+        let fcln = file.clone();
+        let shader = fs::canonicalize(&file)?;
+        // The whole directory, so edits to included files trigger a rebuild too
+        let dir = shader.parent().unwrap_or(Path::new(".")).to_owned();
+        watch
+            .watch(dir, move |event: hotwatch::notify::Event| {
+                let EventKind::Modify(_) = event.kind else {
+                    return;
+                };
+                let relevant = event
+                    .paths
+                    .iter()
+                    .any(|p| *p == shader || rpctx.read().includes.contains(p));
+                if relevant {
+                    println!("File Changed, recompiling...");
+                    pollster::block_on(RenderPipelineContext::rebuild_pipeline(
+                        rpctx.clone(),
+                        &fcln,
+                    ));
                 }
-            }
-            Event::RedrawRequested(window_id) if window_id == read.window().id() => {
-                drop(read);
-                let mut write = app.write();
-                let frame = &camera
-                    .frame()
-                    .unwrap()
-                    .decode_image::<RgbAFormat>()
-                    .unwrap();
-
-                write.update_camera(frame);
-                write.update();
-                let s = write.size;
-                match write.render() {
-                    Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => write.resize(s),
-                    // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+            })
+            .expect("Failed to start watching file!");
+        Some(watch)
+    };
+
+    event_loop.run(move |event, _, control_flow| handle_event(&app, &event, control_flow));
+}
+
+// A recorded session in a window at the size it was recorded at, ignoring input but Escape
+#[cfg(not(target_arch = "wasm32"))]
+async fn run_replay(args: ReplayArgs) -> io::Result<()> {
+    let replay = Replay::open(&args)?;
+    let path = &replay.header.project;
+    let project = Project::open(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Could not open project {}: {e}", path.display()),
+        )
+    })?;
+
+    let event_loop = EventLoop::new();
+    let (width, height) = replay.header.window;
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(width, height))
+        .build(&event_loop)
+        .map_err(io::Error::other)?;
+
+    // No camera, its frames weren't recorded
+    let mut app = App::new(window, None, project, &args.gpu, args.present_mode).await?;
+    app.replay(replay);
+    let app = Rc::new(RwLock::new(app));
+    event_loop.run(move |event, _, control_flow| handle_event(&app, &event, control_flow));
+}
+
+// Window events and redraws, the same for the native and browser builds
+fn handle_event(app: &RwLock<App>, event: &Event<()>, control_flow: &mut ControlFlow) {
+    let read = app.read();
+    match event {
+        Event::WindowEvent { event, window_id } if *window_id == read.window().id() => {
+            drop(read);
+            let mut write = app.write();
+            if !write.input(event) {
+                match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(size) => {
+                        write.resize(*size);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        write.resize(**new_inner_size);
+                    }
+                    _ => {}
                 }
             }
-            Event::MainEventsCleared => {
-                // RedrawRequested will only trigger once, unless we manually
-                // request it.
-                read.window().request_redraw();
+        }
+        Event::RedrawRequested(window_id) if *window_id == read.window().id() => {
+            drop(read);
+            let mut write = app.write();
+            write.update();
+            let s = write.size;
+            match write.render() {
+                Ok(_) => {}
+                // Reconfigure the surface if lost
+                Err(wgpu::SurfaceError::Lost) => write.resize(s),
+                // The system is out of memory, we should probably quit
+                Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                // All other errors (Outdated, Timeout) should be resolved by the next frame
+                Err(e) => eprintln!("{:?}", e),
             }
-            _ => {}
         }
-    });
+        Event::MainEventsCleared => {
+            // RedrawRequested will only trigger once, unless we manually
+            // request it.
+            read.window().request_redraw();
+        }
+        _ => {}
+    }
 }
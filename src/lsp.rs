@@ -0,0 +1,576 @@
+// A language server for workbench shaders, spoken over stdin and stdout by the wgsl-lsp binary.
+// Editors see the shader the way the pipeline does: prelude bindings, params and includes all
+// resolve, and diagnostics are the same ones `validate` reports.
+//
+// Nothing here may print to stdout, that's the protocol channel.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{Completion, GotoDefinition, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticRelatedInformation, DiagnosticSeverity, Documentation, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use naga::{AddressSpace, ArraySize, Handle, Module, ScalarKind, Span, Type, TypeInner};
+
+use crate::{
+    input::Inputs,
+    params,
+    project::Project,
+    shader::{self, FragShader, PRELUDE},
+    validate::{self, Severity},
+};
+
+struct Server {
+    connection: Connection,
+    // Editor buffers, which win over what's on disk, by canonical path
+    docs: HashMap<PathBuf, String>,
+    // Files currently showing diagnostics, so they can be cleared once fixed
+    published: HashSet<Url>,
+}
+
+pub fn run() -> ExitCode {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_owned()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    if let Err(e) = connection.initialize(serde_json::json!(capabilities)) {
+        eprintln!("Failed to initialize: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut server = Server {
+        connection,
+        docs: HashMap::new(),
+        published: HashSet::new(),
+    };
+    let result = server.serve();
+    // The writer thread only finishes once every sender is gone
+    drop(server);
+    match result.and_then(|_| io_threads.join()) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+impl Server {
+    fn serve(&mut self) -> io::Result<()> {
+        let receiver = self.connection.receiver.clone();
+        for message in &receiver {
+            match message {
+                Message::Request(req) => {
+                    if self
+                        .connection
+                        .handle_shutdown(&req)
+                        .map_err(io::Error::other)?
+                    {
+                        return Ok(());
+                    }
+                    let response = self.handle(req);
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.notify(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> io::Result<()> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(io::Error::other)
+    }
+
+    fn handle(&self, req: Request) -> Response {
+        let Request { id, method, params } = req;
+        let result = match method.as_str() {
+            HoverRequest::METHOD => {
+                serde_json::from_value(params).map(|p| serde_json::json!(self.hover(p)))
+            }
+            GotoDefinition::METHOD => {
+                serde_json::from_value(params).map(|p| serde_json::json!(self.definition(p)))
+            }
+            Completion::METHOD => {
+                serde_json::from_value(params).map(|p| serde_json::json!(self.completion(p)))
+            }
+            _ => {
+                let message = format!("unsupported request {method}");
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn notify(&mut self, notification: Notification) -> io::Result<()> {
+        let Notification { method, params } = notification;
+        match method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(params).map_err(io::Error::other)?;
+                if let Some(path) = doc_path(&p.text_document.uri) {
+                    self.docs.insert(path, p.text_document.text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let mut p: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(params).map_err(io::Error::other)?;
+                // Full sync, so the last change is the whole document
+                if let (Some(path), Some(change)) =
+                    (doc_path(&p.text_document.uri), p.content_changes.pop())
+                {
+                    self.docs.insert(path, change.text);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(params).map_err(io::Error::other)?;
+                if let Some(path) = doc_path(&p.text_document.uri) {
+                    self.docs.remove(&path);
+                }
+            }
+            // Includes that aren't open are read from disk, so they may have changed
+            DidSaveTextDocument::METHOD => {}
+            _ => return Ok(()),
+        }
+        self.publish_diagnostics()
+    }
+
+    // A file composed with its includes, reading open ones from the editor
+    fn compose(&self, path: &Path) -> FragShader {
+        let source = self.text(path);
+        let mut frag = FragShader::with_loader(path, source, |p| match self.docs.get(p) {
+            Some(text) => Ok(text.clone()),
+            None => fs::read_to_string(p),
+        });
+        // Inputs from the project next to the shader, if there is one
+        if let Ok(inputs) = Project::open(path).and_then(|p| Inputs::open(&p)) {
+            frag.declare(&inputs.layout());
+        }
+        frag
+    }
+
+    // The shader a file is part of: an open one including it, or else the file alone
+    fn context(&self, path: &Path) -> FragShader {
+        self.docs
+            .keys()
+            .filter(|p| p.as_path() != path)
+            .map(|p| self.compose(p))
+            .find(|frag| frag.files.iter().any(|f| f.path == path))
+            .unwrap_or_else(|| self.compose(path))
+    }
+
+    fn text(&self, path: &Path) -> String {
+        match self.docs.get(path) {
+            Some(text) => text.clone(),
+            None => fs::read_to_string(path).unwrap_or_default(),
+        }
+    }
+
+    // Rechecks every open shader. Files only open because another open shader includes them are
+    // checked as part of that shader, where their params and prelude make sense.
+    fn publish_diagnostics(&mut self) -> io::Result<()> {
+        let frags: Vec<FragShader> = self.docs.keys().map(|p| self.compose(p)).collect();
+        let included: HashSet<&PathBuf> = frags
+            .iter()
+            .flat_map(|frag| &frag.files[1..])
+            .map(|f| &f.path)
+            .collect();
+
+        let mut by_file: HashMap<Url, Vec<lsp_types::Diagnostic>> = HashMap::new();
+        for url in self.published.drain() {
+            by_file.insert(url, vec![]);
+        }
+        for frag in frags
+            .iter()
+            .filter(|f| !included.contains(&f.files[0].path))
+        {
+            let main = &frag.files[0].path;
+            let require_main = frag.source.contains("@fragment");
+            for d in validate::check_shader(main, frag, require_main) {
+                let (path, diagnostic) = to_lsp(frag, &d);
+                let Ok(url) = Url::from_file_path(path) else {
+                    continue;
+                };
+                let diagnostics = by_file.entry(url).or_default();
+                if !diagnostics.contains(&diagnostic) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+
+        for (url, diagnostics) in by_file {
+            if !diagnostics.is_empty() {
+                self.published.insert(url.clone());
+            }
+            let params = PublishDiagnosticsParams::new(url, diagnostics, None);
+            let notification = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+            self.send(Message::Notification(notification))?;
+        }
+        Ok(())
+    }
+
+    // The shader, file text and byte offset a request points at
+    fn locate(&self, position: &TextDocumentPositionParams) -> Option<(FragShader, String, usize)> {
+        let path = doc_path(&position.text_document.uri)?;
+        let text = self.text(&path);
+        let offset = offset(&text, position.position);
+        Some((self.context(&path), text, offset))
+    }
+
+    fn hover(&self, p: HoverParams) -> Option<Hover> {
+        let (frag, text, offset) = self.locate(&p.text_document_position_params)?;
+        let (start, word) = word_at(&text, offset)?;
+
+        let value = if text[..start].ends_with("params.") {
+            let d = frag.param_decls.iter().find(|d| d.name == word)?;
+            format!(
+                "```wgsl\nparams.{}: f32\n```\nDefaults to {}, ranging from {} to {}.",
+                d.name, d.default, d.min, d.max
+            )
+        } else if let Some(item) = PRELUDE.iter().find(|item| item.name == word) {
+            format!("```wgsl\n{}\n```\n{}", item.wgsl, item.doc)
+        } else {
+            let module = naga::front::wgsl::parse_str(&frag.wgsl).ok()?;
+            format!("```wgsl\n{}\n```", declaration(&module, word)?)
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        })
+    }
+
+    fn definition(&self, p: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let (frag, text, offset) = self.locate(&p.text_document_position_params)?;
+        let (start, word) = word_at(&text, offset)?;
+
+        let (file, offset) = if text[..start].ends_with("params.") {
+            frag.files.iter().enumerate().find_map(|(i, f)| {
+                let line = params::decl_line(&f.text, word)?;
+                let line_start = f.text.split_inclusive('\n').take(line).map(str::len).sum();
+                Some((i, line_start))
+            })?
+        } else {
+            // Prelude names have nowhere to go, they're generated
+            let module = naga::front::wgsl::parse_str(&frag.wgsl).ok()?;
+            let range = declaration_span(&module, word)?.to_range()?;
+            let location = frag.location(range.start)?;
+            (location.file, location.offset)
+        };
+
+        let file = &frag.files[file];
+        let position = position(&file.text, offset);
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri: Url::from_file_path(&file.path).ok()?,
+            range: Range::new(position, position),
+        }))
+    }
+
+    fn completion(&self, p: CompletionParams) -> Option<CompletionResponse> {
+        let (frag, text, offset) = self.locate(&p.text_document_position)?;
+        let start = text[..offset]
+            .rfind(|c: char| !is_ident(c))
+            .map_or(0, |i| i + 1);
+
+        let item = |label: &str, kind, detail: String| CompletionItem {
+            label: label.to_owned(),
+            kind: Some(kind),
+            detail: Some(detail),
+            ..Default::default()
+        };
+
+        if text[..start].ends_with("params.") {
+            let items = frag
+                .param_decls
+                .iter()
+                .map(|d| {
+                    let detail = format!("f32 = {} [{}, {}]", d.default, d.min, d.max);
+                    item(&d.name, CompletionItemKind::FIELD, detail)
+                })
+                .collect();
+            return Some(CompletionResponse::Array(items));
+        }
+
+        let mut items: Vec<CompletionItem> = PRELUDE
+            .iter()
+            .map(|prelude| {
+                let kind = match prelude.wgsl.starts_with("fn ") {
+                    true => CompletionItemKind::FUNCTION,
+                    false => CompletionItemKind::VARIABLE,
+                };
+                CompletionItem {
+                    documentation: Some(Documentation::String(prelude.doc.to_owned())),
+                    ..item(prelude.name, kind, prelude.wgsl.to_owned())
+                }
+            })
+            .collect();
+
+        // The rest of the module is only there if it currently parses
+        if let Ok(module) = naga::front::wgsl::parse_str(&frag.wgsl) {
+            let mut names = vec![];
+            for (_, global) in module.global_variables.iter() {
+                names.push((&global.name, CompletionItemKind::VARIABLE));
+            }
+            for (_, constant) in module.constants.iter() {
+                names.push((&constant.name, CompletionItemKind::CONSTANT));
+            }
+            for (_, function) in module.functions.iter() {
+                names.push((&function.name, CompletionItemKind::FUNCTION));
+            }
+            for (_, ty) in module.types.iter() {
+                if let TypeInner::Struct { .. } = ty.inner {
+                    names.push((&ty.name, CompletionItemKind::STRUCT));
+                }
+            }
+            for (name, kind) in names {
+                let Some(name) = name else {
+                    continue;
+                };
+                if items.iter().any(|i| i.label == *name) {
+                    continue;
+                }
+                let detail = declaration(&module, name).unwrap_or_default();
+                items.push(item(name, kind, detail));
+            }
+        }
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+fn doc_path(uri: &Url) -> Option<PathBuf> {
+    uri.to_file_path().ok().map(|p| shader::canonical(&p))
+}
+
+fn to_lsp(frag: &FragShader, d: &validate::Diagnostic) -> (PathBuf, lsp_types::Diagnostic) {
+    let range_of = |label: &validate::Label| {
+        let file = frag.files.iter().find(|f| f.path == label.file);
+        let text = file.map_or("", |f| f.text.as_str());
+        label_range(text, label)
+    };
+
+    let mut message = d.message.clone();
+    let mut range = Range::default();
+    if let Some(label) = d.labels.first() {
+        range = range_of(label);
+        if !label.message.is_empty() {
+            message += &format!("\n{}", label.message);
+        }
+    }
+    let related: Vec<DiagnosticRelatedInformation> = d
+        .labels
+        .iter()
+        .skip(1)
+        .filter_map(|label| {
+            Some(DiagnosticRelatedInformation {
+                location: Location {
+                    uri: Url::from_file_path(&label.file).ok()?,
+                    range: range_of(label),
+                },
+                message: label.message.clone(),
+            })
+        })
+        .collect();
+
+    let severity = match d.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+    };
+    let diagnostic = lsp_types::Diagnostic {
+        range,
+        severity: Some(severity),
+        source: Some("wgsl_workbench".to_owned()),
+        message,
+        related_information: (!related.is_empty()).then_some(related),
+        ..Default::default()
+    };
+    (d.file.clone(), diagnostic)
+}
+
+fn label_range(text: &str, label: &validate::Label) -> Range {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(label.line as usize - 1)
+        .map(str::len)
+        .sum();
+    let start = text[line_start..]
+        .char_indices()
+        .nth(label.column as usize - 1)
+        .map_or(text.len(), |(i, _)| line_start + i);
+    let mut end = (start + label.length as usize).min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Range::new(position(text, start), position(text, end))
+}
+
+// LSP columns count UTF-16 code units
+fn offset(text: &str, position: Position) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].lines().next().unwrap_or("");
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn position(text: &str, offset: usize) -> Position {
+    let prefix = &text[..offset.min(text.len())];
+    let line_start = prefix.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        prefix.matches('\n').count() as u32,
+        prefix[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The identifier around a byte offset, and where it starts
+fn word_at(text: &str, offset: usize) -> Option<(usize, &str)> {
+    let start = text[..offset]
+        .rfind(|c: char| !is_ident(c))
+        .map_or(0, |i| i + 1);
+    let end = text[offset..]
+        .find(|c: char| !is_ident(c))
+        .map_or(text.len(), |i| offset + i);
+    let word = &text[start..end];
+    (!word.is_empty()).then_some((start, word))
+}
+
+// A one-line declaration of a module-scope name, roughly as it would be written in WGSL
+fn declaration(module: &Module, name: &str) -> Option<String> {
+    let named = |n: &Option<String>| n.as_deref() == Some(name);
+
+    if let Some((_, global)) = module.global_variables.iter().find(|(_, g)| named(&g.name)) {
+        let space = match global.space {
+            AddressSpace::Uniform => "<uniform>",
+            AddressSpace::Storage { .. } => "<storage>",
+            AddressSpace::Private => "<private>",
+            AddressSpace::WorkGroup => "<workgroup>",
+            AddressSpace::PushConstant => "<push_constant>",
+            AddressSpace::Handle | AddressSpace::Function => "",
+        };
+        let ty = type_name(module, global.ty);
+        return Some(format!("var{space} {name}: {ty}"));
+    }
+    if let Some((_, constant)) = module.constants.iter().find(|(_, c)| named(&c.name)) {
+        return Some(format!("const {name}: {}", type_name(module, constant.ty)));
+    }
+    if let Some((_, function)) = module.functions.iter().find(|(_, f)| named(&f.name)) {
+        let arguments: Vec<String> = function
+            .arguments
+            .iter()
+            .map(|a| {
+                let ty = type_name(module, a.ty);
+                format!("{}: {ty}", a.name.as_deref().unwrap_or("_"))
+            })
+            .collect();
+        let result = function
+            .result
+            .as_ref()
+            .map(|r| format!(" -> {}", type_name(module, r.ty)))
+            .unwrap_or_default();
+        return Some(format!("fn {name}({}){result}", arguments.join(", ")));
+    }
+    module
+        .types
+        .iter()
+        .find(|(_, ty)| named(&ty.name) && matches!(ty.inner, TypeInner::Struct { .. }))
+        .map(|_| format!("struct {name}"))
+}
+
+fn declaration_span(module: &Module, name: &str) -> Option<Span> {
+    let named = |n: &Option<String>| n.as_deref() == Some(name);
+    let span = if let Some((h, _)) = module.global_variables.iter().find(|(_, g)| named(&g.name)) {
+        module.global_variables.get_span(h)
+    } else if let Some((h, _)) = module.constants.iter().find(|(_, c)| named(&c.name)) {
+        module.constants.get_span(h)
+    } else if let Some((h, _)) = module.functions.iter().find(|(_, f)| named(&f.name)) {
+        module.functions.get_span(h)
+    } else {
+        let (h, _) = module.types.iter().find(|(_, ty)| named(&ty.name))?;
+        module.types.get_span(h)
+    };
+    Some(span)
+}
+
+fn type_name(module: &Module, ty: Handle<Type>) -> String {
+    let ty = &module.types[ty];
+    if let Some(name) = &ty.name {
+        return name.clone();
+    }
+    let scalar = |kind, width| match (kind, width) {
+        (ScalarKind::Float, 2) => "f16",
+        (ScalarKind::Float, 8) => "f64",
+        (ScalarKind::Float, _) => "f32",
+        (ScalarKind::Sint, _) => "i32",
+        (ScalarKind::Uint, _) => "u32",
+        (ScalarKind::Bool, _) => "bool",
+    };
+    match ty.inner {
+        TypeInner::Scalar { kind, width } => scalar(kind, width).to_owned(),
+        TypeInner::Vector { size, kind, width } => {
+            format!("vec{}<{}>", size as u8, scalar(kind, width))
+        }
+        TypeInner::Matrix {
+            columns,
+            rows,
+            width,
+        } => format!(
+            "mat{}x{}<{}>",
+            columns as u8,
+            rows as u8,
+            scalar(ScalarKind::Float, width)
+        ),
+        TypeInner::Atomic { kind, width } => format!("atomic<{}>", scalar(kind, width)),
+        TypeInner::Array { base, size, .. } => match size {
+            ArraySize::Constant(n) => format!("array<{}, {n}>", type_name(module, base)),
+            ArraySize::Dynamic => format!("array<{}>", type_name(module, base)),
+        },
+        TypeInner::Image { dim, .. } => format!("texture_{dim:?}").to_lowercase(),
+        TypeInner::Sampler { comparison: false } => "sampler".to_owned(),
+        TypeInner::Sampler { comparison: true } => "sampler_comparison".to_owned(),
+        _ => format!("{:?}", ty.inner),
+    }
+}
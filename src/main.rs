@@ -1,5 +1,14 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::ExitCode;
+
+#[cfg(not(target_arch = "wasm32"))]
 use wgsl_workbench::run;
 
-fn main() {
-    pollster::block_on(run());
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> ExitCode {
+    pollster::block_on(run())
 }
+
+// The browser build starts from the library's wasm-bindgen exports instead
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,527 @@
+// MIDI controllers: CC, note and clock messages from an input port. `[midi]` in the project maps
+// controls to params, learned by clicking a slider in the param panel and moving a knob, and the
+// `midi` input source binds notes, controllers and the clock for shaders to read directly.
+// Without the `midi` feature ports fail to open, but projects keep their mappings.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, io, sync::mpsc::Receiver};
+
+use crate::input::{Binding, InputSource, Upload};
+
+// Clock messages per quarter note
+const TICKS_PER_BEAT: f32 = 24.0;
+
+// `[midi]` in the project file. Mapping only happens when the section is there.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MidiSettings {
+    // Part of the input port's name, the first port if missing
+    pub port: Option<String>,
+    // By param name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub map: BTreeMap<String, MidiControl>,
+}
+
+// A knob, fader or key, written `{ cc = 21, channel = 1 }` or `{ note = 60 }`. Without a
+// channel, any channel.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MidiControl {
+    Cc {
+        cc: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<u8>,
+    },
+    Note {
+        note: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<u8>,
+    },
+}
+
+// Channels are 0-15 on the wire, 1-16 everywhere people see them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMessage {
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    NoteOff { channel: u8, note: u8 },
+    Cc { channel: u8, cc: u8, value: u8 },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MidiMessage {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = status & 0x0f;
+        Some(match (status & 0xf0, data) {
+            // Note on with no velocity is how most keyboards send note off
+            (0x90, &[note, 0]) | (0x80, &[note, _]) => Self::NoteOff { channel, note },
+            (0x90, &[note, velocity]) => Self::NoteOn {
+                channel,
+                note,
+                velocity,
+            },
+            (0xb0, &[cc, value]) => Self::Cc { channel, cc, value },
+            _ => match status {
+                0xf8 => Self::Clock,
+                0xfa => Self::Start,
+                0xfb => Self::Continue,
+                0xfc => Self::Stop,
+                _ => return None,
+            },
+        })
+    }
+}
+
+fn on_channel(wanted: Option<u8>, channel: u8) -> bool {
+    match wanted {
+        Some(wanted) => wanted == channel + 1,
+        None => true,
+    }
+}
+
+impl MidiControl {
+    // The control that sent a message, for MIDI learn
+    pub fn learn(message: &MidiMessage) -> Option<Self> {
+        match *message {
+            MidiMessage::Cc { channel, cc, .. } => Some(Self::Cc {
+                cc,
+                channel: Some(channel + 1),
+            }),
+            MidiMessage::NoteOn { channel, note, .. } => Some(Self::Note {
+                note,
+                channel: Some(channel + 1),
+            }),
+            _ => None,
+        }
+    }
+
+    // Where a message puts this control, from 0 to 1. Notes are their velocity while held.
+    pub fn value(&self, message: &MidiMessage) -> Option<f32> {
+        match (*self, *message) {
+            (
+                Self::Cc { cc, channel },
+                MidiMessage::Cc {
+                    channel: c,
+                    cc: n,
+                    value,
+                },
+            ) if n == cc && on_channel(channel, c) => Some(value as f32 / 127.0),
+            (
+                Self::Note { note, channel },
+                MidiMessage::NoteOn {
+                    channel: c,
+                    note: n,
+                    velocity,
+                },
+            ) if n == note && on_channel(channel, c) => Some(velocity as f32 / 127.0),
+            (
+                Self::Note { note, channel },
+                MidiMessage::NoteOff {
+                    channel: c,
+                    note: n,
+                },
+            ) if n == note && on_channel(channel, c) => Some(0.0),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MidiControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let channel = match self {
+            Self::Cc { cc, channel } => {
+                write!(f, "CC {cc}")?;
+                channel
+            }
+            Self::Note { note, channel } => {
+                write!(f, "note {note}")?;
+                channel
+            }
+        };
+        match channel {
+            Some(channel) => write!(f, " ch {channel}"),
+            None => Ok(()),
+        }
+    }
+}
+
+// An open input port. Messages arrive on midir's thread and wait in a channel until drained.
+pub struct MidiPort {
+    // With their timestamps, in microseconds
+    messages: Receiver<(u64, Vec<u8>)>,
+    #[cfg(all(feature = "midi", not(target_arch = "wasm32")))]
+    _connection: midir::MidiInputConnection<()>,
+}
+
+impl MidiPort {
+    // The first port whose name contains `name`, or the first port
+    #[cfg(all(feature = "midi", not(target_arch = "wasm32")))]
+    pub fn open(name: Option<&str>) -> io::Result<Self> {
+        use midir::MidiInput;
+        use std::sync::mpsc;
+
+        let input = MidiInput::new("wgsl_workbench").map_err(io::Error::other)?;
+        let ports = input.ports();
+        let port = ports.iter().find(|p| match name {
+            Some(name) => input.port_name(p).is_ok_and(|n| n.contains(name)),
+            None => true,
+        });
+        let Some(port) = port else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                match name {
+                    Some(name) => format!("no MIDI input matching `{name}`"),
+                    None => "no MIDI inputs".to_owned(),
+                },
+            ));
+        };
+
+        let (sender, messages) = mpsc::channel();
+        let connection = input
+            .connect(
+                port,
+                "wgsl_workbench",
+                move |stamp, bytes, _| {
+                    let _ = sender.send((stamp, bytes.to_vec()));
+                },
+                (),
+            )
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Self {
+            messages,
+            _connection: connection,
+        })
+    }
+
+    #[cfg(not(all(feature = "midi", not(target_arch = "wasm32"))))]
+    pub fn open(_name: Option<&str>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "built without MIDI support",
+        ))
+    }
+
+    // Everything received since the last call, skipping messages we have no use for
+    pub fn messages(&self) -> impl Iterator<Item = (u64, MidiMessage)> + '_ {
+        self.messages
+            .try_iter()
+            .filter_map(|(stamp, bytes)| Some((stamp, MidiMessage::parse(&bytes)?)))
+    }
+}
+
+// The `midi` input source, on the port named by `port`. Binds the velocity of every held note
+// as `<name>`, the last value of every controller as `<name>Cc`, both from 0 to 1, and the
+// clock as `<name>Clock`: BPM, beats since start, and 1 while running.
+pub struct MidiSource {
+    name: Option<String>,
+    port: Option<MidiPort>,
+    failed: bool,
+    notes: Vec<f32>,
+    controllers: Vec<f32>,
+    clock: [f32; 4],
+    last_tick: Option<u64>,
+    tick_seconds: f32,
+    ticks: u32,
+}
+
+impl MidiSource {
+    pub fn new(name: Option<String>) -> Self {
+        Self {
+            name,
+            port: None,
+            failed: false,
+            notes: vec![0.0; 128],
+            controllers: vec![0.0; 128],
+            clock: [0.0; 4],
+            last_tick: None,
+            tick_seconds: 0.0,
+            ticks: 0,
+        }
+    }
+
+    fn tick(&mut self, stamp: u64) {
+        if let Some(last) = self.last_tick {
+            let seconds = stamp.saturating_sub(last) as f32 / 1e6;
+            // Smoothed, clocks jitter. A long gap means the clock stopped and started again.
+            if seconds > 0.0 && seconds < 0.5 {
+                self.tick_seconds = match self.tick_seconds {
+                    t if t > 0.0 => t * 0.9 + seconds * 0.1,
+                    _ => seconds,
+                };
+            }
+        }
+        self.last_tick = Some(stamp);
+        if self.clock[2] > 0.0 {
+            self.ticks += 1;
+        }
+    }
+
+    // Messages in arrival order, with their timestamps in microseconds
+    fn receive(&mut self, messages: Vec<(u64, MidiMessage)>) {
+        for (stamp, message) in messages {
+            match message {
+                MidiMessage::NoteOn { note, velocity, .. } => {
+                    self.notes[note as usize & 127] = velocity as f32 / 127.0
+                }
+                MidiMessage::NoteOff { note, .. } => self.notes[note as usize & 127] = 0.0,
+                MidiMessage::Cc { cc, value, .. } => {
+                    self.controllers[cc as usize & 127] = value as f32 / 127.0
+                }
+                MidiMessage::Clock => self.tick(stamp),
+                MidiMessage::Start => {
+                    self.ticks = 0;
+                    self.clock[2] = 1.0;
+                }
+                MidiMessage::Continue => self.clock[2] = 1.0,
+                MidiMessage::Stop => self.clock[2] = 0.0,
+            }
+        }
+
+        if self.tick_seconds > 0.0 {
+            self.clock[0] = 60.0 / (self.tick_seconds * TICKS_PER_BEAT);
+        }
+        self.clock[1] = self.ticks as f32 / TICKS_PER_BEAT;
+    }
+}
+
+impl InputSource for MidiSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![
+            Binding::uniform("", 128),
+            Binding::uniform("Cc", 128),
+            Binding::uniform("Clock", 4),
+        ]
+    }
+
+    fn poll(&mut self) {
+        if self.port.is_none() && !self.failed {
+            match MidiPort::open(self.name.as_deref()) {
+                Ok(port) => self.port = Some(port),
+                Err(e) => {
+                    println!("Failed to open MIDI input: {e}");
+                    self.failed = true;
+                }
+            }
+        }
+        let messages: Vec<_> = match &self.port {
+            Some(port) => port.messages().collect(),
+            None => return,
+        };
+        self.receive(messages);
+    }
+
+    fn upload(&mut self, index: usize) -> Option<Upload<'_>> {
+        match index {
+            0 => Some(Upload::Uniform(&self.notes)),
+            1 => Some(Upload::Uniform(&self.controllers)),
+            _ => Some(Upload::Uniform(&self.clock)),
+        }
+    }
+}
+
+#[cfg(all(feature = "midi", not(target_arch = "wasm32")))]
+pub fn list_input_ports() {
+    use midir::MidiInput;
+
+    println!("MIDI inputs:");
+    match MidiInput::new("wgsl_workbench") {
+        Ok(input) => {
+            for (i, port) in input.ports().iter().enumerate() {
+                let name = input.port_name(port).unwrap_or_else(|e| format!("<{e}>"));
+                println!("  {i}: {name}");
+            }
+        }
+        Err(e) => println!("  Failed to query MIDI inputs: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::{ParamDecl, Params};
+
+    #[test]
+    fn parses_messages() {
+        assert_eq!(
+            MidiMessage::parse(&[0xb2, 21, 64]),
+            Some(MidiMessage::Cc {
+                channel: 2,
+                cc: 21,
+                value: 64
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x90, 60, 100]),
+            Some(MidiMessage::NoteOn {
+                channel: 0,
+                note: 60,
+                velocity: 100
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x8f, 60, 40]),
+            Some(MidiMessage::NoteOff {
+                channel: 15,
+                note: 60
+            })
+        );
+        assert_eq!(MidiMessage::parse(&[0xf8]), Some(MidiMessage::Clock));
+        assert_eq!(MidiMessage::parse(&[0xfa]), Some(MidiMessage::Start));
+        assert_eq!(MidiMessage::parse(&[0xfc]), Some(MidiMessage::Stop));
+        // Pitch bend, a truncated CC and nothing at all
+        assert_eq!(MidiMessage::parse(&[0xe0, 0, 64]), None);
+        assert_eq!(MidiMessage::parse(&[0xb0, 21]), None);
+        assert_eq!(MidiMessage::parse(&[]), None);
+    }
+
+    #[test]
+    fn note_on_without_velocity_is_note_off() {
+        assert_eq!(
+            MidiMessage::parse(&[0x93, 60, 0]),
+            Some(MidiMessage::NoteOff {
+                channel: 3,
+                note: 60
+            })
+        );
+    }
+
+    #[test]
+    fn learns_the_control_that_moved() {
+        let cc = MidiMessage::parse(&[0xb0, 21, 10]).unwrap();
+        assert_eq!(
+            MidiControl::learn(&cc),
+            Some(MidiControl::Cc {
+                cc: 21,
+                channel: Some(1)
+            })
+        );
+        let note = MidiMessage::parse(&[0x95, 36, 90]).unwrap();
+        assert_eq!(
+            MidiControl::learn(&note),
+            Some(MidiControl::Note {
+                note: 36,
+                channel: Some(6)
+            })
+        );
+        // Releasing a key doesn't map it
+        let off = MidiMessage::parse(&[0x85, 36, 0]).unwrap();
+        assert_eq!(MidiControl::learn(&off), None);
+        assert_eq!(MidiControl::learn(&MidiMessage::Clock), None);
+    }
+
+    #[test]
+    fn values_scale_into_the_param_range() {
+        let mut params = Params::default();
+        params.sync(&[ParamDecl {
+            name: "speed".to_owned(),
+            default: 1.0,
+            min: -2.0,
+            max: 8.0,
+        }]);
+
+        let knob = MidiControl::Cc {
+            cc: 21,
+            channel: Some(1),
+        };
+        for (value, speed) in [(0, -2.0), (127, 8.0)] {
+            let message = MidiMessage::parse(&[0xb0, 21, value]).unwrap();
+            params.set_normalized("speed", knob.value(&message).unwrap());
+            assert_eq!(params.get("speed"), Some(speed));
+        }
+        // Another channel or controller
+        assert_eq!(
+            knob.value(&MidiMessage::parse(&[0xb1, 21, 64]).unwrap()),
+            None
+        );
+        assert_eq!(
+            knob.value(&MidiMessage::parse(&[0xb0, 22, 64]).unwrap()),
+            None
+        );
+
+        let any_channel = MidiControl::Cc {
+            cc: 21,
+            channel: None,
+        };
+        let message = MidiMessage::parse(&[0xbf, 21, 127]).unwrap();
+        assert_eq!(any_channel.value(&message), Some(1.0));
+
+        let key = MidiControl::Note {
+            note: 60,
+            channel: None,
+        };
+        let on = MidiMessage::parse(&[0x90, 60, 127]).unwrap();
+        let off = MidiMessage::parse(&[0x90, 60, 0]).unwrap();
+        assert_eq!(key.value(&on), Some(1.0));
+        assert_eq!(key.value(&off), Some(0.0));
+    }
+
+    #[test]
+    fn holds_the_velocity_of_every_note() {
+        let mut source = MidiSource::new(None);
+        source.receive(vec![
+            (0, MidiMessage::parse(&[0x90, 60, 127]).unwrap()),
+            (0, MidiMessage::parse(&[0x91, 64, 0]).unwrap()),
+            (0, MidiMessage::parse(&[0x92, 67, 127]).unwrap()),
+            (0, MidiMessage::parse(&[0x82, 67, 64]).unwrap()),
+            (0, MidiMessage::parse(&[0xb0, 1, 127]).unwrap()),
+        ]);
+        assert_eq!(source.notes.len(), 128);
+        assert_eq!(source.notes[60], 1.0);
+        assert_eq!(source.notes[64], 0.0);
+        assert_eq!(source.notes[67], 0.0);
+        assert_eq!(source.controllers[1], 1.0);
+    }
+
+    #[test]
+    fn bpm_from_clock_ticks() {
+        let mut source = MidiSource::new(None);
+        // 120 BPM is 24 ticks every half second
+        let tick = 500_000 / 24;
+        let mut messages = vec![(0, MidiMessage::Start)];
+        messages.extend((0..48).map(|i| (i * tick, MidiMessage::Clock)));
+        source.receive(messages);
+        assert!((source.clock[0] - 120.0).abs() < 0.1, "{}", source.clock[0]);
+        assert_eq!(source.clock[1], 2.0);
+        assert_eq!(source.clock[2], 1.0);
+
+        // Stopped, the tempo holds and beats stop counting
+        source.receive(vec![
+            (48 * tick, MidiMessage::Stop),
+            (49 * tick, MidiMessage::Clock),
+        ]);
+        assert_eq!(source.clock[1], 2.0);
+        assert_eq!(source.clock[2], 0.0);
+    }
+
+    #[test]
+    fn maps_round_trip_through_toml() {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Project {
+            midi: MidiSettings,
+        }
+
+        let toml = "[midi]\nport = \"nano\"\n\n[midi.map]\n\
+                    gain = { note = 60 }\nspeed = { cc = 21, channel = 2 }\n";
+        let project: Project = toml::from_str(toml).unwrap();
+        assert_eq!(project.midi.port.as_deref(), Some("nano"));
+        assert_eq!(
+            project.midi.map["speed"],
+            MidiControl::Cc {
+                cc: 21,
+                channel: Some(2)
+            }
+        );
+        assert_eq!(
+            project.midi.map["gain"],
+            MidiControl::Note {
+                note: 60,
+                channel: None
+            }
+        );
+        let saved: Project = toml::from_str(&toml::to_string(&project).unwrap()).unwrap();
+        assert_eq!(saved.midi.port, project.midi.port);
+        assert_eq!(saved.midi.map, project.midi.map);
+    }
+}
@@ -0,0 +1,261 @@
+// Rendering without a window, for the render, export and replay subcommands. Time advances a
+// fixed step per frame instead of following the wall clock, so the same command gives the same
+// frames.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    capture,
+    cli::{ExportArgs, GpuArgs, RenderArgs},
+    gpu,
+    input::Inputs,
+    project::Project,
+    renderer::Renderer,
+    shader,
+    timing::{self, FrameTimer, Sample},
+};
+use winit::event::WindowEvent;
+
+const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+pub struct Offline {
+    pub renderer: Renderer,
+    pub project: Project,
+    inputs: Inputs,
+    timer: FrameTimer,
+    // Every frame's, for the summary
+    timings: Vec<Sample>,
+}
+
+impl Offline {
+    pub async fn open(path: &Path, gpu: &GpuArgs, size: Option<(u32, u32)>) -> io::Result<Self> {
+        let project = Project::open(path)?;
+        let shader_path = project.shader_path();
+        if !shader_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Could not find file: {}", shader_path.display()),
+            ));
+        }
+
+        let size = size
+            .or(project.resolution.fixed.map(|[w, h]| (w, h)))
+            .unwrap_or(DEFAULT_SIZE);
+        let (device, queue) = gpu::headless(gpu).await?;
+        // No camera offline, videoBuffer is a single black pixel. Project inputs still run.
+        let inputs = Inputs::open(&project)?;
+        let timer = FrameTimer::new(&device, &queue);
+        let renderer = Renderer::new(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            size,
+            &inputs.layout(),
+            &shader_path,
+            shader::read_frag_shader(&shader_path).ok(),
+        );
+
+        // The pipeline falls back to the default shader rather than failing, which would
+        // silently render the wrong thing here
+        if renderer.rpcontext.read().frag_source.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} failed to compile", shader_path.display()),
+            ));
+        }
+
+        Ok(Self {
+            renderer,
+            project,
+            inputs,
+            timer,
+            timings: vec![],
+        })
+    }
+
+    // Render the frame at shader time `time`, feeding back into the next, and read it back
+    pub fn frame(&mut self, time: f32) -> io::Result<Vec<u8>> {
+        self.renderer.time = time;
+        self.inputs.update(&mut self.renderer);
+        let animated = self.project.timeline.sample(time);
+        self.renderer.params.apply(&animated);
+
+        let mut encoder = self
+            .renderer
+            .rpcontext
+            .read()
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offline Encoder"),
+            });
+        self.timer.begin(&mut encoder);
+        self.renderer.render(&mut encoder);
+        self.timer.mark(&mut encoder, "Shader");
+        self.timer.resolve(&mut encoder);
+        self.timer.submit();
+        self.renderer.queue.submit([encoder.finish()]);
+        let rgba = self.renderer.read_target()?;
+
+        let device = self.renderer.rpcontext.read().device.clone();
+        self.timings.extend(self.timer.collect(&device, true));
+        Ok(rgba)
+    }
+
+    // Feed the project's inputs a window event, for replayed sessions
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        self.inputs.window_event(event);
+    }
+
+    // How long the frames took, a line per pass
+    pub fn print_timings(&self) {
+        let Some(first) = self.timings.first() else {
+            return;
+        };
+        match first.gpu {
+            true => println!("GPU time over {} frames:", self.timings.len()),
+            false => println!(
+                "CPU time from submit to readback over {} frames, timestamp queries aren't supported:",
+                self.timings.len()
+            ),
+        }
+        for (pass, mean, max) in timing::summarize(&self.timings) {
+            println!("  {pass}: {mean:.3} ms mean, {max:.3} ms worst");
+        }
+    }
+}
+
+pub async fn render(args: &RenderArgs) -> io::Result<()> {
+    let mut offline = Offline::open(&args.path, &args.gpu, args.size).await?;
+    let (width, height) = offline.renderer.size();
+    fs::create_dir_all(&args.out)?;
+
+    for i in 0..args.frames {
+        let time = args.start + i as f32 / args.fps;
+        let rgba = offline.frame(time)?;
+        let text = offline.renderer.capture_metadata((width, height));
+        let path = args.out.join(format!("frame-{i:04}.png"));
+        capture::write_png(&path, width, height, &rgba, &text)?;
+        println!("Rendered {}", path.display());
+    }
+    offline.print_timings();
+    Ok(())
+}
+
+pub async fn export(args: &ExportArgs) -> io::Result<()> {
+    let mut offline = Offline::open(&args.path, &args.gpu, args.size).await?;
+    let (width, height) = offline.renderer.size();
+    let duration = args.duration.unwrap_or(offline.project.timeline.length);
+    let frames = ((duration * args.fps).round() as u32).max(1);
+
+    let mut sink = sink(&args.out, (width, height), args.fps)?;
+
+    for i in 0..frames {
+        let mut rgba = offline.frame(args.start + i as f32 / args.fps)?;
+        sink.write(&mut rgba)?;
+        println!("Export: {}/{frames} frames", i + 1);
+    }
+    sink.finish()?;
+    println!("Exported {}", args.out.display());
+    offline.print_timings();
+    Ok(())
+}
+
+// A .gif is encoded directly, anything else is piped to ffmpeg
+pub fn sink(path: &Path, size: (u32, u32), fps: f32) -> io::Result<Box<dyn FrameSink>> {
+    Ok(if path.extension().is_some_and(|e| e == "gif") {
+        Box::new(GifSink::new(path, size, fps)?)
+    } else {
+        Box::new(FfmpegSink::new(path, size, fps)?)
+    })
+}
+
+pub trait FrameSink {
+    fn write(&mut self, rgba: &mut [u8]) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+struct GifSink {
+    encoder: gif::Encoder<BufWriter<File>>,
+    size: (u16, u16),
+    // In hundredths of a second, the only unit GIF has
+    delay: u16,
+}
+
+impl GifSink {
+    fn new(path: &Path, size: (u32, u32), fps: f32) -> io::Result<Self> {
+        let too_big = || io::Error::new(io::ErrorKind::InvalidInput, "Too big for a GIF");
+        let size = (
+            u16::try_from(size.0).map_err(|_| too_big())?,
+            u16::try_from(size.1).map_err(|_| too_big())?,
+        );
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, size.0, size.1, &[]).map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            encoder,
+            size,
+            delay: (100.0 / fps).round().max(1.0) as u16,
+        })
+    }
+}
+
+impl FrameSink for GifSink {
+    fn write(&mut self, rgba: &mut [u8]) -> io::Result<()> {
+        let mut frame = gif::Frame::from_rgba_speed(self.size.0, self.size.1, rgba, 10);
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.encoder.into_inner().map_err(io::Error::other)?.flush()
+    }
+}
+
+// Raw frames piped to ffmpeg, which picks the codec from the output's extension
+struct FfmpegSink {
+    child: std::process::Child,
+}
+
+impl FfmpegSink {
+    fn new(path: &Path, size: (u32, u32), fps: f32) -> io::Result<Self> {
+        let child = Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgba",
+            ])
+            .args(["-s", &format!("{}x{}", size.0, size.1)])
+            .args(["-r", &fps.to_string(), "-i", "-"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("Couldn't start ffmpeg: {e}")))?;
+        Ok(Self { child })
+    }
+}
+
+impl FrameSink for FfmpegSink {
+    fn write(&mut self, rgba: &mut [u8]) -> io::Result<()> {
+        self.child.stdin.as_mut().unwrap().write_all(rgba)
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        drop(self.child.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg exited with {status}")));
+        }
+        Ok(())
+    }
+}
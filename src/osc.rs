@@ -0,0 +1,555 @@
+// Remote control over OSC, from TouchOSC, DAWs or anything else that speaks it over UDP. The
+// listener runs on its own thread and only decodes: commands go over a channel and are applied
+// by `App::update` on the event loop, so nothing outside it ever takes the App's lock.
+//
+//     /param/<name> f            set a param
+//     /param/<name>/normalized f set a param from 0-1, scaled to its declared range
+//     /preset/<n>                morph to a preset
+//     /preset/<n>/snap           recall it instantly
+//     /preset/<n>/save           store the current params in it
+//     /time/pause, /time/play, /time/toggle
+//     /time/set f                jump to a time, in seconds
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryIter},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+// Larger than any UDP payload
+const MAX_PACKET: usize = 65536;
+// How often the listener checks whether the server was dropped
+const POLL: Duration = Duration::from_millis(100);
+
+fn default_bind() -> String {
+    "0.0.0.0".to_owned()
+}
+
+fn default_port() -> u16 {
+    9000
+}
+
+// `[osc]` in the project file. The listener only runs when the section is there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OscSettings {
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    // Incoming addresses to treat as others, e.g. "/1/fader1" = "/param/speed/normalized"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub map: BTreeMap<String, String>,
+}
+
+/// What an OSC message asks the workbench to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscCommand {
+    SetParam {
+        name: String,
+        value: f32,
+    },
+    /// A value from 0 to 1, scaled to the param's declared range.
+    SetParamNormalized {
+        name: String,
+        value: f32,
+    },
+    RecallPreset {
+        slot: u8,
+        morph: bool,
+    },
+    SavePreset {
+        slot: u8,
+    },
+    Pause,
+    Play,
+    TogglePause,
+    SetTime(f32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Arg {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Str(String),
+    // Types we skip over but don't use: blobs, nil, impulse and the 64-bit ones
+    Other,
+}
+
+impl Arg {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            Arg::Int(i) => Some(*i as f32),
+            Arg::Float(f) => Some(*f),
+            Arg::Bool(b) => Some(*b as u8 as f32),
+            Arg::Str(s) => s.parse().ok(),
+            Arg::Other => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    address: String,
+    args: Vec<Arg>,
+}
+
+// OSC strings are null terminated and padded to 4 bytes
+fn read_str<'a>(data: &mut &'a [u8]) -> Option<&'a str> {
+    let len = data.iter().position(|b| *b == 0)?;
+    let s = std::str::from_utf8(&data[..len]).ok()?;
+    *data = data.get((len + 4) & !3..)?;
+    Some(s)
+}
+
+fn read_bytes<const N: usize>(data: &mut &[u8]) -> Option<[u8; N]> {
+    let bytes = data.get(..N)?.try_into().ok()?;
+    *data = &data[N..];
+    Some(bytes)
+}
+
+// Every message in a packet, with bundles flattened. Time tags are ignored: everything
+// applies on the next frame.
+fn decode(packet: &[u8], messages: &mut Vec<Message>) -> Option<()> {
+    let mut data = packet;
+    if data.starts_with(b"#bundle\0") {
+        data = data.get(16..)?;
+        while !data.is_empty() {
+            let len = u32::from_be_bytes(read_bytes(&mut data)?) as usize;
+            decode(data.get(..len)?, messages)?;
+            data = &data[len..];
+        }
+        return Some(());
+    }
+
+    let address = read_str(&mut data)?.to_owned();
+    // Some old senders leave the type tags out, which means no args
+    let tags = match data.first() {
+        Some(b',') => read_str(&mut data)?,
+        _ => ",",
+    };
+    let mut args = vec![];
+    for tag in tags[1..].chars() {
+        let arg = match tag {
+            'i' => Arg::Int(i32::from_be_bytes(read_bytes(&mut data)?)),
+            'f' => Arg::Float(f32::from_be_bytes(read_bytes(&mut data)?)),
+            'T' => Arg::Bool(true),
+            'F' => Arg::Bool(false),
+            's' | 'S' => Arg::Str(read_str(&mut data)?.to_owned()),
+            'h' | 'd' | 't' => {
+                read_bytes::<8>(&mut data)?;
+                Arg::Other
+            }
+            'c' | 'r' | 'm' => {
+                read_bytes::<4>(&mut data)?;
+                Arg::Other
+            }
+            'b' => {
+                let len = u32::from_be_bytes(read_bytes(&mut data)?) as usize;
+                data = data.get((len + 3) & !3..)?;
+                Arg::Other
+            }
+            'N' | 'I' | '[' | ']' => Arg::Other,
+            _ => return None,
+        };
+        args.push(arg);
+    }
+    messages.push(Message { address, args });
+    Some(())
+}
+
+// Buttons send 1 on press and 0 on release, only the press should fire
+fn released(command: &OscCommand, args: &[Arg]) -> bool {
+    let value = args.first().and_then(Arg::as_f32);
+    match command {
+        OscCommand::SetParam { .. }
+        | OscCommand::SetParamNormalized { .. }
+        | OscCommand::SetTime(_) => false,
+        _ => value == Some(0.0),
+    }
+}
+
+fn command(message: &Message, map: &BTreeMap<String, String>) -> Option<OscCommand> {
+    let address = map.get(&message.address).unwrap_or(&message.address);
+    let value = message.args.first().and_then(Arg::as_f32);
+    let parts: Vec<&str> = address.trim_start_matches('/').split('/').collect();
+    let command = match parts[..] {
+        ["param", name] => OscCommand::SetParam {
+            name: name.to_owned(),
+            value: value?,
+        },
+        ["param", name, "normalized"] => OscCommand::SetParamNormalized {
+            name: name.to_owned(),
+            value: value?,
+        },
+        ["preset", slot] => OscCommand::RecallPreset {
+            slot: slot.parse().ok()?,
+            morph: true,
+        },
+        ["preset", slot, "snap"] => OscCommand::RecallPreset {
+            slot: slot.parse().ok()?,
+            morph: false,
+        },
+        ["preset", slot, "save"] => OscCommand::SavePreset {
+            slot: slot.parse().ok()?,
+        },
+        ["time", "pause"] => OscCommand::Pause,
+        ["time", "play"] => OscCommand::Play,
+        ["time", "toggle"] => OscCommand::TogglePause,
+        ["time", "set"] => OscCommand::SetTime(value?),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// A UDP listener turning OSC messages into [`OscCommand`]s.
+///
+/// ```no_run
+/// let osc = wgsl_workbench::OscServer::bind("127.0.0.1:9000").unwrap();
+/// for command in osc.try_iter() {
+///     println!("{command:?}");
+/// }
+/// ```
+pub struct OscServer {
+    addr: SocketAddr,
+    commands: Receiver<OscCommand>,
+    stop: Arc<AtomicBool>,
+}
+
+impl OscServer {
+    /// Listen on `addr`, with incoming addresses in `map` rewritten first. Port 0 picks a free one.
+    pub fn bind_with(addr: &str, map: BTreeMap<String, String>) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL))?;
+        let addr = socket.local_addr()?;
+        let (sender, commands) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut buf = vec![0; MAX_PACKET];
+            while !stopped.load(Ordering::Relaxed) {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(e) => {
+                        println!("OSC receive failed: {e}");
+                        return;
+                    }
+                };
+                let mut messages = vec![];
+                if decode(&buf[..len], &mut messages).is_none() {
+                    println!("Ignoring malformed OSC packet");
+                }
+                for message in messages {
+                    match command(&message, &map) {
+                        Some(command) if released(&command, &message.args) => {}
+                        Some(command) => {
+                            let _ = sender.send(command);
+                        }
+                        None => println!("Ignoring OSC message {}", message.address),
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            commands,
+            stop,
+        })
+    }
+
+    /// Listen on `addr`, e.g. `0.0.0.0:9000`.
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        Self::bind_with(addr, BTreeMap::new())
+    }
+
+    /// Listen as `[osc]` in a project file says.
+    pub(crate) fn open(settings: &OscSettings) -> io::Result<Self> {
+        let addr = format!("{}:{}", settings.bind, settings.port);
+        Self::bind_with(&addr, settings.map.clone())
+            .map_err(|e| io::Error::new(e.kind(), format!("Can't listen for OSC on {addr}: {e}")))
+    }
+
+    /// Where the server is listening.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Commands received since the last call, without waiting.
+    pub fn try_iter(&self) -> TryIter<'_, OscCommand> {
+        self.commands.try_iter()
+    }
+
+    /// Wait up to `timeout` for the next command.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<OscCommand> {
+        self.commands.recv_timeout(timeout).ok()
+    }
+}
+
+// Frees the port once the listener notices
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn padded(bytes: &[u8]) -> Vec<u8> {
+        let mut out = bytes.to_vec();
+        out.push(0);
+        out.resize((out.len() + 3) & !3, 0);
+        out
+    }
+
+    fn encode(address: &str, args: &[Arg]) -> Vec<u8> {
+        let mut tags = ",".to_owned();
+        let mut data = vec![];
+        for arg in args {
+            match arg {
+                Arg::Int(i) => {
+                    tags.push('i');
+                    data.extend(i.to_be_bytes());
+                }
+                Arg::Float(f) => {
+                    tags.push('f');
+                    data.extend(f.to_be_bytes());
+                }
+                Arg::Bool(b) => tags.push(if *b { 'T' } else { 'F' }),
+                Arg::Str(s) => {
+                    tags.push('s');
+                    data.extend(padded(s.as_bytes()));
+                }
+                Arg::Other => tags.push('N'),
+            }
+        }
+        [padded(address.as_bytes()), padded(tags.as_bytes()), data].concat()
+    }
+
+    fn bundle(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = b"#bundle\0".to_vec();
+        // Immediately
+        out.extend(1u64.to_be_bytes());
+        for packet in packets {
+            out.extend((packet.len() as u32).to_be_bytes());
+            out.extend(packet);
+        }
+        out
+    }
+
+    fn decoded(packet: &[u8]) -> Option<Vec<Message>> {
+        let mut messages = vec![];
+        decode(packet, &mut messages)?;
+        Some(messages)
+    }
+
+    fn message(address: &str, args: Vec<Arg>) -> Message {
+        Message {
+            address: address.to_owned(),
+            args,
+        }
+    }
+
+    #[test]
+    fn decodes_messages() {
+        let args = vec![
+            Arg::Int(-3),
+            Arg::Float(0.5),
+            Arg::Str("abcd".to_owned()),
+            Arg::Bool(true),
+            Arg::Bool(false),
+            Arg::Other,
+        ];
+        assert_eq!(
+            decoded(&encode("/param/speed", &args)),
+            Some(vec![message("/param/speed", args)])
+        );
+    }
+
+    #[test]
+    fn skips_blobs_and_64_bit_args() {
+        let mut packet = [padded(b"/a"), padded(b",bhf")].concat();
+        packet.extend(5u32.to_be_bytes());
+        packet.extend([1, 2, 3, 4, 5, 0, 0, 0]);
+        packet.extend(7i64.to_be_bytes());
+        packet.extend(2.0f32.to_be_bytes());
+        assert_eq!(
+            decoded(&packet),
+            Some(vec![message(
+                "/a",
+                vec![Arg::Other, Arg::Other, Arg::Float(2.0)]
+            )])
+        );
+    }
+
+    #[test]
+    fn missing_type_tags_mean_no_args() {
+        assert_eq!(
+            decoded(&padded(b"/time/pause")),
+            Some(vec![message("/time/pause", vec![])])
+        );
+    }
+
+    #[test]
+    fn flattens_nested_bundles() {
+        let inner = bundle(&[encode("/b", &[Arg::Int(2)])]);
+        let packet = bundle(&[encode("/a", &[Arg::Int(1)]), inner, encode("/c", &[])]);
+        assert_eq!(
+            decoded(&packet),
+            Some(vec![
+                message("/a", vec![Arg::Int(1)]),
+                message("/b", vec![Arg::Int(2)]),
+                message("/c", vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let packet = encode("/param/speed", &[Arg::Float(1.0)]);
+        assert_eq!(decoded(&packet[..packet.len() - 2]), None);
+        assert_eq!(decoded(&[padded(b"/a"), padded(b",x")].concat()), None);
+        let mut bundle = bundle(&[packet]);
+        bundle.truncate(bundle.len() - 1);
+        assert_eq!(decoded(&bundle), None);
+    }
+
+    #[test]
+    fn maps_addresses_to_commands() {
+        let map = BTreeMap::from([("/1/fader1".to_owned(), "/param/speed/normalized".to_owned())]);
+        let to_command = |address: &str, args: Vec<Arg>| command(&message(address, args), &map);
+
+        assert_eq!(
+            to_command("/param/speed", vec![Arg::Int(3)]),
+            Some(OscCommand::SetParam {
+                name: "speed".to_owned(),
+                value: 3.0
+            })
+        );
+        assert_eq!(
+            to_command("/1/fader1", vec![Arg::Float(0.25)]),
+            Some(OscCommand::SetParamNormalized {
+                name: "speed".to_owned(),
+                value: 0.25
+            })
+        );
+        assert_eq!(
+            to_command("/preset/4/snap", vec![]),
+            Some(OscCommand::RecallPreset {
+                slot: 4,
+                morph: false
+            })
+        );
+        assert_eq!(
+            to_command("/preset/2/save", vec![]),
+            Some(OscCommand::SavePreset { slot: 2 })
+        );
+        assert_eq!(
+            to_command("/time/set", vec![Arg::Str("1.5".to_owned())]),
+            Some(OscCommand::SetTime(1.5))
+        );
+        assert_eq!(
+            to_command("/time/toggle", vec![]),
+            Some(OscCommand::TogglePause)
+        );
+        // Params need a value, and presets a number
+        assert_eq!(to_command("/param/speed", vec![]), None);
+        assert_eq!(to_command("/preset/one", vec![]), None);
+        assert_eq!(to_command("/unknown", vec![]), None);
+    }
+
+    #[test]
+    fn only_button_presses_fire() {
+        let pause = OscCommand::Pause;
+        assert!(released(&pause, &[Arg::Float(0.0)]));
+        assert!(!released(&pause, &[Arg::Float(1.0)]));
+        assert!(!released(&pause, &[]));
+        let set = OscCommand::SetParam {
+            name: "x".to_owned(),
+            value: 0.0,
+        };
+        assert!(!released(&set, &[Arg::Float(0.0)]));
+    }
+
+    fn send(server: &OscServer, packet: &[u8]) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(packet, server.local_addr()).unwrap();
+    }
+
+    #[test]
+    fn receives_over_udp() {
+        let server = OscServer::bind("127.0.0.1:0").unwrap();
+
+        send(&server, &encode("/param/speed", &[Arg::Float(2.5)]));
+        assert_eq!(
+            server.recv_timeout(TIMEOUT),
+            Some(OscCommand::SetParam {
+                name: "speed".to_owned(),
+                value: 2.5
+            })
+        );
+
+        // The button's release is dropped, so the next command is the pause
+        send(&server, &encode("/preset/3", &[Arg::Float(1.0)]));
+        send(&server, &encode("/preset/3", &[Arg::Float(0.0)]));
+        send(&server, &encode("/time/pause", &[]));
+        assert_eq!(
+            server.recv_timeout(TIMEOUT),
+            Some(OscCommand::RecallPreset {
+                slot: 3,
+                morph: true
+            })
+        );
+        assert_eq!(server.recv_timeout(TIMEOUT), Some(OscCommand::Pause));
+
+        send(
+            &server,
+            &bundle(&[
+                encode("/time/set", &[Arg::Float(4.0)]),
+                encode("/preset/2/snap", &[Arg::Int(1)]),
+            ]),
+        );
+        assert_eq!(server.recv_timeout(TIMEOUT), Some(OscCommand::SetTime(4.0)));
+        assert_eq!(
+            server.recv_timeout(TIMEOUT),
+            Some(OscCommand::RecallPreset {
+                slot: 2,
+                morph: false
+            })
+        );
+        assert_eq!(server.try_iter().next(), None);
+    }
+
+    #[test]
+    fn rewrites_addresses_from_the_map() {
+        let settings = OscSettings {
+            bind: "127.0.0.1".to_owned(),
+            port: 0,
+            map: BTreeMap::from([("/1/push1".to_owned(), "/time/toggle".to_owned())]),
+        };
+        let server = OscServer::open(&settings).unwrap();
+        send(&server, &encode("/1/push1", &[Arg::Float(1.0)]));
+        assert_eq!(server.recv_timeout(TIMEOUT), Some(OscCommand::TogglePause));
+    }
+}
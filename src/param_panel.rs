@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    midi::MidiControl,
+    params::{ParamDecl, Params},
+};
+
+// The egui param panel: a slider per param, across its declared range. With MIDI learn on,
+// clicking a slider picks it for the next control that moves, and right-clicking unmaps it.
+#[derive(Default)]
+pub struct ParamPanel {
+    learn: bool,
+    // The param waiting for a control
+    pub learning: Option<String>,
+}
+
+impl ParamPanel {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        decls: &[ParamDecl],
+        params: &mut Params,
+        mut midi: Option<&mut BTreeMap<String, MidiControl>>,
+    ) {
+        egui::SidePanel::right("params").show(ctx, |ui| {
+            ui.heading("Params");
+            if decls.is_empty() {
+                ui.label("Declare params with `// @param name = default [min, max]`");
+            }
+            if midi.is_some() && ui.checkbox(&mut self.learn, "MIDI learn").changed() {
+                self.learning = None;
+            }
+
+            for decl in decls {
+                let Some(mut value) = params.get(&decl.name) else {
+                    continue;
+                };
+                ui.label(&decl.name);
+                ui.horizontal(|ui| {
+                    let response = ui.add(egui::Slider::new(&mut value, decl.min..=decl.max));
+                    if response.changed() {
+                        params.set(&decl.name, value);
+                    }
+
+                    let Some(map) = midi.as_deref_mut() else {
+                        return;
+                    };
+                    if self.learn && (response.clicked() || response.drag_started()) {
+                        self.learning = Some(decl.name.clone());
+                    }
+                    if self.learn && response.secondary_clicked() {
+                        map.remove(&decl.name);
+                    }
+                    if self.learning.as_ref() == Some(&decl.name) {
+                        ui.colored_label(ui.visuals().warn_fg_color, "move a control");
+                    } else if let Some(control) = map.get(&decl.name) {
+                        ui.weak(control.to_string());
+                    }
+                });
+            }
+        });
+    }
+}
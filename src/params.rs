@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+
+// Size of the params uniform buffer, in f32s. Shaders can declare fewer, never more.
+pub const MAX_PARAMS: usize = 64;
+
+// A tunable parameter, declared in the fragment shader with a comment like:
+//
+//     // @param speed = 1.0 [0.0, 10.0]
+//
+// Every declared parameter becomes a field of the `params` uniform struct in the prelude.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamDecl {
+    pub name: String,
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ParamDecl {
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim().strip_prefix("//")?.trim().strip_prefix("@param")?;
+        let (name, rest) = rest.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None;
+        }
+
+        let (default, range) = match rest.split_once('[') {
+            Some((default, range)) => (default, Some(range.trim_end().strip_suffix(']')?)),
+            None => (rest, None),
+        };
+        let default: f32 = default.trim().parse().ok()?;
+        let (min, max) = match range {
+            Some(range) => {
+                let (min, max) = range.split_once(',')?;
+                (min.trim().parse().ok()?, max.trim().parse().ok()?)
+            }
+            None => (default.min(0.0), default.max(1.0)),
+        };
+
+        Some(Self {
+            name: name.to_owned(),
+            default,
+            min,
+            max,
+        })
+    }
+}
+
+pub fn parse_decls(source: &str) -> Vec<ParamDecl> {
+    let mut decls: Vec<ParamDecl> = vec![];
+    for decl in source.lines().filter_map(ParamDecl::parse) {
+        if decls.len() == MAX_PARAMS {
+            eprintln!("Too many params, ignoring {}", decl.name);
+        } else if decls.iter().any(|d| d.name == decl.name) {
+            eprintln!("Duplicate param {}, ignoring", decl.name);
+        } else {
+            decls.push(decl);
+        }
+    }
+    decls
+}
+
+// Lines that look like param declarations but don't parse, as 0-based line numbers
+pub fn malformed_decls(source: &str) -> Vec<usize> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let annotation = line.trim().strip_prefix("//").map(str::trim_start);
+            annotation.is_some_and(|a| a.starts_with("@param")) && ParamDecl::parse(line).is_none()
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// Where a param is declared, as a 0-based line number
+pub fn decl_line(source: &str, name: &str) -> Option<usize> {
+    source
+        .lines()
+        .position(|line| ParamDecl::parse(line).is_some_and(|d| d.name == name))
+}
+
+// The prelude snippet declaring the `params` uniform. Empty if the shader declares no params.
+pub fn wgsl_struct(decls: &[ParamDecl]) -> String {
+    if decls.is_empty() {
+        return String::new();
+    }
+
+    let fields: Vec<String> = decls
+        .iter()
+        .map(|d| format!("        {}: f32,", d.name))
+        .collect();
+    format!(
+        "
+    struct Params {{
+{}
+    }}
+    @group(0) @binding(5)
+    var<uniform> params: Params;",
+        fields.join("\n")
+    )
+}
+
+// Current parameter values, laid out in declaration order to match the `Params` struct.
+#[derive(Default)]
+pub struct Params {
+    decls: Vec<ParamDecl>,
+    values: Vec<f32>,
+}
+
+impl Params {
+    // Adopt a new set of declarations, keeping the value of every param that survived the reload.
+    pub fn sync(&mut self, decls: &[ParamDecl]) {
+        if self.decls == decls {
+            return;
+        }
+
+        let values = decls
+            .iter()
+            .map(|d| self.get(&d.name).unwrap_or(d.default))
+            .collect();
+        self.decls = decls.to_vec();
+        self.values = values;
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        let i = self.decls.iter().position(|d| d.name == name)?;
+        Some(self.values[i])
+    }
+
+    pub fn set(&mut self, name: &str, value: f32) -> bool {
+        match self.decls.iter().position(|d| d.name == name) {
+            Some(i) => {
+                self.values[i] = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Set a param from 0 to 1 across its declared range, for controllers that don't know it
+    pub fn set_normalized(&mut self, name: &str, t: f32) -> bool {
+        match self.decls.iter().position(|d| d.name == name) {
+            Some(i) => {
+                let decl = &self.decls[i];
+                self.values[i] = decl.min + t.clamp(0.0, 1.0) * (decl.max - decl.min);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn values(&self) -> BTreeMap<String, f32> {
+        self.decls
+            .iter()
+            .zip(&self.values)
+            .map(|(d, v)| (d.name.clone(), *v))
+            .collect()
+    }
+
+    // Set every param present in `values`. Names the shader no longer declares are ignored.
+    pub fn apply(&mut self, values: &BTreeMap<String, f32>) {
+        for (name, value) in values {
+            self.set(name, *value);
+        }
+    }
+
+    pub fn as_uniform(&self) -> [f32; MAX_PARAMS] {
+        let mut unif = [0f32; MAX_PARAMS];
+        unif[..self.values.len()].copy_from_slice(&self.values);
+        unif
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decl(name: &str, default: f32, min: f32, max: f32) -> ParamDecl {
+        ParamDecl {
+            name: name.to_owned(),
+            default,
+            min,
+            max,
+        }
+    }
+
+    #[test]
+    fn parses_declarations() {
+        assert_eq!(
+            ParamDecl::parse("// @param speed = 1.5 [0.0, 10.0]"),
+            Some(decl("speed", 1.5, 0.0, 10.0))
+        );
+        assert_eq!(
+            ParamDecl::parse("    //@param  zoom_2=-2 [ -4 , 4 ]  "),
+            Some(decl("zoom_2", -2.0, -4.0, 4.0))
+        );
+    }
+
+    #[test]
+    fn range_defaults_to_zero_one_widened_to_the_default() {
+        assert_eq!(
+            ParamDecl::parse("// @param gain = 0.5"),
+            Some(decl("gain", 0.5, 0.0, 1.0))
+        );
+        assert_eq!(
+            ParamDecl::parse("// @param scale = 3"),
+            Some(decl("scale", 3.0, 0.0, 3.0))
+        );
+        assert_eq!(
+            ParamDecl::parse("// @param offset = -2"),
+            Some(decl("offset", -2.0, -2.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn default_outside_the_range_is_kept() {
+        assert_eq!(
+            ParamDecl::parse("// @param x = 5 [0, 1]"),
+            Some(decl("x", 5.0, 0.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_declarations() {
+        for line in [
+            "// @param = 1",
+            "// @param bad-name = 1",
+            "// @param x = one",
+            "// @param x 1",
+            "// @param x = 1 [0, 1",
+            "// @param x = 1 [0]",
+            "// @param x = 1 [0, high]",
+            "@param x = 1",
+            "let x = 1; // @param x = 1",
+        ] {
+            assert_eq!(ParamDecl::parse(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_first_of_duplicate_names() {
+        let source = "// @param a = 1\n// @param b = 2\n// @param a = 3 [0, 5]\n";
+        assert_eq!(
+            parse_decls(source),
+            vec![decl("a", 1.0, 0.0, 1.0), decl("b", 2.0, 0.0, 2.0)]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let source = [
+            "// @param a = 1",
+            "fn f() {}",
+            "// @param b = [0, 1]",
+            "// a comment",
+            "  //@param c",
+        ]
+        .join("\n");
+        assert_eq!(malformed_decls(&source), vec![2, 4]);
+        assert_eq!(decl_line(&source, "a"), Some(0));
+        assert_eq!(decl_line(&source, "b"), None);
+    }
+}
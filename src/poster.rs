@@ -0,0 +1,73 @@
+// Stills bigger than the GPU's max texture size, rendered in tiles and stitched on the CPU
+
+use serde::{Deserialize, Serialize};
+use std::{io::Write, path::Path};
+
+use crate::{capture, renderer::Renderer};
+
+fn default_scale() -> u32 {
+    4
+}
+
+fn default_tile() -> u32 {
+    2048
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PosterSettings {
+    // Defaults to the render target's size times `scale`
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(default = "default_scale")]
+    pub scale: u32,
+    #[serde(default = "default_tile")]
+    pub tile: u32,
+}
+
+impl Default for PosterSettings {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            scale: default_scale(),
+            tile: default_tile(),
+        }
+    }
+}
+
+// Render a `canvas` sized image as a grid of tiles, one row of tiles at a time, streaming each
+// finished row into the PNG so only a single strip of the image is ever held in memory.
+pub fn render(
+    renderer: &mut Renderer,
+    canvas: (u32, u32),
+    tile: u32,
+    path: &Path,
+) -> std::io::Result<()> {
+    let (width, height) = canvas;
+    let text = renderer.capture_metadata(canvas);
+    let mut png = capture::png_writer(path, width, height, &text)?;
+    let mut stream = png.stream_writer()?;
+
+    let row_bytes = 4 * width as usize;
+    for y in (0..height).step_by(tile as usize) {
+        let strip_height = tile.min(height - y);
+        let mut strip = vec![0u8; row_bytes * strip_height as usize];
+
+        for x in (0..width).step_by(tile as usize) {
+            let tile_width = tile.min(width - x);
+            let pixels = renderer.render_region(canvas, (x, y), (tile_width, strip_height))?;
+
+            let tile_row_bytes = 4 * tile_width as usize;
+            for (row, src) in pixels.chunks_exact(tile_row_bytes).enumerate() {
+                let start = row * row_bytes + 4 * x as usize;
+                strip[start..start + tile_row_bytes].copy_from_slice(src);
+            }
+        }
+
+        stream.write_all(&strip)?;
+        println!("Poster: {}/{height} rows", y + strip_height);
+    }
+
+    stream.finish()?;
+    Ok(png.finish()?)
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use web_time::Instant;
+
+// A named snapshot of every param value plus the clock time, recalled with a number key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub slot: u8,
+    pub time: f32,
+    #[serde(default)]
+    pub values: BTreeMap<String, f32>,
+}
+
+fn default_morph_seconds() -> f32 {
+    1.0
+}
+
+// All presets for one shader, stored as `<shader>.presets.toml` next to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PresetBank {
+    #[serde(default = "default_morph_seconds")]
+    pub morph_seconds: f32,
+    #[serde(default, rename = "preset")]
+    pub presets: Vec<Preset>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl PresetBank {
+    pub fn path_for(shader: &Path) -> PathBuf {
+        shader.with_extension("presets.toml")
+    }
+
+    pub fn new(shader: &Path) -> Self {
+        Self {
+            morph_seconds: default_morph_seconds(),
+            presets: vec![],
+            path: Self::path_for(shader),
+        }
+    }
+
+    // Load the bank next to `shader`, or start an empty one if there is none yet.
+    pub fn load(shader: &Path) -> io::Result<Self> {
+        let path = Self::path_for(shader);
+        if !path.exists() {
+            return Ok(Self::new(shader));
+        }
+
+        let mut bank: Self = toml::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        bank.path = path;
+        Ok(bank)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let s = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, s)
+    }
+
+    pub fn get(&self, slot: u8) -> Option<&Preset> {
+        self.presets.iter().find(|p| p.slot == slot)
+    }
+
+    // Store a preset in its slot, replacing whatever was there.
+    pub fn store(&mut self, preset: Preset) {
+        self.presets.retain(|p| p.slot != preset.slot);
+        self.presets.push(preset);
+        self.presets.sort_by_key(|p| p.slot);
+    }
+}
+
+// A smooth transition between two sets of param values.
+pub struct Morph {
+    from: BTreeMap<String, f32>,
+    to: BTreeMap<String, f32>,
+    start: Instant,
+    duration: f32,
+}
+
+impl Morph {
+    pub fn new(from: BTreeMap<String, f32>, to: BTreeMap<String, f32>, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            start: Instant::now(),
+            duration,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.start.elapsed().as_secs_f32() >= self.duration
+    }
+
+    // Values at the current point of the morph. Params missing from `from` snap straight to `to`.
+    pub fn sample(&self) -> BTreeMap<String, f32> {
+        let t = if self.duration > 0.0 {
+            (self.start.elapsed().as_secs_f32() / self.duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let t = t * t * (3.0 - 2.0 * t);
+
+        self.to
+            .iter()
+            .map(|(name, to)| {
+                let v = match self.from.get(name) {
+                    Some(from) => from + (to - from) * t,
+                    None => *to,
+                };
+                (name.clone(), v)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::api::ApiSettings;
+use crate::{
+    capture::CaptureSettings, input::InputConfig, midi::MidiSettings, osc::OscSettings,
+    poster::PosterSettings, resize::ResizePolicy, timeline::Timeline,
+};
+
+// Everything about a workbench session that should survive a restart, stored as TOML.
+// Running on a bare shader uses `<shader>.workbench.toml` next to it, created on first save.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Project {
+    // Relative to the project file
+    pub shader: PathBuf,
+    #[serde(default)]
+    pub timeline: Timeline,
+    #[serde(default)]
+    pub capture: CaptureSettings,
+    #[serde(default)]
+    pub poster: PosterSettings,
+    #[serde(default)]
+    pub resolution: ResizePolicy,
+    // By the name the shader sees them under
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, InputConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osc: Option<OscSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub midi: Option<MidiSettings>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiSettings>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Project {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if path.extension().is_some_and(|e| e == "toml") {
+            let mut project: Self = toml::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            project.path = path.to_owned();
+            return Ok(project);
+        }
+
+        let project_path = path.with_extension("workbench.toml");
+        if project_path.exists() {
+            return Self::open(&project_path);
+        }
+
+        Ok(Self {
+            shader: PathBuf::from(path.file_name().unwrap_or_default()),
+            timeline: Timeline::default(),
+            capture: CaptureSettings::default(),
+            poster: PosterSettings::default(),
+            resolution: ResizePolicy::default(),
+            inputs: BTreeMap::new(),
+            osc: None,
+            midi: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            api: None,
+            path: project_path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Resolve a path from the project file against the project's directory
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) => dir.join(path),
+            None => path.to_owned(),
+        }
+    }
+
+    pub fn shader_path(&self) -> PathBuf {
+        self.resolve(&self.shader)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let s = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, s)
+    }
+}
@@ -0,0 +1,701 @@
+// The shader's GPU side: uniforms, video and input bindings, the feedback texture, and the render
+// target the shader draws into. Owns no window, so it also drives offscreen captures.
+
+use parking_lot::RwLock;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use wgpu::{util::DeviceExt, Buffer, Extent3d, Texture, TextureFormat, TextureView};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::debug_print::DebugLog;
+use crate::{
+    appstate::{RenderPipelineContext, ValidationError},
+    capture,
+    input::{self, InputBindings, InputLayout, Upload},
+    params::{self, Params},
+    resize::{Blitter, ResizePolicy},
+    shader::FragShader,
+};
+
+// The previous frame, sampled by the shader as backBuffer. Always the size of the render target.
+pub struct BackBuffer {
+    bind_group: wgpu::BindGroup,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // Shared by every back buffer, so sampling doesn't change when the buffer is recreated
+    sampler: wgpu::Sampler,
+    sample_texture: Texture,
+    sample_texture_view: TextureView,
+}
+
+impl BackBuffer {
+    fn new(device: &wgpu::Device, format: TextureFormat, size: (u32, u32)) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("bb_bind_group_layout"),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (sample_texture, sample_texture_view, bind_group) =
+            Self::create(device, &bind_group_layout, &sampler, format, size);
+
+        Self {
+            bind_group,
+            bind_group_layout,
+            sampler,
+            sample_texture,
+            sample_texture_view,
+        }
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        format: TextureFormat,
+        size: (u32, u32),
+    ) -> (Texture, TextureView, wgpu::BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // RENDER_ATTACHMENT so old contents can be blitted in when resizing, COPY_SRC for the
+            // inspector
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
+            label: Some("Back-Buffer Texture"),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("bb_bind_group"),
+        });
+
+        (texture, view, bind_group)
+    }
+
+    // Swap in a buffer of a new size, optionally stretching the old contents into it
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        blitter: &Blitter,
+        size: (u32, u32),
+        preserve: bool,
+    ) {
+        let format = self.sample_texture.format();
+        let (texture, view, bind_group) =
+            Self::create(device, &self.bind_group_layout, &self.sampler, format, size);
+
+        if preserve {
+            let viewport = [0.0, 0.0, size.0 as f32, size.1 as f32];
+            blitter.blit(
+                device,
+                encoder,
+                &self.sample_texture_view,
+                &view,
+                viewport,
+                wgpu::FilterMode::Linear,
+            );
+        }
+
+        self.sample_texture = texture;
+        self.sample_texture_view = view;
+        self.bind_group = bind_group;
+    }
+}
+
+// The textures a frame goes through, for inspecting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stage {
+    // What the shader drew this frame
+    #[default]
+    Target,
+    // The previous frame, as the shader samples it
+    BackBuffer,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 2] = [Stage::Target, Stage::BackBuffer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Target => "Render target",
+            Stage::BackBuffer => "Back buffer",
+        }
+    }
+}
+
+// The buffers behind bind group 0
+struct Uniforms {
+    res: Buffer,
+    frame: Buffer,
+    time: Buffer,
+    params: Buffer,
+    viewport: Buffer,
+}
+
+impl Uniforms {
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        video: &TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.res.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.frame.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(video),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.time.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: self.viewport.as_entire_binding(),
+                },
+            ],
+            label: Some("unif_bind_group"),
+        })
+    }
+}
+
+pub struct Renderer {
+    pub queue: Arc<wgpu::Queue>,
+    pub rpcontext: Arc<RwLock<RenderPipelineContext>>,
+    pub shader_path: PathBuf,
+    unif_bind_group: wgpu::BindGroup,
+    unif_bind_group_layout: wgpu::BindGroupLayout,
+    uniforms: Uniforms,
+    video_texture: Texture,
+    video_sampler: wgpu::Sampler,
+    inputs: InputBindings,
+    backbuffer: BackBuffer,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub debug: DebugLog,
+    // What the shader draws into each frame, presented to the window by the blitter
+    target: Texture,
+    target_view: TextureView,
+    blitter: Blitter,
+    pub frame: u32,
+    // Clock time sampled once per frame, so everything in a frame agrees on it
+    pub time: f32,
+    pub params: Params,
+}
+
+impl Renderer {
+    // `frag_shader` is None, or doesn't validate, the default shader runs. `shader_path` is only
+    // for capture metadata.
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        format: TextureFormat,
+        size: (u32, u32),
+        input_layout: &InputLayout,
+        shader_path: &Path,
+        frag_shader: Option<FragShader>,
+    ) -> Self {
+        // videoBuffer is a black pixel until a frame is uploaded
+        let (video_texture, video_view) = input::create_texture(&device, (1, 1));
+        let video_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let inputs = InputBindings::new(&device, input_layout);
+
+        let backbuffer = BackBuffer::new(&device, format, size);
+        #[cfg(not(target_arch = "wasm32"))]
+        let debug = DebugLog::new(&device);
+        let (target, target_view) = create_target(&device, format, size);
+        let blitter = Blitter::new(&device, format);
+
+        // Set up uniforms (resolution, framecount, etc)
+
+        let res_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Resolution Uniform"),
+            contents: bytemuck::cast_slice(&[0f32; 2]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let frame_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Frame Count Uniform"),
+            contents: bytemuck::cast_slice(&[0u32, 1]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let time_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Time Uniform"),
+            contents: bytemuck::cast_slice(&[0f32]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let viewport_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Viewport Uniform"),
+            contents: bytemuck::cast_slice(&[0f32, 0.0, 1.0, 0.0]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let params_unif = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Params Uniform"),
+            contents: bytemuck::cast_slice(&[0f32; params::MAX_PARAMS]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let unif_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    uniform_entry(0),
+                    uniform_entry(1),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    uniform_entry(4),
+                    uniform_entry(5),
+                    uniform_entry(6),
+                ],
+                label: Some("unif_bind_group_layout"),
+            });
+
+        let uniforms = Uniforms {
+            res: res_unif,
+            frame: frame_unif,
+            time: time_unif,
+            params: params_unif,
+            viewport: viewport_unif,
+        };
+        let unif_bind_group = uniforms.bind_group(
+            &device,
+            &unif_bind_group_layout,
+            &video_view,
+            &video_sampler,
+        );
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &unif_bind_group_layout,
+                    &backbuffer.bind_group_layout,
+                    &inputs.layout,
+                    #[cfg(not(target_arch = "wasm32"))]
+                    &debug.layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let validation_errors = Arc::new(RwLock::new(vec![]));
+        let c_validation_errors = validation_errors.clone();
+
+        device.on_uncaptured_error(Box::new(move |e| match e {
+            wgpu::Error::OutOfMemory { .. } => panic!("Device out of memory!"),
+            wgpu::Error::Validation { description, .. } => {
+                println!("validation error! {:}", description);
+                c_validation_errors
+                    .write()
+                    .push(ValidationError { description });
+            }
+        }));
+
+        let rpctx = RenderPipelineContext::new(
+            device,
+            render_pipeline_layout,
+            format,
+            validation_errors,
+            frag_shader,
+            input_layout.clone(),
+        );
+
+        let mut params = Params::default();
+        params.sync(&rpctx.param_decls);
+
+        Self {
+            queue,
+            rpcontext: Arc::new(RwLock::new(rpctx)),
+            shader_path: shader_path.to_owned(),
+            unif_bind_group,
+            unif_bind_group_layout,
+            uniforms,
+            video_texture,
+            video_sampler,
+            inputs,
+            backbuffer,
+            #[cfg(not(target_arch = "wasm32"))]
+            debug,
+            target,
+            target_view,
+            blitter,
+            frame: 0,
+            time: 0.0,
+            params,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.target.width(), self.target.height())
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.target.format()
+    }
+
+    // Recreate the render target and back buffer together, so they never disagree on size
+    pub fn resize(&mut self, size: (u32, u32), preserve_feedback: bool) {
+        if size == self.size() || size.0 == 0 || size.1 == 0 {
+            return;
+        }
+
+        let rpctx = self.rpcontext.read();
+        let (target, target_view) = create_target(&rpctx.device, self.format(), size);
+
+        let mut encoder = rpctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Resize Encoder"),
+            });
+        self.backbuffer.resize(
+            &rpctx.device,
+            &mut encoder,
+            &self.blitter,
+            size,
+            preserve_feedback,
+        );
+        self.queue.submit([encoder.finish()]);
+
+        self.target = target;
+        self.target_view = target_view;
+    }
+
+    // A new videoBuffer frame, resizing the texture if the source changed size
+    pub fn upload_video(&mut self, width: u32, height: u32, rgba: &[u8]) {
+        if width == 0 || height == 0 || rgba.len() < (width * height * 4) as usize {
+            return;
+        }
+        let device = self.rpcontext.read().device.clone();
+        if (self.video_texture.width(), self.video_texture.height()) != (width, height) {
+            let (texture, view) = input::create_texture(&device, (width, height));
+            self.unif_bind_group = self.uniforms.bind_group(
+                &device,
+                &self.unif_bind_group_layout,
+                &view,
+                &self.video_sampler,
+            );
+            self.video_texture = texture;
+        }
+        input::write_texture(&self.queue, &self.video_texture, rgba);
+    }
+
+    // New contents for binding `index` of the input layout
+    pub fn upload_input(&mut self, index: usize, upload: Upload) {
+        let device = self.rpcontext.read().device.clone();
+        self.inputs.upload(&device, &self.queue, index, upload);
+    }
+
+    // Pick up params added or removed by a shader reload
+    pub fn sync_params(&mut self) {
+        self.params.sync(&self.rpcontext.read().param_decls);
+    }
+
+    // `res` is the size of the whole canvas, which `offset` places this render's target within
+    fn write_uniforms(&self, res: (u32, u32), offset: (u32, u32)) {
+        self.queue.write_buffer(
+            &self.uniforms.res,
+            0,
+            bytemuck::cast_slice(&[res.0 as f32, res.1 as f32]),
+        );
+
+        let scale = res.0 as f32 / self.size().0.max(1) as f32;
+        self.queue.write_buffer(
+            &self.uniforms.viewport,
+            0,
+            bytemuck::cast_slice(&[offset.0 as f32, offset.1 as f32, scale, 0.0]),
+        );
+
+        self.queue
+            .write_buffer(&self.uniforms.frame, 0, bytemuck::cast_slice(&[self.frame]));
+
+        self.queue
+            .write_buffer(&self.uniforms.time, 0, bytemuck::cast_slice(&[self.time]));
+
+        self.queue.write_buffer(
+            &self.uniforms.params,
+            0,
+            bytemuck::cast_slice(&self.params.as_uniform()),
+        );
+    }
+
+    fn draw(
+        &self,
+        rpctx: &RenderPipelineContext,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &TextureView,
+        clear: wgpu::Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&rpctx.pipeline);
+        render_pass.set_bind_group(0, &self.unif_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.backbuffer.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.inputs.bind_group, &[]);
+        #[cfg(not(target_arch = "wasm32"))]
+        render_pass.set_bind_group(3, &self.debug.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+
+    // Draw a frame into the render target and keep it as the next frame's back buffer
+    pub fn render(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.write_uniforms(self.size(), (0, 0));
+        #[cfg(not(target_arch = "wasm32"))]
+        self.debug.reset(&self.queue, true);
+        self.frame += 1;
+
+        self.draw(
+            &self.rpcontext.read(),
+            encoder,
+            &self.target_view,
+            wgpu::Color::WHITE,
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        self.debug.copy(encoder);
+
+        // Same size by construction, see resize
+        encoder.copy_texture_to_texture(
+            self.target.as_image_copy(),
+            self.backbuffer.sample_texture.as_image_copy(),
+            self.target.size(),
+        );
+    }
+
+    // Blit the render target into a window-sized view according to the policy
+    pub fn present(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &TextureView,
+        window: (u32, u32),
+        policy: &ResizePolicy,
+    ) {
+        self.blitter.present(
+            &self.rpcontext.read().device,
+            encoder,
+            policy,
+            (&self.target_view, self.size()),
+            (view, window),
+        );
+    }
+
+    // Read back the last frame drawn by render, as RGBA8
+    pub fn read_target(&self) -> io::Result<Vec<u8>> {
+        let rpctx = self.rpcontext.read();
+        capture::read_texture(&rpctx.device, &self.queue, &self.target)
+    }
+
+    // Read back `size` texels at `origin` of a stage's texture, as the values the shader wrote
+    pub fn read_region(
+        &self,
+        stage: Stage,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<Vec<[f32; 4]>> {
+        let texture = match stage {
+            Stage::Target => &self.target,
+            Stage::BackBuffer => &self.backbuffer.sample_texture,
+        };
+        let rpctx = self.rpcontext.read();
+        capture::read_region(&rpctx.device, &self.queue, texture, origin, size)
+    }
+
+    // Every texture the shader draws into or samples, for the texture viewer
+    pub fn textures(&self) -> Vec<(&str, &Texture)> {
+        let mut textures = vec![
+            (Stage::Target.name(), &self.target),
+            (Stage::BackBuffer.name(), &self.backbuffer.sample_texture),
+            ("Camera", &self.video_texture),
+        ];
+        textures.extend(self.inputs.textures());
+        textures
+    }
+
+    // Render the current frame into a fresh texture of any size and read it back as RGBA8.
+    // Doesn't advance the frame counter or touch the back buffer.
+    pub fn render_offscreen(&mut self, width: u32, height: u32) -> io::Result<Vec<u8>> {
+        self.render_region((width, height), (0, 0), (width, height))
+    }
+
+    // Like render_offscreen, but for one `size` region at `offset` of a bigger virtual canvas
+    pub fn render_region(
+        &mut self,
+        canvas: (u32, u32),
+        offset: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<Vec<u8>> {
+        let rpctx = self.rpcontext.read();
+        let (texture, view) = create_target(&rpctx.device, self.format(), size);
+
+        self.write_uniforms(canvas, offset);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.debug.reset(&self.queue, false);
+
+        let mut encoder = rpctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen Encoder"),
+            });
+        self.draw(&rpctx, &mut encoder, &view, wgpu::Color::BLACK);
+        self.queue.submit([encoder.finish()]);
+
+        capture::read_texture(&rpctx.device, &self.queue, &texture)
+    }
+
+    // Everything needed to reproduce a capture, for the PNG's text chunks
+    pub fn capture_metadata(&self, res: (u32, u32)) -> Vec<(String, String)> {
+        let rpctx = self.rpcontext.read();
+        let mut text = vec![
+            ("Software".to_owned(), "wgsl_workbench".to_owned()),
+            ("Shader".to_owned(), self.shader_path.display().to_string()),
+            (
+                "ShaderSHA256".to_owned(),
+                capture::hash(rpctx.frag_source.as_bytes()),
+            ),
+            ("res".to_owned(), format!("{} {}", res.0, res.1)),
+            ("frame".to_owned(), self.frame.to_string()),
+            ("time".to_owned(), self.time.to_string()),
+        ];
+        for (name, value) in self.params.values() {
+            text.push((format!("params.{name}"), value.to_string()));
+        }
+        text
+    }
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    format: TextureFormat,
+    size: (u32, u32),
+) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        label: Some("Render Target"),
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
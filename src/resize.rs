@@ -0,0 +1,336 @@
+// How the shader's render target relates to the window, and the blits that move pixels between them
+
+use serde::{Deserialize, Serialize};
+
+fn default_preserve_feedback() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scaling {
+    // Keep the aspect ratio, with black bars filling the rest of the window
+    #[default]
+    Letterbox,
+    // Fill the window, distorting the image if the aspect ratios differ
+    Stretch,
+    // Whole multiples of the target size with nearest filtering, letterboxed
+    Pixel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResizePolicy {
+    // Render at this internal resolution whatever the window size. Follows the window when unset.
+    pub fixed: Option<[u32; 2]>,
+    #[serde(default)]
+    pub scaling: Scaling,
+    // Rescale the old back buffer into the new one on resize, rather than starting from black
+    #[serde(default = "default_preserve_feedback")]
+    pub preserve_feedback: bool,
+}
+
+impl Default for ResizePolicy {
+    fn default() -> Self {
+        Self {
+            fixed: None,
+            scaling: Scaling::default(),
+            preserve_feedback: default_preserve_feedback(),
+        }
+    }
+}
+
+impl ResizePolicy {
+    pub fn target_size(&self, window: (u32, u32)) -> (u32, u32) {
+        match self.fixed {
+            Some([w, h]) => (w.max(1), h.max(1)),
+            None => window,
+        }
+    }
+
+    // Where a `target` sized image lands in the window, as x, y, width, height in window pixels
+    pub fn viewport(&self, target: (u32, u32), window: (u32, u32)) -> [f32; 4] {
+        let (tw, th) = (target.0 as f32, target.1 as f32);
+        let (ww, wh) = (window.0 as f32, window.1 as f32);
+        if self.fixed.is_none() || self.scaling == Scaling::Stretch {
+            return [0.0, 0.0, ww, wh];
+        }
+
+        let fit = (ww / tw).min(wh / th);
+        let scale = match self.scaling {
+            // Fall back to a fractional downscale when the window is smaller than the target
+            Scaling::Pixel if fit >= 1.0 => fit.floor(),
+            _ => fit,
+        };
+        let (w, h) = ((tw * scale).min(ww), (th * scale).min(wh));
+        [((ww - w) / 2.0).floor(), ((wh - h) / 2.0).floor(), w, h]
+    }
+
+    // A window position as 0-1 across the render target from its top left. Past 0-1 over any
+    // letterbox bars.
+    pub fn window_to_uv(&self, window: (u32, u32), position: (f32, f32)) -> (f32, f32) {
+        let [x, y, w, h] = self.viewport(self.target_size(window), window);
+        ((position.0 - x) / w.max(1.0), (position.1 - y) / h.max(1.0))
+    }
+
+    // The `target` pixel under a window position, or None if the target isn't drawn there
+    pub fn window_to_target(
+        &self,
+        target: (u32, u32),
+        window: (u32, u32),
+        position: (f32, f32),
+    ) -> Option<(u32, u32)> {
+        let [x, y, w, h] = self.viewport(target, window);
+        let (u, v) = ((position.0 - x) / w, (position.1 - y) / h);
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        Some(((u * target.0 as f32) as u32, (v * target.1 as f32) as u32))
+    }
+
+    fn filter(&self) -> wgpu::FilterMode {
+        match self.scaling {
+            Scaling::Pixel => wgpu::FilterMode::Nearest,
+            _ => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+// Draws one texture into a viewport of another with a fullscreen triangle
+pub struct Blitter {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    linear: wgpu::Sampler,
+    nearest: wgpu::Sampler,
+}
+
+impl Blitter {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("blit_bind_group_layout"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let sampler = |filter| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter: filter,
+                ..Default::default()
+            })
+        };
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            linear: sampler(wgpu::FilterMode::Linear),
+            nearest: sampler(wgpu::FilterMode::Nearest),
+        }
+    }
+
+    // Clear `dest` to black and draw `source` into the `viewport` rect of it
+    pub fn blit(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        dest: &wgpu::TextureView,
+        viewport: [f32; 4],
+        filter: wgpu::FilterMode,
+    ) {
+        let sampler = match filter {
+            wgpu::FilterMode::Nearest => &self.nearest,
+            wgpu::FilterMode::Linear => &self.linear,
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("blit_bind_group"),
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: dest,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let [x, y, w, h] = viewport;
+        pass.set_viewport(x, y, w, h, 0.0, 1.0);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    // Present a render target to the window according to the policy
+    pub fn present(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        policy: &ResizePolicy,
+        source: (&wgpu::TextureView, (u32, u32)),
+        dest: (&wgpu::TextureView, (u32, u32)),
+    ) {
+        let viewport = policy.viewport(source.1, dest.1);
+        self.blit(device, encoder, source.0, dest.0, viewport, policy.filter());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(width: u32, height: u32, scaling: Scaling) -> ResizePolicy {
+        ResizePolicy {
+            fixed: Some([width, height]),
+            scaling,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn follows_the_window_without_a_fixed_size() {
+        let policy = ResizePolicy::default();
+        assert_eq!(policy.target_size((640, 480)), (640, 480));
+        assert_eq!(
+            policy.viewport((640, 480), (640, 480)),
+            [0.0, 0.0, 640.0, 480.0]
+        );
+    }
+
+    #[test]
+    fn letterboxes_wide_and_tall_windows() {
+        let policy = fixed(100, 100, Scaling::Letterbox);
+        assert_eq!(policy.target_size((300, 200)), (100, 100));
+        // Bars left and right
+        assert_eq!(
+            policy.viewport((100, 100), (300, 200)),
+            [50.0, 0.0, 200.0, 200.0]
+        );
+        // Bars above and below
+        assert_eq!(
+            policy.viewport((100, 100), (200, 300)),
+            [0.0, 50.0, 200.0, 200.0]
+        );
+    }
+
+    #[test]
+    fn stretch_fills_the_window() {
+        let policy = fixed(100, 100, Scaling::Stretch);
+        assert_eq!(
+            policy.viewport((100, 100), (300, 200)),
+            [0.0, 0.0, 300.0, 200.0]
+        );
+    }
+
+    #[test]
+    fn pixel_scaling_uses_whole_multiples() {
+        let policy = fixed(100, 50, Scaling::Pixel);
+        // 3.5x fits, so 3x, centred
+        assert_eq!(
+            policy.viewport((100, 50), (350, 180)),
+            [25.0, 15.0, 300.0, 150.0]
+        );
+        // Smaller than the target falls back to a fractional downscale
+        assert_eq!(
+            policy.viewport((100, 50), (50, 50)),
+            [0.0, 12.0, 50.0, 25.0]
+        );
+    }
+
+    #[test]
+    fn maps_window_positions_into_the_target() {
+        let policy = fixed(100, 100, Scaling::Letterbox);
+        let (target, window) = ((100, 100), (300, 200));
+        assert_eq!(
+            policy.window_to_target(target, window, (150.0, 100.0)),
+            Some((50, 50))
+        );
+        assert_eq!(
+            policy.window_to_target(target, window, (50.0, 0.0)),
+            Some((0, 0))
+        );
+        assert_eq!(
+            policy.window_to_target(target, window, (249.0, 199.0)),
+            Some((99, 99))
+        );
+        // In the bars
+        assert_eq!(policy.window_to_target(target, window, (25.0, 100.0)), None);
+        assert_eq!(
+            policy.window_to_target(target, window, (250.0, 100.0)),
+            None
+        );
+
+        assert_eq!(policy.window_to_uv(window, (150.0, 100.0)), (0.5, 0.5));
+        assert_eq!(policy.window_to_uv(window, (0.0, 0.0)), (-0.25, 0.0));
+        // A window that hasn't been sized yet
+        assert_eq!(
+            ResizePolicy::default().window_to_uv((0, 0), (0.0, 0.0)),
+            (0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,418 @@
+// Recording a live session to replay it later, frame for frame. A session file is JSON lines: a
+// header, then a line per frame drawn with the window events it got, the params that changed,
+// the clock time and a hash of any new camera frame:
+//
+//     {"version":1,"project":"/jam/scene.toml","window":[1280,720],"shader":"9f86d0..."}
+//     {"at":0.0,"time":0.0,"params":{"speed":1.0,"zoom":2.0}}
+//     {"at":0.016,"time":0.016,"events":[{"event":"cursor_moved","x":640.0,"y":360.0}]}
+//
+// Replay steps through the recorded wall clock at a fixed rate, so a session plays the same
+// however fast it's drawn. The camera isn't stored, just its hashes, so a session that used it
+// only replays with --no-camera, leaving videoBuffer black.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+        TouchPhase, WindowEvent,
+    },
+};
+
+use crate::{
+    capture,
+    cli::ReplayArgs,
+    offline::{self, Offline},
+    project::Project,
+};
+
+const VERSION: u32 = 1;
+
+pub enum Session {
+    Recording(Recorder),
+    Replaying(Replay),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    // The project file, or the shader when it has none yet. Absolute, so it replays from anywhere.
+    pub project: PathBuf,
+    // In window pixels, when recording started
+    pub window: (u32, u32),
+    // Of the shader source, to tell when it's been edited since
+    pub shader: String,
+}
+
+impl Header {
+    pub fn new(project: &Project, window: (u32, u32), source: &str) -> Self {
+        // Project::open takes either
+        let path = match project.path().exists() {
+            true => project.path().to_owned(),
+            false => project.shader_path(),
+        };
+        Self {
+            version: VERSION,
+            project: fs::canonicalize(&path).unwrap_or(path),
+            window,
+            shader: capture::hash(source.as_bytes()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Frame {
+    // Wall clock seconds since the first frame
+    at: f64,
+    // Shader time
+    time: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    events: Vec<Input>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    params: BTreeMap<String, f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    camera: Option<String>,
+}
+
+// The window events that affect what's drawn, without winit's device ids
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Input {
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    CursorEntered,
+    CursorLeft,
+    Mouse {
+        state: ElementState,
+        button: MouseButton,
+    },
+    MouseWheel {
+        delta: MouseScrollDelta,
+        phase: TouchPhase,
+    },
+    Keyboard {
+        input: KeyboardInput,
+    },
+    Modifiers {
+        modifiers: ModifiersState,
+    },
+    Character {
+        char: char,
+    },
+    Focused {
+        focused: bool,
+    },
+}
+
+impl Input {
+    fn from_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::Resized(size) => Input::Resized {
+                width: size.width,
+                height: size.height,
+            },
+            // Replayed as the resize it causes, the scale factor is the display's
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => Input::Resized {
+                width: new_inner_size.width,
+                height: new_inner_size.height,
+            },
+            WindowEvent::CursorMoved { position, .. } => Input::CursorMoved {
+                x: position.x,
+                y: position.y,
+            },
+            WindowEvent::CursorEntered { .. } => Input::CursorEntered,
+            WindowEvent::CursorLeft { .. } => Input::CursorLeft,
+            WindowEvent::MouseInput { state, button, .. } => Input::Mouse {
+                state: *state,
+                button: *button,
+            },
+            WindowEvent::MouseWheel { delta, phase, .. } => Input::MouseWheel {
+                delta: *delta,
+                phase: *phase,
+            },
+            WindowEvent::KeyboardInput { input, .. } => Input::Keyboard { input: *input },
+            WindowEvent::ModifiersChanged(modifiers) => Input::Modifiers {
+                modifiers: *modifiers,
+            },
+            WindowEvent::ReceivedCharacter(char) => Input::Character { char: *char },
+            WindowEvent::Focused(focused) => Input::Focused { focused: *focused },
+            _ => return None,
+        })
+    }
+
+    #[allow(deprecated)]
+    fn to_event(&self) -> WindowEvent<'static> {
+        // Nothing here looks at which device an event came from
+        let device_id = unsafe { DeviceId::dummy() };
+        let modifiers = ModifiersState::empty();
+        match self.clone() {
+            Input::Resized { width, height } => {
+                WindowEvent::Resized(PhysicalSize::new(width, height))
+            }
+            Input::CursorMoved { x, y } => WindowEvent::CursorMoved {
+                device_id,
+                position: PhysicalPosition::new(x, y),
+                modifiers,
+            },
+            Input::CursorEntered => WindowEvent::CursorEntered { device_id },
+            Input::CursorLeft => WindowEvent::CursorLeft { device_id },
+            Input::Mouse { state, button } => WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                modifiers,
+            },
+            Input::MouseWheel { delta, phase } => WindowEvent::MouseWheel {
+                device_id,
+                delta,
+                phase,
+                modifiers,
+            },
+            Input::Keyboard { input } => WindowEvent::KeyboardInput {
+                device_id,
+                input,
+                is_synthetic: false,
+            },
+            Input::Modifiers { modifiers } => WindowEvent::ModifiersChanged(modifiers),
+            Input::Character { char } => WindowEvent::ReceivedCharacter(char),
+            Input::Focused { focused } => WindowEvent::Focused(focused),
+        }
+    }
+}
+
+pub struct Recorder {
+    file: BufWriter<File>,
+    // When the first frame was written
+    started: Option<Instant>,
+    // Since the last frame
+    events: Vec<Input>,
+    camera: Option<String>,
+    // As of the last frame, to write only what changed
+    params: BTreeMap<String, f32>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &Header) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut file, header)?;
+        writeln!(file)?;
+        Ok(Self {
+            file,
+            started: None,
+            events: vec![],
+            camera: None,
+            params: BTreeMap::new(),
+        })
+    }
+
+    pub fn event(&mut self, event: &WindowEvent) {
+        self.events.extend(Input::from_event(event));
+    }
+
+    pub fn camera(&mut self, rgba: &[u8]) {
+        self.camera = Some(capture::hash(rgba));
+    }
+
+    // Finish the frame, flushing so a crash loses nothing before it
+    pub fn frame(&mut self, time: f32, params: BTreeMap<String, f32>) -> io::Result<()> {
+        let at = match self.started {
+            Some(started) => started.elapsed().as_secs_f64(),
+            None => {
+                self.started = Some(Instant::now());
+                0.0
+            }
+        };
+        let changed = params
+            .iter()
+            .filter(|(name, value)| self.params.get(*name) != Some(value))
+            .map(|(name, value)| (name.clone(), *value))
+            .collect();
+        self.params = params;
+
+        let frame = Frame {
+            at,
+            time,
+            events: std::mem::take(&mut self.events),
+            params: changed,
+            camera: self.camera.take(),
+        };
+        serde_json::to_writer(&mut self.file, &frame)?;
+        writeln!(self.file)?;
+        self.file.flush()
+    }
+}
+
+// What to apply for one replayed frame
+pub struct Step {
+    pub events: Vec<WindowEvent<'static>>,
+    pub time: f32,
+    // Every param recorded so far, not only the ones that changed, so nothing live drifts them
+    pub params: BTreeMap<String, f32>,
+}
+
+pub struct Replay {
+    pub header: Header,
+    frames: Vec<Frame>,
+    fps: f32,
+    step: u32,
+    // The first frame not yet applied
+    next: usize,
+    params: BTreeMap<String, f32>,
+}
+
+impl Replay {
+    pub fn open(args: &ReplayArgs) -> io::Result<Self> {
+        let path = &args.session;
+        let text = fs::read_to_string(path)?;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let invalid = |line: usize, e: serde_json::Error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {e}", path.display(), line + 1),
+            )
+        };
+
+        let Some((line, header)) = lines.next() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is empty", path.display()),
+            ));
+        };
+        let header: Header = serde_json::from_str(header).map_err(|e| invalid(line, e))?;
+        if header.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is a version {} session, only version {VERSION} can be replayed",
+                    path.display(),
+                    header.version
+                ),
+            ));
+        }
+        let frames = lines
+            .map(|(line, frame)| serde_json::from_str(frame).map_err(|e| invalid(line, e)))
+            .collect::<io::Result<Vec<Frame>>>()?;
+
+        // It wouldn't be the same replay without them
+        let camera_frames = frames.iter().filter(|f| f.camera.is_some()).count();
+        if camera_frames > 0 && !args.no_camera {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} used the camera for {camera_frames} frames, which can't be replayed. \
+                     Pass --no-camera to replay it with videoBuffer black.",
+                    path.display()
+                ),
+            ));
+        }
+
+        Ok(Self {
+            header,
+            frames,
+            fps: args.fps,
+            step: 0,
+            next: 0,
+            params: BTreeMap::new(),
+        })
+    }
+
+    // Warn if the shader's been edited since, which will draw something different
+    pub fn check_shader(&self, source: &str) {
+        if capture::hash(source.as_bytes()) != self.header.shader {
+            println!("The shader has changed since this session was recorded");
+        }
+    }
+
+    // How many steps the whole session takes
+    pub fn steps(&self) -> u32 {
+        let length = self.frames.last().map_or(0.0, |f| f.at);
+        (length * self.fps as f64).ceil() as u32 + 1
+    }
+
+    // The next step, 1/fps seconds of the recording on from the last, until it runs out. Every
+    // recorded frame up to then is applied, so events aren't lost when stepping slower than
+    // the recording.
+    pub fn step(&mut self) -> Option<Step> {
+        if self.next >= self.frames.len() {
+            return None;
+        }
+        let at = self.step as f64 / self.fps as f64;
+        self.step += 1;
+
+        let mut events = vec![];
+        while let Some(frame) = self.frames.get(self.next).filter(|f| f.at <= at) {
+            events.extend(frame.events.iter().map(Input::to_event));
+            self.params
+                .extend(frame.params.iter().map(|(n, v)| (n.clone(), *v)));
+            self.next += 1;
+        }
+
+        // Between recorded frames the clock moves on at the rate it did, unless it jumped back
+        let last = &self.frames[self.next.max(1) - 1];
+        let time = match self.frames.get(self.next) {
+            Some(next) if next.time >= last.time && next.at > last.at => {
+                let t = ((at - last.at) / (next.at - last.at)).clamp(0.0, 1.0) as f32;
+                last.time + (next.time - last.time) * t
+            }
+            _ => last.time,
+        };
+
+        Some(Step {
+            events,
+            time,
+            params: self.params.clone(),
+        })
+    }
+}
+
+// Render a session through the offline renderer instead of a window, to a video like export
+pub async fn render(args: &ReplayArgs, out: &Path) -> io::Result<()> {
+    let mut replay = Replay::open(args)?;
+    let project_path = replay.header.project.clone();
+    let (width, height) = replay.header.window;
+    let size = match args.size {
+        Some(size) => size,
+        None => Project::open(&project_path)?
+            .resolution
+            .target_size((width, height)),
+    };
+
+    let mut offline = Offline::open(&project_path, &args.gpu, Some(size)).await?;
+    replay.check_shader(&offline.renderer.rpcontext.read().frag_source);
+    offline.window_event(&WindowEvent::Resized(PhysicalSize::new(width, height)));
+    let mut sink = offline::sink(out, size, args.fps)?;
+
+    let steps = replay.steps();
+    let mut rendered = 0;
+    while let Some(step) = replay.step() {
+        for event in &step.events {
+            offline.window_event(event);
+        }
+        offline.renderer.params.apply(&step.params);
+        let mut rgba = offline.frame(step.time)?;
+        sink.write(&mut rgba)?;
+        rendered += 1;
+        println!("Replay: {rendered}/{steps} frames");
+    }
+    sink.finish()?;
+    println!("Rendered replay to {}", out.display());
+    offline.print_timings();
+    Ok(())
+}
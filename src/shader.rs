@@ -0,0 +1,623 @@
+// Turning the user's fragment shader into the WGSL the pipeline compiles, and mapping positions in
+// that WGSL back to the user's files.
+//
+// Shaders can pull in other files with a comment like:
+//
+//     // @include "noise.wgsl"
+//
+// Paths are relative to the including file, and each file is only included once. `.glsl` and
+// `.frag` files are Shadertoy-style GLSL, which includes the same way and goes through `glsl`.
+// `.spv` files are SPIR-V binaries, which go through `spirv`.
+//
+// In WGSL, each `debug_print(pixel, value)` call gets a site number put in as its first argument,
+// so what it logs can be shown next to the line it came from.
+
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    glsl,
+    input::InputLayout,
+    params::{self, ParamDecl},
+    spirv,
+};
+
+// Something the workbench declares for every shader
+pub struct PreludeItem {
+    pub name: &'static str,
+    pub wgsl: &'static str,
+    pub doc: &'static str,
+}
+
+pub const PRELUDE: &[PreludeItem] = &[
+    PreludeItem {
+        name: "res",
+        wgsl: "@group(0) @binding(0) var<uniform> res: vec2<f32>;",
+        doc: "Size of the canvas in pixels. In poster renders this is the whole poster, not the tile.",
+    },
+    PreludeItem {
+        name: "frame",
+        wgsl: "@group(0) @binding(1) var<uniform> frame: u32;",
+        doc: "Number of frames rendered so far.",
+    },
+    PreludeItem {
+        name: "videoBuffer",
+        wgsl: "@group(0) @binding(2) var videoBuffer: texture_2d<f32>;",
+        doc: "The latest camera frame. A single black pixel when running without a camera.",
+    },
+    PreludeItem {
+        name: "videoSampler",
+        wgsl: "@group(0) @binding(3) var videoSampler: sampler;",
+        doc: "Linear, clamp-to-edge sampler for videoBuffer.",
+    },
+    PreludeItem {
+        name: "time",
+        wgsl: "@group(0) @binding(4) var<uniform> time: f32;",
+        doc: "Clock time in seconds. Pauses with Space, and jumps when presets are recalled.",
+    },
+    PreludeItem {
+        name: "viewport",
+        wgsl: "@group(0) @binding(6) var<uniform> viewport: vec4<f32>;",
+        doc: "xy: this render's offset into the virtual canvas, z: canvas pixels per window pixel.",
+    },
+    PreludeItem {
+        name: "backBuffer",
+        wgsl: "@group(1) @binding(0) var backBuffer: texture_2d<f32>;",
+        doc: "The previous frame, for feedback effects. Always the size of the render target.",
+    },
+    PreludeItem {
+        name: "backSampler",
+        wgsl: "@group(1) @binding(1) var backSampler: sampler;",
+        doc: "Linear, clamp-to-edge sampler for backBuffer.",
+    },
+    PreludeItem {
+        name: "fragCoord",
+        wgsl: "fn fragCoord(pos: vec4<f32>) -> vec2<f32> { return pos.xy + viewport.xy; }",
+        doc: "Pixel position on the whole canvas. Use this over pos.xy to stay seamless in poster renders.",
+    },
+    // Fragment shaders can't write storage buffers on WebGL
+    #[cfg(not(target_arch = "wasm32"))]
+    PreludeItem {
+        name: "debugLog",
+        wgsl: "@group(3) @binding(0) var<storage, read_write> debugLog: array<u32>;",
+        doc: "Where debug_print writes for the picked pixel. Read back by the workbench, not for direct use.",
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    PreludeItem {
+        name: "debug_log",
+        wgsl: "fn debug_log(site: u32, pixel: vec2<f32>, value: vec4<f32>, size: u32) { \
+            if any(vec2<u32>(pixel) != vec2(debugLog[0], debugLog[1])) { return; } \
+            let i = debugLog[2]; debugLog[2] = i + 1u; if i >= 64u { return; } \
+            let at = 4u + i * 8u; debugLog[at] = site; debugLog[at + 1u] = size; \
+            for (var c = 0u; c < 4u; c++) { debugLog[at + 4u + c] = bitcast<u32>(value[c]); } }",
+        doc: "What the debug_print functions share.",
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    PreludeItem {
+        name: "debug_print",
+        wgsl: "fn debug_print(site: u32, pixel: vec2<f32>, value: f32) { debug_log(site, pixel, vec4(value, 0.0, 0.0, 0.0), 1u); }",
+        doc: "Log a value for the pixel Ctrl+clicked in the window, as `debug_print(pos.xy, value)`. `site` is filled in by the workbench.",
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    PreludeItem {
+        name: "debug_print2",
+        wgsl: "fn debug_print2(site: u32, pixel: vec2<f32>, value: vec2<f32>) { debug_log(site, pixel, vec4(value, 0.0, 0.0), 2u); }",
+        doc: "debug_print for a vec2<f32>.",
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    PreludeItem {
+        name: "debug_print3",
+        wgsl: "fn debug_print3(site: u32, pixel: vec2<f32>, value: vec3<f32>) { debug_log(site, pixel, vec4(value, 0.0), 3u); }",
+        doc: "debug_print for a vec3<f32>.",
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    PreludeItem {
+        name: "debug_print4",
+        wgsl: "fn debug_print4(site: u32, pixel: vec2<f32>, value: vec4<f32>) { debug_log(site, pixel, value, 4u); }",
+        doc: "debug_print for a vec4<f32>.",
+    },
+];
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+// A file's position for a point in the composed WGSL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    // Index into FragShader::files
+    pub file: usize,
+    // Byte offset into the file
+    pub offset: usize,
+    // 1-based
+    pub line: u32,
+    pub column: u32,
+}
+
+pub struct IncludeError {
+    pub file: usize,
+    // 1-based
+    pub line: u32,
+    pub message: String,
+}
+
+// Something the GLSL or SPIR-V front-end rejected, with where it is if that's in the user's files
+pub struct ImportError {
+    pub location: Option<Location>,
+    // In bytes
+    pub length: usize,
+    pub message: String,
+}
+
+// What the user's shader is written in, going by its extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    Wgsl,
+    Glsl,
+    SpirV,
+}
+
+impl Language {
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("glsl" | "frag") => Self::Glsl,
+            Some("spv") => Self::SpirV,
+            _ => Self::Wgsl,
+        }
+    }
+}
+
+// A `debug_print` call, with its line for showing next to what it logged
+#[derive(Clone, Debug)]
+pub struct DebugSite {
+    pub path: PathBuf,
+    // 1-based
+    pub line: u32,
+    pub text: String,
+}
+
+// A run of `source` copied verbatim from one file
+struct Segment {
+    start: usize,
+    len: usize,
+    file: usize,
+    file_start: usize,
+}
+
+pub struct FragShader {
+    // The user's code with includes expanded, without the prelude. For SPIR-V, the WGSL it
+    // translates to.
+    pub source: String,
+    pub language: Language,
+    // For GLSL and SPIR-V, translated by naga, and empty if there are import errors
+    pub wgsl: String,
+    pub param_decls: Vec<ParamDecl>,
+    // The main file first, then includes in the order they were pulled in
+    pub files: Vec<SourceFile>,
+    pub include_errors: Vec<IncludeError>,
+    pub import_errors: Vec<ImportError>,
+    // Indexed by the site number each call is given
+    pub debug_sites: Vec<DebugSite>,
+    segments: Vec<Segment>,
+    // Where `source` starts in `wgsl`
+    header: usize,
+    // The module as read, for SPIR-V
+    spirv: Vec<u8>,
+}
+
+impl FragShader {
+    pub fn new(path: &Path, source: String) -> Self {
+        Self::with_loader(path, source, |p| fs::read_to_string(p))
+    }
+
+    pub fn from_spirv(path: &Path, spirv: Vec<u8>) -> Self {
+        let mut frag = Self::with_loader(path, String::new(), |_| {
+            Err(io::ErrorKind::Unsupported.into())
+        });
+        frag.spirv = spirv;
+        frag.import_spirv(&InputLayout::default());
+        frag
+    }
+
+    // Like new, but reading includes through `load`, for editors with unsaved buffers
+    pub fn with_loader(
+        path: &Path,
+        source: String,
+        load: impl Fn(&Path) -> io::Result<String>,
+    ) -> Self {
+        let mut frag = Self {
+            source: String::new(),
+            language: Language::of(path),
+            wgsl: String::new(),
+            param_decls: vec![],
+            files: vec![SourceFile {
+                path: canonical(path),
+                text: source,
+            }],
+            include_errors: vec![],
+            import_errors: vec![],
+            debug_sites: vec![],
+            segments: vec![],
+            header: 0,
+            spirv: vec![],
+        };
+
+        let mut expanded = String::new();
+        frag.expand(0, &mut expanded, &load);
+        frag.param_decls = params::parse_decls(&expanded);
+        frag.source = expanded;
+
+        match frag.language {
+            Language::Wgsl => {
+                let prelude: Vec<&str> = PRELUDE.iter().map(|item| item.wgsl).collect();
+                let header = [
+                    &prelude.join("\n"),
+                    &params::wgsl_struct(&frag.param_decls),
+                    "",
+                ]
+                .join("\n");
+                frag.header = header.len();
+                frag.wgsl = header + &frag.source;
+            }
+            Language::Glsl => frag.import_glsl(&InputLayout::default()),
+            Language::SpirV => frag.import_spirv(&InputLayout::default()),
+        }
+        frag
+    }
+
+    // Translate `source` to WGSL, with `inputs` declared
+    fn import_glsl(&mut self, inputs: &InputLayout) {
+        self.wgsl.clear();
+        let mut problems = glsl::check(&self.source, inputs);
+        if problems.is_empty() {
+            let (composed, header) = glsl::compose(&self.source, &self.param_decls, inputs);
+            match glsl::translate(&composed) {
+                Ok(wgsl) => self.wgsl = wgsl,
+                Err(errors) => {
+                    // Anything outside the user's code is ours
+                    let user = header..header + self.source.len();
+                    problems = errors
+                        .into_iter()
+                        .map(|(range, message)| {
+                            let range = range.filter(|r| user.contains(&r.start));
+                            (range.map(|r| r.start - header..r.end - header), message)
+                        })
+                        .collect();
+                }
+            }
+        }
+        self.import_errors = problems
+            .into_iter()
+            .map(|(range, message)| ImportError {
+                location: range.as_ref().and_then(|r| self.locate(r.start)),
+                length: range.map_or(0, |r| r.len()),
+                message,
+            })
+            .collect();
+    }
+
+    // Translate the binary to WGSL, checking it against `inputs` and the prelude
+    fn import_spirv(&mut self, inputs: &InputLayout) {
+        self.source.clear();
+        (self.wgsl, self.import_errors) = match spirv::translate(&self.spirv, inputs) {
+            Ok(wgsl) => {
+                self.source.clone_from(&wgsl);
+                (wgsl, vec![])
+            }
+            Err(problems) => {
+                let errors = problems
+                    .into_iter()
+                    .map(|message| ImportError {
+                        location: None,
+                        length: 0,
+                        message,
+                    })
+                    .collect();
+                (String::new(), errors)
+            }
+        };
+    }
+
+    fn expand(
+        &mut self,
+        file: usize,
+        out: &mut String,
+        load: &impl Fn(&Path) -> io::Result<String>,
+    ) {
+        let text = self.files[file].text.clone();
+        let mut run_start = 0;
+        let mut offset = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let line_start = offset;
+            offset += line.len();
+            let Some(target) = include_target(line) else {
+                continue;
+            };
+
+            self.push_code(out, file, &text, run_start..line_start);
+            run_start = offset;
+
+            let dir = self.files[file].path.parent().unwrap_or(Path::new(""));
+            let path = canonical(&dir.join(target));
+            if self.files.iter().any(|f| f.path == path) {
+                continue;
+            }
+            match load(&path) {
+                Ok(text) => {
+                    self.files.push(SourceFile { path, text });
+                    self.expand(self.files.len() - 1, out, load);
+                    out.push('\n');
+                }
+                Err(e) => self.include_errors.push(IncludeError {
+                    file,
+                    line: i as u32 + 1,
+                    message: format!("Can't include {target}: {e}"),
+                }),
+            }
+        }
+        self.push_code(out, file, &text, run_start..text.len());
+    }
+
+    // Copy a run of a file, numbering WGSL's debug_print calls. The numbers aren't in the file,
+    // so they're left out of the segments.
+    fn push_code(
+        &mut self,
+        out: &mut String,
+        file: usize,
+        text: &str,
+        range: std::ops::Range<usize>,
+    ) {
+        let mut start = range.start;
+        if self.language == Language::Wgsl {
+            for (call, args) in debug_calls(&text[range.clone()]) {
+                let (call, args) = (range.start + call, range.start + args);
+                self.push_segment(out, file, text, start..args);
+                start = args;
+
+                out.push_str(&format!("{}u, ", self.debug_sites.len()));
+                let (line, _) = line_column(text, call);
+                let line_start = text[..call].rfind('\n').map_or(0, |i| i + 1);
+                let line_text = text[line_start..].lines().next().unwrap_or_default();
+                self.debug_sites.push(DebugSite {
+                    path: self.files[file].path.clone(),
+                    line,
+                    text: line_text.trim().to_owned(),
+                });
+            }
+        }
+        self.push_segment(out, file, text, start..range.end);
+    }
+
+    fn push_segment(
+        &mut self,
+        out: &mut String,
+        file: usize,
+        text: &str,
+        range: std::ops::Range<usize>,
+    ) {
+        if range.is_empty() {
+            return;
+        }
+        self.segments.push(Segment {
+            start: out.len(),
+            len: range.len(),
+            file,
+            file_start: range.start,
+        });
+        out.push_str(&text[range]);
+    }
+
+    // Declare the input bindings, which don't come from the prelude. WGSL gets them put ahead of
+    // everything else, GLSL is translated again with them, and SPIR-V checked against them.
+    pub fn declare(&mut self, inputs: &InputLayout) {
+        match self.language {
+            Language::Wgsl => {}
+            Language::Glsl => return self.import_glsl(inputs),
+            Language::SpirV => return self.import_spirv(inputs),
+        }
+        let wgsl = inputs.wgsl();
+        if wgsl.is_empty() {
+            return;
+        }
+        let header = format!("{wgsl}\n");
+        self.header += header.len();
+        self.wgsl.insert_str(0, &header);
+    }
+
+    // An import error as `file:line:column: message`, for printing
+    pub fn describe(&self, e: &ImportError) -> String {
+        match e.location {
+            Some(loc) => format!(
+                "{}:{}:{}: {}",
+                self.files[loc.file].path.display(),
+                loc.line,
+                loc.column,
+                e.message
+            ),
+            None => format!("{}: {}", self.files[0].path.display(), e.message),
+        }
+    }
+
+    pub fn include_paths(&self) -> Vec<PathBuf> {
+        self.files[1..].iter().map(|f| f.path.clone()).collect()
+    }
+
+    // Where a byte offset into `wgsl` came from. None for generated code, which is all of it for
+    // GLSL and SPIR-V.
+    pub fn location(&self, offset: usize) -> Option<Location> {
+        if self.language != Language::Wgsl {
+            return None;
+        }
+        self.locate(offset.checked_sub(self.header)?)
+    }
+
+    // Where a byte offset into `source` came from
+    fn locate(&self, offset: usize) -> Option<Location> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|s| (s.start..s.start + s.len).contains(&offset))?;
+        let file_offset = segment.file_start + offset - segment.start;
+        let (line, column) = line_column(&self.files[segment.file].text, file_offset);
+        Some(Location {
+            file: segment.file,
+            offset: file_offset,
+            line,
+            column,
+        })
+    }
+}
+
+// 1-based line and column of a byte offset
+pub fn line_column(text: &str, offset: usize) -> (u32, u32) {
+    let prefix = &text[..offset.min(text.len())];
+    let line = prefix.matches('\n').count() + 1;
+    let line_start = prefix.rfind('\n').map_or(0, |i| i + 1);
+    let column = prefix[line_start..].chars().count() + 1;
+    (line as u32, column as u32)
+}
+
+// Where each debug_print call starts, and where its arguments do. Calls in comments don't count.
+fn debug_calls(text: &str) -> Vec<(usize, usize)> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut calls = vec![];
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += block_comment_len(rest);
+        } else if let Some(after) = rest.strip_prefix("debug_print") {
+            let name = after.strip_prefix(['2', '3', '4']).unwrap_or(after);
+            let in_name = text[..i].chars().next_back().is_some_and(is_ident);
+            if let Some(args) = name.trim_start().strip_prefix('(').filter(|_| !in_name) {
+                calls.push((i, text.len() - args.len()));
+            }
+            i += "debug_print".len();
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    calls
+}
+
+// Bytes up to the end of the block comment `text` starts with. WGSL's block comments nest.
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if rest.starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    text.len()
+}
+
+fn include_target(line: &str) -> Option<&str> {
+    let rest = line
+        .trim()
+        .strip_prefix("//")?
+        .trim()
+        .strip_prefix("@include")?;
+    rest.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+// naga's validation errors nest, with the useful part at the bottom
+pub fn error_chain(e: &dyn Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message += &format!(": {e}");
+        source = e.source();
+    }
+    message
+}
+
+// So the same file reached by different relative paths is recognised
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+pub fn read_frag_shader(path: &Path) -> io::Result<FragShader> {
+    match Language::of(path) {
+        Language::SpirV => Ok(FragShader::from_spirv(path, fs::read(path)?)),
+        _ => Ok(FragShader::new(path, fs::read_to_string(path)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_names(text: &str) -> Vec<&str> {
+        debug_calls(text)
+            .into_iter()
+            .map(|(call, args)| text[call..args].trim_end_matches(['(', ' ']))
+            .collect()
+    }
+
+    #[test]
+    fn finds_debug_print_calls() {
+        let text = "debug_print(p, a); debug_print3 (p, b);\nx = debug_print4(p, c);";
+        assert_eq!(
+            call_names(text),
+            vec!["debug_print", "debug_print3", "debug_print4"]
+        );
+        let (call, args) = debug_calls(text)[1];
+        assert_eq!(
+            (&text[call..call + 12], &text[args..args + 2]),
+            ("debug_print3", "p,")
+        );
+    }
+
+    #[test]
+    fn skips_other_names() {
+        let text = "my_debug_print(p, a); debug_printer(p, a); debug_print5(p, a); debug_print;";
+        assert!(debug_calls(text).is_empty());
+    }
+
+    #[test]
+    fn skips_calls_in_comments() {
+        let text = "// debug_print(p, a);
+            let b = 1.0; // then debug_print2(p, b)
+            /* debug_print(p, a); /* nested */ debug_print(p, a); */
+            /**/debug_print(p, a);
+            /* unterminated debug_print(p, a);";
+        let calls = debug_calls(text);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(line_column(text, calls[0].0), (4, 17));
+    }
+
+    #[test]
+    fn numbers_only_real_calls() {
+        let source = "// debug_print(pos.xy, 1.0);
+@fragment
+fn main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    /* debug_print(pos.xy, 2.0); */
+    debug_print(pos.xy, 3.0);
+    return vec4<f32>(1.0);
+}
+";
+        let frag = FragShader::new(Path::new("frag.wgsl"), source.to_owned());
+        assert_eq!(frag.debug_sites.len(), 1);
+        assert_eq!(frag.debug_sites[0].line, 5);
+        assert!(frag.wgsl.contains("debug_print(0u, pos.xy, 3.0)"));
+        assert!(frag.wgsl.contains("// debug_print(pos.xy, 1.0);"));
+        naga::front::wgsl::parse_str(&frag.wgsl).unwrap();
+    }
+}
@@ -0,0 +1,794 @@
+// Shadertoy's JSON, as its API and site export it, in and out of workbench projects.
+//
+// Importing writes the Image pass, with Common included if there is one, as Shadertoy-style GLSL
+// beside a project binding its channels. The workbench renders a single pass, so a buffer only
+// comes along when it feeds back into itself and the Image pass just shows it, the way exports
+// from here look: the buffer becomes the shader, reading itself as backBuffer. Other passes are
+// saved but not run.
+//
+// Exporting translates WGSL to GLSL ES with naga's GLSL back-end, keeping the prelude's uniforms
+// and defining them as Shadertoy's. Shaders reading backBuffer run in Buffer A, shown by the
+// Image pass.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use naga::{
+    back::glsl::{Options, PipelineOptions, Version, Writer},
+    proc::BoundsCheckPolicies,
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage,
+};
+
+use crate::{
+    cli::ShadertoyCommand,
+    glsl::{self, CHANNELS, MOUSE_INPUT},
+    input::{BindingKind, InputConfig, InputLayout, Inputs},
+    project::Project,
+    shader::{self, error_chain, FragShader, Language},
+    validate,
+};
+
+// The output ids Shadertoy gives the Image pass and Buffer A
+const IMAGE_ID: &str = "4dfGRr";
+const BUFFER_A_ID: &str = "4dXGR8";
+const BUFFER_A_PREVIEW: &str = "/media/previz/buffer00.png";
+
+// The prelude's uniforms in terms of Shadertoy's. The viewport is the renderer's pan and zoom,
+// `[offset.x, offset.y, scale, 0.0]`, which Shadertoy leaves at rest.
+const PRELUDE: &[(&str, &str)] = &[
+    ("res", "iResolution.xy"),
+    ("time", "iTime"),
+    ("frame", "uint(iFrame)"),
+    ("viewport", "vec4(0.0, 0.0, 1.0, 0.0)"),
+];
+
+// WGSL's texture coordinates run down from the top left, Shadertoy's up from the bottom left.
+// Renamed lookups flip them, under names the workbench's own flipping leaves alone on import.
+const LOOKUPS: &[(&str, &str)] = &[
+    (
+        "texture",
+        "#define workbench_texture(s, uv) texture(s, vec2((uv).x, 1.0 - (uv).y))",
+    ),
+    (
+        "textureLod",
+        "#define workbench_textureLod(s, uv, lod) textureLod(s, vec2((uv).x, 1.0 - (uv).y), lod)",
+    ),
+    (
+        "texelFetch",
+        "#define workbench_texelFetch(s, p, lod) \
+         texelFetch(s, ivec2((p).x, textureSize(s, lod).y - 1 - (p).y), lod)",
+    ),
+];
+
+// Image passes that only show Buffer A, what we export first
+const PASSTHROUGHS: &[&str] = &[
+    "// Shows Buffer A, where the shader runs so it can read its last frame
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    fragColor = texelFetch(iChannel0, ivec2(fragCoord), 0);
+}
+",
+    "void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    fragColor = texture(iChannel0, fragCoord / iResolution.xy);
+}",
+];
+
+#[derive(Serialize, Deserialize)]
+struct Shader {
+    #[serde(default)]
+    ver: String,
+    #[serde(default)]
+    info: Info,
+    renderpass: Vec<RenderPass>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Info {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    // Dates, view counts and flags, passed through
+    #[serde(flatten)]
+    rest: serde_json::Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RenderPass {
+    #[serde(default)]
+    inputs: Vec<PassInput>,
+    #[serde(default)]
+    outputs: Vec<PassOutput>,
+    code: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    description: String,
+    // image, buffer, common, sound or cubemap
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+// The API has `ctype` and `src` where site exports have `type` and `filepath`. Ids are numbers
+// in one and strings in the other.
+#[derive(Serialize, Deserialize)]
+struct PassInput {
+    id: Value,
+    #[serde(default, alias = "src")]
+    filepath: String,
+    // texture, buffer, webcam, video, keyboard, music, mic, cubemap...
+    #[serde(rename = "type", alias = "ctype")]
+    kind: String,
+    channel: usize,
+    #[serde(default)]
+    sampler: Sampler,
+    #[serde(default)]
+    published: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PassOutput {
+    id: Value,
+    channel: usize,
+}
+
+// Booleans are usually the strings "true" and "false"
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Sampler {
+    filter: String,
+    wrap: String,
+    vflip: Value,
+    srgb: Value,
+    internal: String,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            filter: "linear".to_owned(),
+            wrap: "clamp".to_owned(),
+            vflip: json!("true"),
+            srgb: json!("false"),
+            internal: "byte".to_owned(),
+        }
+    }
+}
+
+// What a channel reads, in terms of workbench inputs
+enum Channel {
+    Feedback,
+    Camera,
+    Image(String),
+    Video(String),
+}
+
+fn id(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// Lowercase letters, digits and underscores, or None if nothing's left
+fn slug(name: &str) -> Option<String> {
+    let slug: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect();
+    let slug = slug.trim_matches('_').to_owned();
+    (!slug.is_empty()).then_some(slug)
+}
+
+// Code without comments or whitespace, to compare passes by
+fn squeeze(code: &str) -> String {
+    code.lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .flat_map(str::chars)
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+pub fn run(command: &ShadertoyCommand) -> io::Result<()> {
+    match command {
+        ShadertoyCommand::Import(args) => import(&args.path, args.out.as_deref()).map(|_| ()),
+        ShadertoyCommand::Export(args) => export(&args.path, &args.out),
+    }
+}
+
+// The shader in a file from the API, which wraps it in `Shader`, or saved from the site, which
+// may hold a list of them
+fn read(path: &Path) -> io::Result<Shader> {
+    let mut value: Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| invalid(format!("{}: {e}", path.display())))?;
+    if let Some(shader) = value.get_mut("Shader") {
+        value = shader.take();
+    }
+    if let Value::Array(shaders) = &mut value {
+        if shaders.len() > 1 {
+            println!("warning: importing the first of {} shaders", shaders.len());
+        }
+        let first = shaders.drain(..).next();
+        value = first.unwrap_or_default();
+    }
+    serde_json::from_value(value).map_err(|e| invalid(format!("{}: {e}", path.display())))
+}
+
+// The buffer an Image pass does nothing but show
+fn shown_buffer<'a>(image: &RenderPass, passes: &'a [RenderPass]) -> Option<&'a RenderPass> {
+    let [input] = &image.inputs[..] else {
+        return None;
+    };
+    let code = squeeze(&image.code);
+    if input.kind != "buffer" || !PASSTHROUGHS.iter().any(|p| squeeze(p) == code) {
+        return None;
+    }
+    passes
+        .iter()
+        .find(|p| p.kind == "buffer" && p.outputs.iter().any(|o| id(&o.id) == id(&input.id)))
+}
+
+// Shadertoy's own textures are paths on its site, expected beside the project under their name
+fn media(filepath: &str, dir: &Path, warnings: &mut Vec<String>) -> String {
+    let file = Path::new(filepath)
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if !dir.join(&file).exists() {
+        let from = match filepath {
+            path if path.starts_with('/') => format!("download https://www.shadertoy.com{path}"),
+            path if path.starts_with("http") => format!("download {path}"),
+            path => format!("copy {path}"),
+        };
+        warnings.push(format!("{from} to {}", dir.join(&file).display()));
+    }
+    file
+}
+
+// Inputs are always sampled linearly, clamped, and the right way up
+fn sampler_warnings(channel: &str, sampler: &Sampler, warnings: &mut Vec<String>) {
+    if sampler.filter == "nearest" {
+        warnings.push(format!("{channel} is filtered linearly, not nearest"));
+    }
+    if sampler.wrap == "repeat" {
+        warnings.push(format!(
+            "{channel} clamps to the edge rather than repeating"
+        ));
+    }
+    if sampler.vflip == json!("false") || sampler.vflip == json!(false) {
+        warnings.push(format!("{channel} is flipped to be upright"));
+    }
+}
+
+// Write the shader in a Shadertoy JSON file and a project for it to `out`, or beside the file,
+// returning the shader's path
+pub fn import(path: &Path, out: Option<&Path>) -> io::Result<PathBuf> {
+    let shader = read(path)?;
+    let dir = match out {
+        Some(out) => out.to_owned(),
+        None => path.parent().unwrap_or(Path::new("")).to_owned(),
+    };
+    fs::create_dir_all(&dir)?;
+    let name = slug(&shader.info.name)
+        .or_else(|| slug(&path.file_stem()?.to_string_lossy()))
+        .unwrap_or_else(|| "shadertoy".to_owned());
+
+    let passes = &shader.renderpass;
+    let image = passes
+        .iter()
+        .find(|p| p.kind == "image")
+        .ok_or_else(|| invalid(format!("{}: no Image pass", path.display())))?;
+    let common = passes.iter().find(|p| p.kind == "common");
+    let main = shown_buffer(image, passes).unwrap_or(image);
+
+    let mut warnings = vec![];
+    for pass in passes {
+        let shown = main.kind == "buffer" && std::ptr::eq(pass, image);
+        if std::ptr::eq(pass, main) || pass.kind == "common" || shown {
+            continue;
+        }
+        let pass_name = slug(&pass.name).unwrap_or_else(|| pass.kind.clone());
+        let file = format!("{name}_{pass_name}.frag");
+        fs::write(dir.join(&file), &pass.code)?;
+        warnings.push(format!(
+            "{} is saved to {file} but not run, the workbench renders a single pass",
+            pass.name
+        ));
+    }
+
+    let own = main.outputs.first().map(|o| id(&o.id));
+    let mut channels: [Option<Channel>; 4] = Default::default();
+    for input in &main.inputs {
+        let Some(&name) = CHANNELS.get(input.channel) else {
+            warnings.push(format!("there's no channel {}", input.channel));
+            continue;
+        };
+        let channel = match input.kind.as_str() {
+            "buffer" if Some(id(&input.id)) == own => Channel::Feedback,
+            "buffer" => {
+                warnings.push(format!("{name} reads another pass, which isn't imported"));
+                continue;
+            }
+            "webcam" => Channel::Camera,
+            "texture" => Channel::Image(media(&input.filepath, &dir, &mut warnings)),
+            "video" => Channel::Video(media(&input.filepath, &dir, &mut warnings)),
+            kind => {
+                warnings.push(format!(
+                    "{name} is a {kind}, which the workbench doesn't have"
+                ));
+                continue;
+            }
+        };
+        if !matches!(channel, Channel::Feedback) {
+            sampler_warnings(name, &input.sampler, &mut warnings);
+        }
+        channels[input.channel] = Some(channel);
+    }
+
+    // Feedback only comes through iChannel1, so it trades places with whatever was there
+    let mut code = main.code.clone();
+    if let Some(i) = channels
+        .iter()
+        .position(|c| matches!(c, Some(Channel::Feedback)))
+    {
+        if i != 1 {
+            channels.swap(i, 1);
+            code = glsl::swap_words(&code, CHANNELS[i], CHANNELS[1]);
+            warnings.push(format!(
+                "{} and iChannel1 trade places, the workbench feeds back the last frame through \
+                 iChannel1",
+                CHANNELS[i]
+            ));
+        }
+        warnings.push(
+            "the last frame comes back with 8 bits per channel, not as floats, so values \
+             outside 0-1 are clamped"
+                .to_owned(),
+        );
+    }
+
+    let mut inputs = BTreeMap::new();
+    for (i, channel) in channels.iter().enumerate() {
+        let path =
+            |path: &String| toml::Table::from_iter([("path".to_owned(), path.as_str().into())]);
+        let config = match channel {
+            None | Some(Channel::Feedback) => continue,
+            // What iChannel0 is without an input
+            Some(Channel::Camera) if i == 0 => continue,
+            Some(Channel::Camera) => InputConfig::new("camera", toml::Table::new()),
+            Some(Channel::Image(file)) => InputConfig::new("image", path(file)),
+            Some(Channel::Video(file)) => InputConfig::new("video", path(file)),
+        };
+        inputs.insert(CHANNELS[i].to_owned(), config);
+    }
+    if glsl::uses(&code, MOUSE_INPUT) {
+        inputs.insert(
+            MOUSE_INPUT.to_owned(),
+            InputConfig::new("mouse", toml::Table::new()),
+        );
+    }
+
+    let info = &shader.info;
+    let mut header = match info.username.as_str() {
+        "" => format!("// {}, imported from Shadertoy\n", info.name),
+        user => format!("// {} by {user}, imported from Shadertoy\n", info.name),
+    };
+    if !info.id.is_empty() {
+        header += &format!("// https://www.shadertoy.com/view/{}\n", info.id);
+    }
+    if let Some(common) = common {
+        let file = format!("{name}_common.glsl");
+        fs::write(dir.join(&file), &common.code)?;
+        header += &format!("// @include \"{file}\"\n");
+    }
+    let shader_path = dir.join(format!("{name}.frag"));
+    fs::write(&shader_path, header + "\n" + &code)?;
+
+    let mut project = Project::open(&shader_path)?;
+    project.inputs = inputs;
+    project.save()?;
+
+    for warning in &warnings {
+        println!("warning: {warning}");
+    }
+    // Translating now, so anything naga doesn't like shows up with the import
+    let diagnostics = validate::check(&shader_path).unwrap_or_else(|d| vec![d]);
+    for d in &diagnostics {
+        validate::print_human(d);
+    }
+    println!(
+        "Saved shader {} and project {}",
+        shader_path.display(),
+        project.path().display()
+    );
+    Ok(shader_path)
+}
+
+fn channel_of(name: &str, config: &InputConfig) -> io::Result<Channel> {
+    let path = || config.str("path").unwrap_or_default().to_owned();
+    Ok(match config.source.as_str() {
+        "camera" => Channel::Camera,
+        "image" => Channel::Image(path()),
+        "video" => Channel::Video(path()),
+        source => {
+            return Err(invalid(format!(
+                "{name} is a {source} input, which Shadertoy channels can't be"
+            )))
+        }
+    })
+}
+
+fn channel_input(channel: usize, source: &Channel, own: &str) -> PassInput {
+    let (kind, id, filepath) = match source {
+        Channel::Feedback => ("buffer", own, BUFFER_A_PREVIEW),
+        Channel::Camera => ("webcam", "", ""),
+        Channel::Image(path) => ("texture", "", path.as_str()),
+        Channel::Video(path) => ("video", "", path.as_str()),
+    };
+    PassInput {
+        id: json!(id),
+        filepath: filepath.to_owned(),
+        kind: kind.to_owned(),
+        channel,
+        sampler: Sampler::default(),
+        published: json!(1),
+    }
+}
+
+// The value of a uniform the workbench would bind, in terms of Shadertoy's
+fn uniform_value(
+    name: &str,
+    frag: &FragShader,
+    project: &Project,
+    layout: &InputLayout,
+    warnings: &mut Vec<String>,
+) -> String {
+    if let Some((_, value)) = PRELUDE.iter().find(|(n, _)| *n == name) {
+        return (*value).to_owned();
+    }
+    match name {
+        "params" => {
+            let defaults: Vec<String> = frag
+                .param_decls
+                .iter()
+                .map(|d| format!("{:?}", d.default))
+                .collect();
+            format!("Params({})", defaults.join(", "))
+        }
+        // The mouse source's cursor is 0-1 from the top left
+        name if project
+            .inputs
+            .get(name)
+            .is_some_and(|c| c.source == "mouse") =>
+        {
+            "vec4(iMouse.x / iResolution.x, 1.0 - iMouse.y / iResolution.y, \
+             iMouse.z > 0.0 ? 1.0 : 0.0, 0.0)"
+                .to_owned()
+        }
+        name => {
+            warnings.push(format!("`{name}` is always zero on Shadertoy"));
+            match layout.kind(name) {
+                Some(BindingKind::Uniform { len }) if len > 4 => {
+                    let n = len.div_ceil(4);
+                    format!("vec4[{n}]({})", vec!["vec4(0.0)"; n as usize].join(", "))
+                }
+                _ => "vec4(0.0)".to_owned(),
+            }
+        }
+    }
+}
+
+// GLSL ES for a WGSL shader, with what its channels read
+fn from_wgsl(
+    frag: &FragShader,
+    project: &Project,
+    layout: &InputLayout,
+    warnings: &mut Vec<String>,
+) -> io::Result<(String, [Option<Channel>; 4])> {
+    let module = naga::front::wgsl::parse_str(&frag.wgsl).map_err(|e| {
+        invalid(format!(
+            "the shader doesn't compile, `validate` says why: {}",
+            e.message()
+        ))
+    })?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| invalid(format!("the shader doesn't validate: {}", error_chain(&e))))?;
+    let options = Options {
+        version: Version::Embedded {
+            version: 300,
+            is_webgl: true,
+        },
+        ..Default::default()
+    };
+    let pipeline = PipelineOptions {
+        shader_stage: ShaderStage::Fragment,
+        entry_point: "main".to_owned(),
+        multiview: None,
+    };
+    let mut out = String::new();
+    let reflection = Writer::new(
+        &mut out,
+        &module,
+        &info,
+        &options,
+        &pipeline,
+        BoundsCheckPolicies::default(),
+    )
+    .and_then(|mut writer| writer.write())
+    .map_err(|e| invalid(format!("Can't write GLSL: {e}")))?;
+
+    let global_name = |handle| {
+        let global: &naga::GlobalVariable = &module.global_variables[handle];
+        global.name.clone().unwrap_or_default()
+    };
+    // Reflection names the blocks, but code reads their instance, which naga names by binding
+    let mut defines = vec![];
+    let mut uniforms: Vec<_> = reflection.uniforms.keys().collect();
+    uniforms.sort_by_key(|handle| handle.index());
+    for &handle in uniforms {
+        let Some(binding) = &module.global_variables[handle].binding else {
+            continue;
+        };
+        let value = uniform_value(&global_name(handle), frag, project, layout, warnings);
+        defines.push(format!(
+            "#define _group_{}_binding_{}_fs {value}",
+            binding.group, binding.binding
+        ));
+    }
+
+    // The camera and feedback take the channels they have in GLSL shaders, inputs the rest
+    let mut channels: [Option<Channel>; 4] = Default::default();
+    let mut textures: Vec<(&String, String)> = reflection
+        .texture_mapping
+        .iter()
+        .map(|(glsl_name, mapping)| (glsl_name, global_name(mapping.texture)))
+        .collect();
+    textures.sort_by_key(|(_, name)| (name != "videoBuffer", name != "backBuffer", name.clone()));
+    for (glsl_name, name) in textures {
+        let channel = match name.as_str() {
+            "videoBuffer" => Channel::Camera,
+            "backBuffer" => Channel::Feedback,
+            name => match project.inputs.get(name) {
+                Some(config) => channel_of(name, config)?,
+                None => return Err(invalid(format!("`{name}` has no Shadertoy channel"))),
+            },
+        };
+        let preferred = match channel {
+            Channel::Camera => 0,
+            Channel::Feedback => 1,
+            _ => 2,
+        };
+        let free = (preferred..4)
+            .chain(0..preferred)
+            .find(|i| channels[*i].is_none())
+            .ok_or_else(|| invalid("Shadertoy passes read at most four textures"))?;
+        channels[free] = Some(channel);
+        defines.push(format!("#define {glsl_name} {}", CHANNELS[free]));
+    }
+
+    // Shadertoy declares its own version and precision, and the prelude is defined above
+    let mut output = String::new();
+    let mut body = vec![];
+    for line in out.lines() {
+        if line.starts_with("#version") || line.starts_with("precision ") {
+            continue;
+        }
+        if line.starts_with("uniform ") {
+            continue;
+        }
+        if let Some(decl) = line.strip_prefix("layout(location = 0) out ") {
+            output = decl
+                .trim_end_matches(';')
+                .rsplit(' ')
+                .next()
+                .unwrap_or_default()
+                .to_owned();
+            body.push(decl.to_owned());
+            continue;
+        }
+        if line.is_empty() && body.last().is_none_or(String::is_empty) {
+            continue;
+        }
+        body.push(
+            line.replace("void main()", "void workbench_main()")
+                .replace("gl_FragCoord", "workbench_FragCoord"),
+        );
+    }
+
+    let mut body = body.join("\n");
+    let mut lookups = vec![];
+    for (name, define) in LOOKUPS {
+        if glsl::uses(&body, name) {
+            body = glsl::swap_words(&body, name, &format!("workbench_{name}"));
+            lookups.push(*define);
+        }
+    }
+
+    let code = format!(
+        "{}\nvec4 workbench_FragCoord;\n{}\n\n{}\n\
+         void mainImage(out vec4 workbench_fragColor, in vec2 workbench_fragCoord) {{\n    \
+         workbench_FragCoord = vec4(workbench_fragCoord.x, iResolution.y - workbench_fragCoord.y, \
+         0.5, 1.0);\n    workbench_main();\n    workbench_fragColor = {output};\n}}\n",
+        defines.join("\n"),
+        lookups.join("\n"),
+        body.trim(),
+    );
+    Ok((code, channels))
+}
+
+// Shadertoy-style GLSL mostly goes as it is, with params as constants and the prelude's
+// uniforms as Shadertoy's
+fn from_glsl(
+    frag: &FragShader,
+    project: &Project,
+    warnings: &mut Vec<String>,
+) -> io::Result<(String, [Option<Channel>; 4])> {
+    let mut header = vec![];
+    if !frag.param_decls.is_empty() {
+        let fields: Vec<String> = frag
+            .param_decls
+            .iter()
+            .map(|d| format!("float {};", d.name))
+            .collect();
+        let defaults: Vec<String> = frag
+            .param_decls
+            .iter()
+            .map(|d| format!("{:?}", d.default))
+            .collect();
+        header.push(format!("struct Params {{ {} }};", fields.join(" ")));
+        header.push(format!(
+            "const Params params = Params({});",
+            defaults.join(", ")
+        ));
+    }
+    for (name, value) in PRELUDE {
+        if glsl::uses(&frag.source, name) {
+            header.push(format!("#define {name} {value}"));
+        }
+    }
+    for name in ["videoBuffer", "backBuffer"] {
+        if glsl::uses(&frag.source, name) {
+            warnings.push(format!(
+                "`{name}` isn't there on Shadertoy, read its channel"
+            ));
+        }
+    }
+
+    let mut channels: [Option<Channel>; 4] = Default::default();
+    for (i, name) in CHANNELS.iter().enumerate() {
+        if !glsl::uses(&frag.source, name) {
+            continue;
+        }
+        channels[i] = match project.inputs.get(*name) {
+            Some(config) => Some(channel_of(name, config)?),
+            None if i == 0 => Some(Channel::Camera),
+            None if i == 1 => Some(Channel::Feedback),
+            None => None,
+        };
+    }
+
+    // Left out when imported again, where the real ones are there
+    let code = match header.is_empty() {
+        true => frag.source.clone(),
+        false => format!(
+            "#ifndef WORKBENCH\n{}\n#endif\n\n{}",
+            header.join("\n"),
+            frag.source
+        ),
+    };
+    Ok((code, channels))
+}
+
+// Write the shader at `path`, a shader file or project, as Shadertoy JSON
+pub fn export(path: &Path, out: &Path) -> io::Result<()> {
+    let project = Project::open(path)?;
+    let shader_path = project.shader_path();
+    let mut frag = shader::read_frag_shader(&shader_path)?;
+    if let Some(e) = frag.include_errors.first() {
+        return Err(invalid(e.message.clone()));
+    }
+    let layout = Inputs::open(&project)?.layout();
+    frag.declare(&layout);
+    if let Some(e) = frag.import_errors.first() {
+        return Err(invalid(frag.describe(e)));
+    }
+
+    let mut warnings = vec![];
+    let (code, channels) = match frag.language {
+        Language::Wgsl | Language::SpirV => from_wgsl(&frag, &project, &layout, &mut warnings)?,
+        Language::Glsl => from_glsl(&frag, &project, &mut warnings)?,
+    };
+    for channel in channels.iter().flatten() {
+        if let Channel::Image(path) | Channel::Video(path) = channel {
+            warnings.push(format!(
+                "Shadertoy only has its own media, pick something there for {path}"
+            ));
+        }
+    }
+
+    let inputs = |own: &str| {
+        let channels = channels.iter().enumerate();
+        channels
+            .filter_map(|(i, c)| Some(channel_input(i, c.as_ref()?, own)))
+            .collect()
+    };
+    let pass = |kind: &str, name: &str, id: &str, code: String, inputs| RenderPass {
+        inputs,
+        outputs: vec![PassOutput {
+            id: json!(id),
+            channel: 0,
+        }],
+        code,
+        name: name.to_owned(),
+        description: String::new(),
+        kind: kind.to_owned(),
+    };
+    let renderpass = match channels
+        .iter()
+        .any(|c| matches!(c, Some(Channel::Feedback)))
+    {
+        true => vec![
+            pass("buffer", "Buffer A", BUFFER_A_ID, code, inputs(BUFFER_A_ID)),
+            pass(
+                "image",
+                "Image",
+                IMAGE_ID,
+                PASSTHROUGHS[0].to_owned(),
+                vec![channel_input(0, &Channel::Feedback, BUFFER_A_ID)],
+            ),
+        ],
+        false => vec![pass("image", "Image", IMAGE_ID, code, inputs(IMAGE_ID))],
+    };
+
+    let name = shader_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let rest = json!({
+        "date": "0",
+        "viewed": 0,
+        "likes": 0,
+        "published": 0,
+        "flags": 0,
+        "usePreview": 0,
+        "hasliked": 0,
+    });
+    let shader = Shader {
+        ver: "0.1".to_owned(),
+        info: Info {
+            name,
+            description: "Exported from wgsl_workbench".to_owned(),
+            rest: rest.as_object().cloned().unwrap_or_default(),
+            ..Default::default()
+        },
+        renderpass,
+    };
+    let json = serde_json::to_string_pretty(&shader).map_err(io::Error::other)?;
+    fs::write(out, json)?;
+
+    for warning in &warnings {
+        println!("warning: {warning}");
+    }
+    println!("Exported {}", out.display());
+    Ok(())
+}
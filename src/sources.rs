@@ -0,0 +1,280 @@
+// The input sources that come with the workbench, by the name projects use for them
+
+use std::{io, path::PathBuf};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::Read,
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    thread,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use parking_lot::Mutex;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+use crate::{
+    capture,
+    input::{Binding, InputConfig, InputSource, Upload},
+    resize::ResizePolicy,
+};
+
+pub fn builtin(config: &InputConfig) -> io::Result<Box<dyn InputSource>> {
+    Ok(match config.source.as_str() {
+        #[cfg(all(feature = "camera", not(target_arch = "wasm32")))]
+        "camera" => Box::new(crate::camera::CameraSource::new(
+            config.int("index").unwrap_or(0) as u32,
+        )),
+        #[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+        "audio" => Box::new(crate::audio::AudioSource::new(
+            config.str("device").map(str::to_owned),
+        )),
+        "midi" => Box::new(crate::midi::MidiSource::new(
+            config.str("port").map(str::to_owned),
+        )),
+        #[cfg(not(target_arch = "wasm32"))]
+        "video" => Box::new(VideoSource {
+            path: config.path("path")?,
+            size: (
+                config.int("width").unwrap_or(640) as u32,
+                config.int("height").unwrap_or(360) as u32,
+            ),
+            ffmpeg: None,
+            latest: Arc::new(Mutex::new(None)),
+            frame: vec![],
+        }),
+        "image" => Box::new(ImageSource {
+            path: config.path("path")?,
+            image: None,
+            fresh: false,
+        }),
+        "mouse" => Box::new(MouseSource {
+            resolution: config.resolution().clone(),
+            ..Default::default()
+        }),
+        "keyboard" => Box::new(KeyboardSource {
+            keys: [0.0; KEYS],
+            changed: true,
+        }),
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown input source `{other}`"),
+            ))
+        }
+    })
+}
+
+// A PNG, loaded once
+struct ImageSource {
+    path: PathBuf,
+    image: Option<(u32, u32, Vec<u8>)>,
+    // Set when loaded, cleared by the upload
+    fresh: bool,
+}
+
+impl InputSource for ImageSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![Binding::texture("")]
+    }
+
+    fn poll(&mut self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+        match capture::read_png(&self.path) {
+            Ok(image) => {
+                self.image = Some(image);
+                self.fresh = true;
+            }
+            Err(e) => println!("Failed to read image {}: {e}", self.path.display()),
+        }
+        // Only tried once
+        self.path = PathBuf::new();
+    }
+
+    fn upload(&mut self, _index: usize) -> Option<Upload<'_>> {
+        if !std::mem::take(&mut self.fresh) {
+            return None;
+        }
+        let (width, height, rgba) = self.image.as_ref()?;
+        let upload = Upload::Texture {
+            width: *width,
+            height: *height,
+            rgba,
+        };
+        Some(upload)
+    }
+}
+
+// A video file decoded by ffmpeg at its own frame rate, looping, scaled to `size`
+#[cfg(not(target_arch = "wasm32"))]
+struct VideoSource {
+    path: PathBuf,
+    size: (u32, u32),
+    ffmpeg: Option<Child>,
+    // Written by the reader thread, taken by upload
+    latest: Arc<Mutex<Option<Vec<u8>>>>,
+    frame: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl VideoSource {
+    fn start(&mut self) -> io::Result<()> {
+        let (width, height) = self.size;
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-stream_loop", "-1", "-re", "-i"])
+            .arg(&self.path)
+            .args(["-vf", &format!("scale={width}:{height}")])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("Couldn't start ffmpeg: {e}")))?;
+
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let latest = self.latest.clone();
+        let frame_len = (width * height * 4) as usize;
+        thread::spawn(move || {
+            let mut frame = vec![0; frame_len];
+            while stdout.read_exact(&mut frame).is_ok() {
+                *latest.lock() = Some(frame.clone());
+            }
+        });
+        self.ffmpeg = Some(child);
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl InputSource for VideoSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![Binding::texture("")]
+    }
+
+    fn poll(&mut self) {
+        if self.ffmpeg.is_none() && !self.path.as_os_str().is_empty() {
+            if let Err(e) = self.start() {
+                println!("Failed to play {}: {e}", self.path.display());
+                self.path = PathBuf::new();
+            }
+        }
+    }
+
+    fn upload(&mut self, _index: usize) -> Option<Upload<'_>> {
+        self.frame = self.latest.lock().take()?;
+        Some(Upload::Texture {
+            width: self.size.0,
+            height: self.size.1,
+            rgba: &self.frame,
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for VideoSource {
+    fn drop(&mut self) {
+        if let Some(ffmpeg) = &mut self.ffmpeg {
+            let _ = ffmpeg.kill();
+            let _ = ffmpeg.wait();
+        }
+    }
+}
+
+// The cursor as 0-1 across the render target from the top left, like fragCoord / res, a bitmask
+// of held buttons (left 1, right 2, middle 4) and the accumulated scroll in lines. The cursor
+// goes past 0-1 over letterbox bars.
+#[derive(Default)]
+struct MouseSource {
+    resolution: ResizePolicy,
+    window: (u32, u32),
+    position: (f32, f32),
+    buttons: u32,
+    scroll: f32,
+    uniform: [f32; 4],
+}
+
+impl InputSource for MouseSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![Binding::uniform("", 4)]
+    }
+
+    fn poll(&mut self) {}
+
+    fn upload(&mut self, _index: usize) -> Option<Upload<'_>> {
+        let (x, y) = self.resolution.window_to_uv(self.window, self.position);
+        self.uniform = [x, y, self.buttons as f32, self.scroll];
+        // Small enough to upload every frame rather than tracking changes
+        Some(Upload::Uniform(&self.uniform))
+    }
+
+    fn window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Resized(size) => {
+                self.window = (size.width, size.height);
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.position = (position.x as f32, position.y as f32);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let bit = match button {
+                    MouseButton::Left => 1,
+                    MouseButton::Right => 2,
+                    MouseButton::Middle => 4,
+                    MouseButton::Other(_) => return,
+                };
+                match state {
+                    ElementState::Pressed => self.buttons |= bit,
+                    ElementState::Released => self.buttons &= !bit,
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / 20.0,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+const KEYS: usize = 256;
+
+// 1 for every held key, indexed by winit's VirtualKeyCode
+struct KeyboardSource {
+    keys: [f32; KEYS],
+    changed: bool,
+}
+
+impl InputSource for KeyboardSource {
+    fn describe_bindings(&self) -> Vec<Binding> {
+        vec![Binding::uniform("", KEYS as u32)]
+    }
+
+    fn poll(&mut self) {}
+
+    fn upload(&mut self, _index: usize) -> Option<Upload<'_>> {
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+        Some(Upload::Uniform(&self.keys))
+    }
+
+    fn window_event(&mut self, event: &WindowEvent) {
+        let WindowEvent::KeyboardInput { input, .. } = event else {
+            return;
+        };
+        let Some(key) = input.virtual_keycode else {
+            return;
+        };
+        if let Some(held) = self.keys.get_mut(key as usize) {
+            *held = match input.state {
+                ElementState::Pressed => 1.0,
+                ElementState::Released => 0.0,
+            };
+            self.changed = true;
+        }
+    }
+}
@@ -0,0 +1,139 @@
+// Fragment shaders compiled to SPIR-V by other toolchains, parsed by naga's SPIR-V front-end and
+// written out as WGSL for the pipeline. Nothing can be added to a binary, so rather than having
+// the prelude injected, the resources `main` uses are checked against what the workbench binds,
+// and a mismatch is reported here instead of by wgpu when the pipeline's created.
+//
+// The prelude's bindings are where WGSL has them, inputs are in group 2 in project order, and
+// params are a uniform block of up to 64 floats at group 0, binding 5. There are no `@param`
+// comments in a binary, so the param panel stays empty.
+
+use naga::{
+    back::wgsl::WriterFlags,
+    front::spv::{self, Options},
+    valid::{Capabilities, ValidationFlags, Validator},
+    AddressSpace, GlobalVariable, ImageClass, ImageDimension, Module, ShaderStage, TypeInner,
+};
+
+use crate::{
+    input::InputLayout,
+    params::MAX_PARAMS,
+    shader::{error_chain, PRELUDE},
+};
+
+// WGSL for a SPIR-V module, or everything wrong with it
+pub fn translate(spirv: &[u8], inputs: &InputLayout) -> Result<String, Vec<String>> {
+    // SPIR-V's clip space is flipped, but that only matters for vertex shaders
+    let options = Options {
+        adjust_coordinate_space: false,
+        ..Default::default()
+    };
+    let module = spv::parse_u8_slice(spirv, &options)
+        .map_err(|e| vec![format!("Can't read SPIR-V: {e}")])?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| vec![error_chain(&e)])?;
+    let Some(entry_point) = module
+        .entry_points
+        .iter()
+        .position(|e| e.name == "main" && e.stage == ShaderStage::Fragment)
+    else {
+        return Err(vec!["no fragment entry point named `main`".to_owned()]);
+    };
+
+    let expected = bound(inputs);
+    let used = info.get_entry_point(entry_point);
+    let problems: Vec<String> = module
+        .global_variables
+        .iter()
+        .filter(|(handle, _)| !used[*handle].is_empty())
+        .filter_map(|(_, global)| mismatch(&module, global, &expected))
+        .collect();
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    naga::back::wgsl::write_string(&module, &info, WriterFlags::empty())
+        .map_err(|e| vec![format!("Can't write WGSL: {e}")])
+}
+
+// What the workbench binds, as a module of declarations
+fn bound(inputs: &InputLayout) -> Module {
+    let mut wgsl: Vec<String> = PRELUDE
+        .iter()
+        .filter(|item| item.wgsl.starts_with('@'))
+        .map(|item| item.wgsl.to_owned())
+        .collect();
+    wgsl.push(format!(
+        "@group(0) @binding(5) var<uniform> params: array<vec4<f32>, {}>;",
+        MAX_PARAMS / 4
+    ));
+    wgsl.push(inputs.wgsl());
+    naga::front::wgsl::parse_str(&wgsl.join("\n")).expect("the prelude and inputs are valid WGSL")
+}
+
+// Why a global doesn't fit what the workbench binds at its slot, if it doesn't
+fn mismatch(module: &Module, global: &GlobalVariable, expected: &Module) -> Option<String> {
+    let binding = global.binding.as_ref()?;
+    let name = global.name.as_deref().unwrap_or("unnamed");
+    let at = format!("group {}, binding {}", binding.group, binding.binding);
+    let Some(bound) = expected
+        .global_variables
+        .iter()
+        .map(|(_, g)| g)
+        .find(|g| g.binding.as_ref() == Some(binding))
+    else {
+        return Some(format!(
+            "`{name}` is at {at}, where the workbench binds nothing"
+        ));
+    };
+    let bound_name = bound.name.as_deref().unwrap_or_default();
+
+    let (actual, wanted) = (kind(module, global), kind(expected, bound));
+    if actual != wanted {
+        return Some(format!(
+            "`{name}` is {actual}, but {at} is `{bound_name}`, {wanted}"
+        ));
+    }
+    if global.space == AddressSpace::Uniform {
+        let size = module.types[global.ty].inner.size(module.to_ctx());
+        let bound_size = expected.types[bound.ty].inner.size(expected.to_ctx());
+        if size > bound_size {
+            return Some(format!(
+                "`{name}` is {size} bytes, but {at} is `{bound_name}`, {bound_size} bytes"
+            ));
+        }
+    }
+    None
+}
+
+fn kind(module: &Module, global: &GlobalVariable) -> String {
+    match (global.space, &module.types[global.ty].inner) {
+        (AddressSpace::Uniform, _) => "a uniform buffer".to_owned(),
+        (AddressSpace::Storage { .. }, _) => "a storage buffer".to_owned(),
+        (
+            _,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let class = match class {
+                ImageClass::Sampled { kind, multi: false } => format!("{kind:?}"),
+                ImageClass::Sampled { kind, multi: true } => format!("multisampled {kind:?}"),
+                ImageClass::Depth { .. } => "depth".to_owned(),
+                ImageClass::Storage { .. } => "storage".to_owned(),
+            };
+            let dim = match dim {
+                ImageDimension::D1 => "1D",
+                ImageDimension::D2 => "2D",
+                ImageDimension::D3 => "3D",
+                ImageDimension::Cube => "cube",
+            };
+            let array = if *arrayed { " array" } else { "" };
+            format!("a {dim} {} texture{array}", class.to_lowercase())
+        }
+        (_, TypeInner::Sampler { comparison: false }) => "a sampler".to_owned(),
+        (_, TypeInner::Sampler { comparison: true }) => "a comparison sampler".to_owned(),
+        _ => "not a resource".to_owned(),
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use egui::TextureId;
+use wgpu::{util::DeviceExt, TextureFormat};
+
+use crate::{gui::Gui, renderer::Renderer};
+
+// The longest side thumbnails are drawn at, and shown at in the list
+const DISPLAY_SIZE: u32 = 512;
+const THUMBNAIL_SIZE: f32 = 96.0;
+const CHANNELS: [&str; 4] = ["R", "G", "B", "A"];
+
+// How texture_viewer.wgsl reads each kind of texture. Views can't pick out a mip level or layer
+// on every backend, GL's are always the whole texture, so the shader does.
+const SOURCE_2D: &str = "
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+fn source_size() -> vec2<u32> { return textureDimensions(source, i32(view.mip)); }
+fn load(texel: vec2<u32>) -> vec4<f32> { return textureLoad(source, texel, i32(view.mip)); }
+";
+const SOURCE_ARRAY: &str = "
+@group(0) @binding(0)
+var source: texture_2d_array<f32>;
+fn source_size() -> vec2<u32> { return textureDimensions(source, i32(view.mip)); }
+fn load(texel: vec2<u32>) -> vec4<f32> {
+    return textureLoad(source, texel, i32(view.layer), i32(view.mip));
+}
+";
+
+// The egui texture viewer: every texture the workbench owns, each drawn through
+// texture_viewer.wgsl into a thumbnail the gui can show. Channels can be shown on their own, HDR
+// values scaled into range with exposure, and any mip level or array layer picked.
+pub struct TextureViewer {
+    pub open: bool,
+    selected: Option<String>,
+    // By texture name, so they survive the texture being recreated at a new size
+    settings: HashMap<String, Settings>,
+    thumbnails: HashMap<String, Thumbnail>,
+    // What the window lists, as of the last render
+    entries: Vec<Entry>,
+    // For 2D textures, then arrays
+    pipelines: [ViewPipeline; 2],
+}
+
+struct ViewPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    dimension: wgpu::TextureViewDimension,
+}
+
+// How one texture is shown
+#[derive(Clone, Copy)]
+struct Settings {
+    channels: [bool; 4],
+    // In stops
+    exposure: f32,
+    mip: u32,
+    layer: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            channels: [true; 4],
+            exposure: 0.0,
+            mip: 0,
+            layer: 0,
+        }
+    }
+}
+
+struct Thumbnail {
+    view: wgpu::TextureView,
+    size: (u32, u32),
+    uniforms: wgpu::Buffer,
+    id: TextureId,
+}
+
+struct Entry {
+    name: String,
+    size: (u32, u32),
+    format: TextureFormat,
+    mips: u32,
+    layers: u32,
+    // The selected mip level's size, scaled to fit DISPLAY_SIZE
+    thumbnail: (u32, u32),
+    id: TextureId,
+}
+
+impl TextureViewer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            open: false,
+            selected: None,
+            settings: HashMap::new(),
+            thumbnails: HashMap::new(),
+            entries: vec![],
+            pipelines: [
+                ViewPipeline::new(device, SOURCE_2D, wgpu::TextureViewDimension::D2),
+                ViewPipeline::new(device, SOURCE_ARRAY, wgpu::TextureViewDimension::D2Array),
+            ],
+        }
+    }
+
+    // Draw every texture's thumbnail as it is now, before the gui is painted
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        renderer: &Renderer,
+        gui: &mut Gui,
+    ) {
+        if !self.open {
+            return;
+        }
+
+        let textures = renderer.textures();
+        self.thumbnails.retain(|name, thumbnail| {
+            let keep = textures.iter().any(|(n, _)| n == name);
+            if !keep {
+                gui.free_texture(thumbnail.id);
+            }
+            keep
+        });
+
+        self.entries.clear();
+        for (name, texture) in textures {
+            let (mips, layers) = (texture.mip_level_count(), texture.depth_or_array_layers());
+            let settings = self.settings.entry(name.to_owned()).or_default();
+            settings.mip = settings.mip.min(mips - 1);
+            settings.layer = settings.layer.min(layers - 1);
+
+            let mip_size = (
+                (texture.width() >> settings.mip).max(1),
+                (texture.height() >> settings.mip).max(1),
+            );
+            let scale = (DISPLAY_SIZE as f32 / mip_size.0.max(mip_size.1) as f32).min(1.0);
+            let size = (
+                ((mip_size.0 as f32 * scale) as u32).max(1),
+                ((mip_size.1 as f32 * scale) as u32).max(1),
+            );
+            if self.thumbnails.get(name).is_none_or(|t| t.size != size) {
+                let thumbnail = Thumbnail::new(device, gui, size);
+                if let Some(old) = self.thumbnails.insert(name.to_owned(), thumbnail) {
+                    gui.free_texture(old.id);
+                }
+            }
+            let thumbnail = &self.thumbnails[name];
+
+            let channels = settings.channels.map(|on| on as u32 as f32);
+            let exposure = 2f32.powf(settings.exposure);
+            let (mip, layer) = (settings.mip as f32, settings.layer as f32);
+            queue.write_buffer(
+                &thumbnail.uniforms,
+                0,
+                bytemuck::cast_slice(&[channels, [exposure, mip, layer, 0.0]]),
+            );
+
+            let pipeline = &self.pipelines[(layers > 1) as usize];
+            let source = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(pipeline.dimension),
+                ..Default::default()
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &pipeline.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: thumbnail.uniforms.as_entire_binding(),
+                    },
+                ],
+                label: Some("texture_viewer_bind_group"),
+            });
+
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Texture Viewer Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &thumbnail.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&pipeline.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+            drop(pass);
+
+            self.entries.push(Entry {
+                name: name.to_owned(),
+                size: (texture.width(), texture.height()),
+                format: texture.format(),
+                mips,
+                layers,
+                thumbnail: size,
+                id: thumbnail.id,
+            });
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Textures")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if self.entries.is_empty() {
+                    ui.label("No textures yet");
+                    return;
+                }
+
+                ui.horizontal_wrapped(|ui| {
+                    for entry in &self.entries {
+                        let selected = self.selected.as_ref() == Some(&entry.name);
+                        ui.vertical(|ui| {
+                            let size = fit(entry.thumbnail, THUMBNAIL_SIZE);
+                            let button = egui::ImageButton::new(entry.id, size).selected(selected);
+                            if ui.add(button).clicked() {
+                                self.selected = Some(entry.name.clone());
+                            }
+                            ui.small(&entry.name);
+                        });
+                    }
+                });
+
+                let Some(entry) =
+                    (self.entries.iter()).find(|entry| self.selected.as_ref() == Some(&entry.name))
+                else {
+                    ui.weak("Click a texture for its settings");
+                    return;
+                };
+                let settings = self.settings.entry(entry.name.clone()).or_default();
+
+                ui.separator();
+                let (width, height) = entry.size;
+                ui.label(format!(
+                    "{}: {width}x{height} {:?}",
+                    entry.name, entry.format
+                ));
+                ui.horizontal(|ui| {
+                    for (on, channel) in settings.channels.iter_mut().zip(CHANNELS) {
+                        ui.toggle_value(on, channel);
+                    }
+                    ui.separator();
+                    ui.add(
+                        egui::Slider::new(&mut settings.exposure, -10.0..=10.0)
+                            .text("Exposure (EV)"),
+                    );
+                    if ui.button("Reset").clicked() {
+                        *settings = Settings::default();
+                    }
+                });
+                if entry.mips > 1 {
+                    ui.add(
+                        egui::Slider::new(&mut settings.mip, 0..=entry.mips - 1).text("Mip level"),
+                    );
+                }
+                if entry.layers > 1 {
+                    ui.add(
+                        egui::Slider::new(&mut settings.layer, 0..=entry.layers - 1)
+                            .text("Array layer"),
+                    );
+                }
+
+                let size = fit(
+                    entry.thumbnail,
+                    ui.available_width().min(DISPLAY_SIZE as f32),
+                );
+                ui.image(entry.id, size);
+            });
+        self.open = open;
+    }
+}
+
+impl ViewPipeline {
+    fn new(device: &wgpu::Device, source: &str, dimension: wgpu::TextureViewDimension) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("texture_viewer.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{source}{}", include_str!("texture_viewer.wgsl")).into(),
+            ),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: dimension,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_viewer_bind_group_layout"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Texture Viewer Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Texture Viewer Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: TextureFormat::Rgba8UnormSrgb,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            dimension,
+        }
+    }
+}
+
+impl Thumbnail {
+    fn new(device: &wgpu::Device, gui: &mut Gui, size: (u32, u32)) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            label: Some("Texture Viewer Thumbnail"),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Texture Viewer Uniform"),
+            contents: bytemuck::cast_slice(&[0f32; 8]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let id = gui.register_texture(device, &view);
+        Self {
+            view,
+            size,
+            uniforms,
+            id,
+        }
+    }
+}
+
+// `size` scaled so its longest side is `side`
+fn fit(size: (u32, u32), side: f32) -> egui::Vec2 {
+    let scale = side / size.0.max(size.1) as f32;
+    egui::vec2(size.0 as f32, size.1 as f32) * scale
+}
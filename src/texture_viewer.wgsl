@@ -0,0 +1,50 @@
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct View {
+    // 1 for each channel shown, 0 for the rest
+    channels: vec4<f32>,
+    exposure: f32,
+    mip: f32,
+    layer: f32,
+}
+
+// The texture is bound at group 0, binding 0 by texture_viewer.rs, along with source_size and
+// load for the mip level and layer picked. They're loaded rather than sampled, so float formats
+// that can't be filtered work too.
+@group(0) @binding(1)
+var<uniform> view: View;
+
+// One triangle covering the viewport, like blit.wgsl
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    var out: VertexOutput;
+    out.pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = source_size();
+    let texel = min(vec2<u32>(in.uv * vec2<f32>(size)), size - 1u);
+    let value = load(texel);
+
+    // A channel on its own is shown in grey
+    if dot(view.channels, vec4<f32>(1.0)) == 1.0 {
+        let grey = dot(value, view.channels) * view.exposure;
+        return vec4<f32>(vec3<f32>(grey), 1.0);
+    }
+
+    let color = value.rgb * view.channels.rgb * view.exposure;
+    if view.channels.a == 0.0 {
+        return vec4<f32>(color, 1.0);
+    }
+    // Alpha with colour blends over a checkerboard
+    let check = (u32(in.pos.x) / 8u + u32(in.pos.y) / 8u) % 2u;
+    let background = vec3<f32>(select(0.2, 0.4, check == 1u));
+    return vec4<f32>(mix(background, color, value.a), 1.0);
+}
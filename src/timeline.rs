@@ -0,0 +1,556 @@
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Shape, Stroke};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{clock::Clock, params::Params};
+
+// How a keyframe eases into the next one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Easing {
+    #[default]
+    Linear,
+    // Hold this keyframe's value until the next one
+    Step,
+    // CSS-style cubic-bezier(x1, y1, x2, y2)
+    Bezier([f32; 4]),
+}
+
+impl Easing {
+    pub const EASE_IN_OUT: Self = Self::Bezier([0.42, 0.0, 0.58, 1.0]);
+
+    pub fn apply(&self, t: f32) -> f32 {
+        match *self {
+            Self::Linear => t,
+            Self::Step => 0.0,
+            Self::Bezier([x1, y1, x2, y2]) => {
+                let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+                let bezier = |a: f32, b: f32, s: f32| {
+                    let r = 1.0 - s;
+                    3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+                };
+
+                // x(s) is monotonic with the control points clamped to [0, 1], so bisection always
+                // converges. A fixed iteration count keeps offline renders bit-for-bit reproducible.
+                let (mut lo, mut hi) = (0.0, 1.0);
+                for _ in 0..24 {
+                    let mid = 0.5 * (lo + hi);
+                    if bezier(x1, x2, mid) < t {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                bezier(y1, y2, 0.5 * (lo + hi))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: f32,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+// Keyframes for a single param, kept sorted by time
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Track {
+    pub param: String,
+    #[serde(default, deserialize_with = "sorted_keys")]
+    pub keys: Vec<Keyframe>,
+}
+
+// Hand-edited project files can list keys in any order. Of keys at the same time the last one
+// listed wins, as if they'd been inserted in order.
+fn sorted_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Keyframe>, D::Error> {
+    let mut keys = Vec::<Keyframe>::deserialize(deserializer)?;
+    keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    keys.dedup_by(|later, earlier| {
+        let same = later.time == earlier.time;
+        if same {
+            *earlier = *later;
+        }
+        same
+    });
+    Ok(keys)
+}
+
+impl Track {
+    pub fn sample(&self, time: f32) -> Option<f32> {
+        let first = self.keys.first()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+
+        for pair in self.keys.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if time < b.time {
+                let t = (time - a.time) / (b.time - a.time);
+                return Some(a.value + (b.value - a.value) * a.easing.apply(t));
+            }
+        }
+        self.keys.last().map(|k| k.value)
+    }
+
+    // Insert a key, replacing any key already at that exact time. Returns its index.
+    pub fn insert(&mut self, key: Keyframe) -> usize {
+        self.keys.retain(|k| k.time != key.time);
+        let i = self.keys.partition_point(|k| k.time < key.time);
+        self.keys.insert(i, key);
+        i
+    }
+
+    // Move a key to a new time, replacing any key already there. Returns the key's new index.
+    pub fn move_key(&mut self, i: usize, time: f32) -> usize {
+        let mut key = self.keys.remove(i);
+        key.time = time;
+        self.insert(key)
+    }
+}
+
+fn default_length() -> f32 {
+    10.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Timeline {
+    #[serde(default = "default_length")]
+    pub length: f32,
+    #[serde(default)]
+    pub looping: bool,
+    #[serde(default, rename = "track")]
+    pub tracks: Vec<Track>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            length: default_length(),
+            looping: false,
+            tracks: vec![],
+        }
+    }
+}
+
+impl Timeline {
+    pub fn local_time(&self, time: f32) -> f32 {
+        if self.looping && self.length > 0.0 {
+            time.rem_euclid(self.length)
+        } else {
+            time
+        }
+    }
+
+    // Values of every animated param at `time`. A pure function of time, so anything driving the
+    // clock at a fixed step (like an offline render) gets the same values every run.
+    pub fn sample(&self, time: f32) -> BTreeMap<String, f32> {
+        let time = self.local_time(time);
+        self.tracks
+            .iter()
+            .filter_map(|t| Some((t.param.clone(), t.sample(time)?)))
+            .collect()
+    }
+
+    pub fn track_mut(&mut self, param: &str) -> &mut Track {
+        match self.tracks.iter().position(|t| t.param == param) {
+            Some(i) => &mut self.tracks[i],
+            None => {
+                self.tracks.push(Track {
+                    param: param.to_owned(),
+                    keys: vec![],
+                });
+                self.tracks.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+enum Drag {
+    Scrub,
+    Key { param: String, index: usize },
+}
+
+// The egui timeline strip: a ruler to scrub the clock, and one row of keyframes per param
+#[derive(Default)]
+pub struct TimelineStrip {
+    selected: Option<(String, usize)>,
+    drag: Option<Drag>,
+}
+
+const LABEL_WIDTH: f32 = 100.0;
+const ROW_HEIGHT: f32 = 18.0;
+const KEY_RADIUS: f32 = 5.0;
+
+impl TimelineStrip {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        timeline: &mut Timeline,
+        clock: &mut Clock,
+        params: &Params,
+    ) {
+        egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let play = if clock.is_paused() { "Play" } else { "Pause" };
+                if ui.button(play).clicked() {
+                    clock.toggle_pause();
+                }
+                ui.label(format!("{:.2}s", clock.time()));
+                ui.separator();
+                ui.label("Length");
+                ui.add(
+                    egui::DragValue::new(&mut timeline.length)
+                        .speed(0.1)
+                        .clamp_range(0.1..=3600.0)
+                        .suffix("s"),
+                );
+                ui.checkbox(&mut timeline.looping, "Loop");
+                ui.separator();
+                if ui.button("Key all").clicked() {
+                    let time = timeline.local_time(clock.time());
+                    for (param, value) in params.values() {
+                        timeline.track_mut(&param).insert(Keyframe {
+                            time,
+                            value,
+                            easing: Easing::Linear,
+                        });
+                    }
+                }
+            });
+
+            self.tracks(ui, timeline, clock, params);
+            self.key_editor(ui, timeline);
+        });
+    }
+
+    fn tracks(
+        &mut self,
+        ui: &mut egui::Ui,
+        timeline: &mut Timeline,
+        clock: &mut Clock,
+        params: &Params,
+    ) {
+        let names: Vec<String> = params.values().into_keys().collect();
+        let height = ROW_HEIGHT * (names.len() + 1) as f32;
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), height),
+            Sense::click_and_drag(),
+        );
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+
+        let area = Rect::from_min_max(rect.min + egui::vec2(LABEL_WIDTH, 0.0), rect.max);
+        let length = timeline.length.max(0.1);
+        let to_x = |t: f32| area.left() + t / length * area.width();
+        let from_x = |x: f32| ((x - area.left()) / area.width() * length).clamp(0.0, length);
+        let row_y = |row: usize| rect.top() + ROW_HEIGHT * (row as f32 + 0.5);
+
+        // Ruler
+        painter.rect_filled(
+            Rect::from_min_size(area.min, egui::vec2(area.width(), ROW_HEIGHT)),
+            0.0,
+            visuals.extreme_bg_color,
+        );
+        let step = (length / 10.0).max(0.1).ceil();
+        let mut t = 0.0;
+        while t <= length {
+            painter.line_segment(
+                [
+                    Pos2::new(to_x(t), area.top()),
+                    Pos2::new(to_x(t), area.bottom()),
+                ],
+                Stroke::new(1.0, visuals.faint_bg_color),
+            );
+            painter.text(
+                Pos2::new(to_x(t) + 2.0, row_y(0)),
+                Align2::LEFT_CENTER,
+                format!("{t}"),
+                FontId::monospace(10.0),
+                visuals.text_color(),
+            );
+            t += step;
+        }
+
+        // Keyframes, one row per param
+        let mut hit = None;
+        let pointer = response.interact_pointer_pos().or(response.hover_pos());
+        for (row, name) in names.iter().enumerate() {
+            let y = row_y(row + 1);
+            painter.text(
+                Pos2::new(rect.left() + 4.0, y),
+                Align2::LEFT_CENTER,
+                name,
+                FontId::proportional(12.0),
+                visuals.text_color(),
+            );
+
+            let Some(track) = timeline.tracks.iter().find(|t| &t.param == name) else {
+                continue;
+            };
+            for (i, key) in track.keys.iter().enumerate() {
+                let c = Pos2::new(to_x(key.time), y);
+                let selected = self.selected.as_ref() == Some(&(name.clone(), i));
+                let fill = if selected {
+                    visuals.selection.bg_fill
+                } else {
+                    visuals.text_color()
+                };
+                painter.add(Shape::convex_polygon(
+                    vec![
+                        c + egui::vec2(0.0, -KEY_RADIUS),
+                        c + egui::vec2(KEY_RADIUS, 0.0),
+                        c + egui::vec2(0.0, KEY_RADIUS),
+                        c + egui::vec2(-KEY_RADIUS, 0.0),
+                    ],
+                    fill,
+                    Stroke::NONE,
+                ));
+
+                if pointer.is_some_and(|p| p.distance(c) <= KEY_RADIUS + 1.0) {
+                    hit = Some((name.clone(), i));
+                }
+            }
+        }
+
+        // Playhead
+        let x = to_x(timeline.local_time(clock.time()).min(length));
+        painter.line_segment(
+            [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+            Stroke::new(2.0, Color32::from_rgb(230, 80, 60)),
+        );
+
+        // Click a key to select it, right-click to delete it, drag it to move it. Dragging anywhere
+        // else scrubs the clock, and double-clicking an empty spot on a row adds a key there.
+        if response.drag_started() {
+            self.drag = Some(match &hit {
+                Some((param, index)) => {
+                    self.selected = Some((param.clone(), *index));
+                    Drag::Key {
+                        param: param.clone(),
+                        index: *index,
+                    }
+                }
+                None => Drag::Scrub,
+            });
+        }
+        if let (Some(drag), Some(pos)) = (&mut self.drag, response.interact_pointer_pos()) {
+            match drag {
+                Drag::Scrub => clock.set_time(from_x(pos.x)),
+                Drag::Key { param, index } => {
+                    *index = timeline.track_mut(param).move_key(*index, from_x(pos.x));
+                    self.selected = Some((param.clone(), *index));
+                }
+            }
+        }
+        if response.drag_released() {
+            self.drag = None;
+        }
+
+        if response.clicked() {
+            self.selected = hit.clone();
+        }
+        if response.secondary_clicked() {
+            if let Some((param, index)) = &hit {
+                timeline.track_mut(param).keys.remove(*index);
+                self.selected = None;
+            }
+        }
+        if response.double_clicked() && hit.is_none() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let row = ((pos.y - rect.top()) / ROW_HEIGHT) as usize;
+                if let Some(name) = row.checked_sub(1).and_then(|r| names.get(r)) {
+                    let time = from_x(pos.x);
+                    let track = timeline.track_mut(name);
+                    let value = track
+                        .sample(time)
+                        .or(params.get(name))
+                        .unwrap_or_default();
+                    let index = track.insert(Keyframe {
+                        time,
+                        value,
+                        easing: Easing::Linear,
+                    });
+                    self.selected = Some((name.clone(), index));
+                }
+            }
+        }
+    }
+
+    fn key_editor(&mut self, ui: &mut egui::Ui, timeline: &mut Timeline) {
+        let Some((param, index)) = &self.selected else {
+            return;
+        };
+        let Some(key) = timeline
+            .tracks
+            .iter_mut()
+            .find(|t| &t.param == param)
+            .and_then(|t| t.keys.get_mut(*index))
+        else {
+            self.selected = None;
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{param}[{index}]"));
+            ui.label("Value");
+            ui.add(egui::DragValue::new(&mut key.value).speed(0.01));
+
+            let kind = match key.easing {
+                Easing::Linear => "Linear",
+                Easing::Step => "Step",
+                Easing::Bezier(_) => "Bezier",
+            };
+            egui::ComboBox::from_id_source("easing")
+                .selected_text(kind)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(kind == "Linear", "Linear").clicked() {
+                        key.easing = Easing::Linear;
+                    }
+                    if ui.selectable_label(kind == "Step", "Step").clicked() {
+                        key.easing = Easing::Step;
+                    }
+                    if ui.selectable_label(kind == "Bezier", "Bezier").clicked()
+                        && kind != "Bezier"
+                    {
+                        key.easing = Easing::EASE_IN_OUT;
+                    }
+                });
+
+            if let Easing::Bezier(points) = &mut key.easing {
+                for p in points.iter_mut() {
+                    ui.add(egui::DragValue::new(p).speed(0.01));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, value: f32, easing: Easing) -> Keyframe {
+        Keyframe {
+            time,
+            value,
+            easing,
+        }
+    }
+
+    fn track(keys: Vec<Keyframe>) -> Track {
+        Track {
+            param: "x".to_owned(),
+            keys,
+        }
+    }
+
+    #[test]
+    fn easing_endpoints() {
+        for easing in [
+            Easing::Linear,
+            Easing::EASE_IN_OUT,
+            Easing::Bezier([0.9, -0.5, 0.1, 1.5]),
+        ] {
+            assert!(easing.apply(0.0).abs() < 1e-4, "{easing:?}");
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-4, "{easing:?}");
+        }
+        assert_eq!(Easing::Step.apply(0.0), 0.0);
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+    }
+
+    #[test]
+    fn bezier_is_monotonic_and_symmetric() {
+        let mut last = 0.0;
+        for i in 0..=100 {
+            let y = Easing::EASE_IN_OUT.apply(i as f32 / 100.0);
+            assert!(y >= last, "{y} after {last} at {i}");
+            last = y;
+        }
+        assert!((Easing::EASE_IN_OUT.apply(0.5) - 0.5).abs() < 1e-4);
+        // Slow at the ends, so behind linear early and ahead of it late
+        assert!(Easing::EASE_IN_OUT.apply(0.25) < 0.25);
+        assert!(Easing::EASE_IN_OUT.apply(0.75) > 0.75);
+    }
+
+    #[test]
+    fn holds_outside_the_keys() {
+        let track = track(vec![
+            key(1.0, 10.0, Easing::Linear),
+            key(2.0, 20.0, Easing::Linear),
+        ]);
+        assert_eq!(track.sample(0.0), Some(10.0));
+        assert_eq!(track.sample(1.0), Some(10.0));
+        assert_eq!(track.sample(1.5), Some(15.0));
+        assert_eq!(track.sample(2.0), Some(20.0));
+        assert_eq!(track.sample(5.0), Some(20.0));
+        assert_eq!(self::track(vec![]).sample(1.0), None);
+    }
+
+    #[test]
+    fn step_holds_until_the_next_key() {
+        let track = track(vec![
+            key(0.0, 1.0, Easing::Step),
+            key(1.0, 2.0, Easing::Linear),
+        ]);
+        assert_eq!(track.sample(0.5), Some(1.0));
+        assert_eq!(track.sample(0.999), Some(1.0));
+        assert_eq!(track.sample(1.0), Some(2.0));
+    }
+
+    #[test]
+    fn looping_wraps_time() {
+        let mut timeline = Timeline {
+            length: 2.0,
+            looping: true,
+            tracks: vec![track(vec![
+                key(0.0, 0.0, Easing::Linear),
+                key(2.0, 2.0, Easing::Linear),
+            ])],
+        };
+        assert_eq!(timeline.sample(2.5)["x"], 0.5);
+        assert_eq!(timeline.sample(-0.5)["x"], 1.5);
+        timeline.looping = false;
+        assert_eq!(timeline.sample(2.5)["x"], 2.0);
+    }
+
+    #[test]
+    fn insert_and_move_replace_keys_at_the_same_time() {
+        let mut track = track(vec![]);
+        assert_eq!(track.insert(key(1.0, 1.0, Easing::Linear)), 0);
+        assert_eq!(track.insert(key(0.0, 0.0, Easing::Linear)), 0);
+        assert_eq!(track.insert(key(2.0, 2.0, Easing::Linear)), 2);
+        assert_eq!(track.insert(key(1.0, 5.0, Easing::Linear)), 1);
+        assert_eq!(track.keys.len(), 3);
+
+        // Dragging the first key onto the last replaces it
+        assert_eq!(track.move_key(0, 2.0), 1);
+        let keys: Vec<_> = track.keys.iter().map(|k| (k.time, k.value)).collect();
+        assert_eq!(keys, vec![(1.0, 5.0), (2.0, 0.0)]);
+    }
+
+    #[test]
+    fn sorts_keys_when_loaded() {
+        let timeline: Timeline = toml::from_str(
+            r#"
+            [[track]]
+            param = "x"
+            keys = [
+                { time = 2.0, value = 2.0 },
+                { time = 0.0, value = 0.0 },
+                { time = 1.0, value = 5.0 },
+                { time = 1.0, value = 1.0 },
+            ]
+            "#,
+        )
+        .unwrap();
+        let keys: Vec<_> = timeline.tracks[0]
+            .keys
+            .iter()
+            .map(|k| (k.time, k.value))
+            .collect();
+        assert_eq!(keys, vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]);
+    }
+}
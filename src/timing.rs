@@ -0,0 +1,262 @@
+// How long each pass of a frame takes, from GPU timestamps when the device has TIMESTAMP_QUERY.
+// Without it there's only the CPU time from submitting a frame to presenting it, or to reading it
+// back offline, which covers the whole frame along with any waiting on the driver.
+//
+// Timestamps are read back without stalling the window: a frame's queries are only copied out
+// while the last copy isn't still being mapped, so some frames go untimed.
+
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+
+use egui::plot::{Legend, Line, Plot, PlotPoints};
+use web_time::Instant;
+
+// The start of the frame, then the end of each pass
+const MAX_MARKS: u32 = 8;
+// Frames shown in the graph
+const HISTORY: usize = 240;
+
+#[derive(Clone, Debug)]
+pub struct Sample {
+    // GPU timestamps rather than the CPU fallback
+    pub gpu: bool,
+    // Milliseconds for each pass, in the order they ran
+    pub passes: Vec<(&'static str, f32)>,
+}
+
+struct Queries {
+    set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    // Nanoseconds per tick
+    period: f32,
+    // The passes whose timestamps are in readback, until they're read
+    copied: Option<Vec<&'static str>>,
+    mapping: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+pub struct FrameTimer {
+    queries: Option<Queries>,
+    // Marked so far in the frame being encoded
+    passes: Vec<&'static str>,
+    submitted: Option<Instant>,
+}
+
+impl FrameTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let timestamps = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        let queries = timestamps.then(|| {
+            let size = MAX_MARKS as u64 * 8;
+            Queries {
+                set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Frame Timestamps"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: MAX_MARKS,
+                }),
+                resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Frame Timestamps Resolve"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                readback: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Frame Timestamps Readback"),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                period: queue.get_timestamp_period(),
+                copied: None,
+                mapping: None,
+            }
+        });
+
+        Self {
+            queries,
+            passes: vec![],
+            submitted: None,
+        }
+    }
+
+    // Start timing a frame, before its first pass
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.passes.clear();
+        if let Some(queries) = &self.queries {
+            encoder.write_timestamp(&queries.set, 0);
+        }
+    }
+
+    // The end of a pass, which started at the last mark
+    pub fn mark(&mut self, encoder: &mut wgpu::CommandEncoder, pass: &'static str) {
+        if self.passes.len() as u32 + 1 >= MAX_MARKS {
+            return;
+        }
+        self.passes.push(pass);
+        if let Some(queries) = &self.queries {
+            encoder.write_timestamp(&queries.set, self.passes.len() as u32);
+        }
+    }
+
+    // Copy the frame's timestamps out, last thing before the encoder's finished
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        if queries.copied.is_some() {
+            return;
+        }
+        let marks = self.passes.len() as u32 + 1;
+        encoder.resolve_query_set(&queries.set, 0..marks, &queries.resolve, 0);
+        encoder.copy_buffer_to_buffer(&queries.resolve, 0, &queries.readback, 0, marks as u64 * 8);
+        queries.copied = Some(self.passes.clone());
+    }
+
+    // Right before the frame's submitted
+    pub fn submit(&mut self) {
+        self.submitted = Some(Instant::now());
+    }
+
+    // The latest frame's timings once it's presented or read back, if there are any new ones.
+    // `wait` blocks until the GPU has finished with them, for offline rendering.
+    pub fn collect(&mut self, device: &wgpu::Device, wait: bool) -> Option<Sample> {
+        let submitted = self.submitted.take();
+        let Some(queries) = &mut self.queries else {
+            let elapsed = submitted?.elapsed().as_secs_f32() * 1000.0;
+            return Some(Sample {
+                gpu: false,
+                passes: vec![("Frame", elapsed)],
+            });
+        };
+
+        let passes = queries.copied.as_ref()?;
+        let rx = queries.mapping.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+            queries
+                .readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |r| {
+                    let _ = tx.send(r);
+                });
+            rx
+        });
+        device.poll(match wait {
+            true => wgpu::Maintain::Wait,
+            false => wgpu::Maintain::Poll,
+        });
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) | Ok(Err(_)) => {
+                queries.mapping = None;
+                queries.copied = None;
+                return None;
+            }
+            Ok(Ok(())) => {}
+        }
+
+        let ticks: Vec<u64> = (queries.readback.slice(..).get_mapped_range())
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        queries.readback.unmap();
+        let to_ms = |ticks: u64| ticks as f32 * queries.period / 1_000_000.0;
+        let sample = Sample {
+            gpu: true,
+            passes: passes
+                .iter()
+                .zip(ticks.windows(2))
+                .map(|(pass, t)| (*pass, to_ms(t[1].saturating_sub(t[0]))))
+                .collect(),
+        };
+        queries.mapping = None;
+        queries.copied = None;
+        Some(sample)
+    }
+}
+
+// Each pass's mean and worst time over `samples`, in the order they first ran
+pub fn summarize<'a>(
+    samples: impl IntoIterator<Item = &'a Sample>,
+) -> Vec<(&'static str, f32, f32)> {
+    let mut totals: Vec<(&'static str, f32, f32, u32)> = vec![];
+    for (pass, ms) in samples.into_iter().flat_map(|s| &s.passes) {
+        match totals.iter_mut().find(|t| t.0 == *pass) {
+            Some(total) => {
+                total.1 += ms;
+                total.2 = total.2.max(*ms);
+                total.3 += 1;
+            }
+            None => totals.push((pass, *ms, *ms, 1)),
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(pass, sum, max, n)| (pass, sum / n as f32, max))
+        .collect()
+}
+
+// The rolling graph of recent frames' timings
+#[derive(Default)]
+pub struct TimingGraph {
+    pub open: bool,
+    history: VecDeque<Sample>,
+}
+
+impl TimingGraph {
+    pub fn push(&mut self, sample: Sample) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Timing")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                let Some(last) = self.history.back() else {
+                    ui.label("No frames timed yet");
+                    return;
+                };
+                ui.weak(match last.gpu {
+                    true => "GPU time per pass, from timestamp queries",
+                    false => "CPU time from submit to present, timestamp queries aren't supported",
+                });
+
+                let summary = summarize(&self.history);
+                egui::Grid::new("timing_summary").show(ui, |ui| {
+                    ui.strong("Pass");
+                    ui.strong("Mean");
+                    ui.strong("Worst");
+                    ui.end_row();
+                    for (pass, mean, max) in &summary {
+                        ui.label(*pass);
+                        ui.monospace(format!("{mean:.3} ms"));
+                        ui.monospace(format!("{max:.3} ms"));
+                        ui.end_row();
+                    }
+                });
+
+                Plot::new("timing_graph")
+                    .height(160.0)
+                    .legend(Legend::default())
+                    .include_y(0.0)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .y_axis_formatter(|ms, _| format!("{ms} ms"))
+                    .show(ui, |plot| {
+                        for (pass, _, _) in &summary {
+                            let points: PlotPoints = (self.history.iter().enumerate())
+                                .filter_map(|(i, sample)| {
+                                    let (_, ms) = sample.passes.iter().find(|p| p.0 == *pass)?;
+                                    Some([i as f64, *ms as f64])
+                                })
+                                .collect();
+                            plot.line(Line::new(points).name(*pass));
+                        }
+                    });
+            });
+    }
+}
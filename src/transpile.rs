@@ -0,0 +1,594 @@
+// Writing shaders out through naga's back-ends, for engines that don't take WGSL. What goes out is
+// what the pipeline gets, with the prelude, includes and inputs resolved, and a report of the
+// resources `main` uses says where each one ends up in the target:
+//
+//     glsl  uniform blocks, storage blocks, textures and storage images each numbered from 0, in
+//           group and binding order. GLSL has no separate samplers, so they're combined into the
+//           textures they sample.
+//     hlsl  the binding as the register, in the group's register space
+//     msl   buffers, textures and samplers each numbered from 0, in group and binding order
+//     spv   the group and binding as descriptor set and binding
+
+use std::{collections::BTreeMap, fs, io};
+
+use naga::{
+    back::{glsl, hlsl, msl, spv},
+    proc::BoundsCheckPolicies,
+    valid::{Capabilities, ModuleInfo, ValidationFlags, Validator},
+    AddressSpace, ArraySize, GlobalVariable, Handle, ImageClass, ImageDimension, Module,
+    ResourceBinding, ScalarKind, ShaderStage, StorageAccess, Type, TypeInner,
+};
+
+use crate::{
+    cli::{Target, TranspileArgs},
+    input::Inputs,
+    project::Project,
+    shader::{self, error_chain},
+    validate::{self, Severity},
+};
+
+// What a resource is, which decides the kind of slot it takes
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Uniform,
+    Storage { writable: bool },
+    Texture,
+    StorageTexture,
+    Sampler { comparison: bool },
+}
+
+struct Resource {
+    handle: Handle<GlobalVariable>,
+    binding: ResourceBinding,
+    name: String,
+    ty: Handle<Type>,
+    class: Class,
+}
+
+// A line of the report, with the name and type as the target has them
+struct Row {
+    binding: ResourceBinding,
+    name: String,
+    ty: String,
+    slot: String,
+}
+
+// The translated shader, its entry point's name, the report, and anything else integrators need
+// to know
+struct Output {
+    bytes: Vec<u8>,
+    entry_point: String,
+    rows: Vec<Row>,
+    notes: Vec<String>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub fn run(args: &TranspileArgs) -> io::Result<()> {
+    let project = Project::open(&args.path)?;
+    let shader_path = project.shader_path();
+    let mut frag = shader::read_frag_shader(&shader_path)?;
+    frag.declare(&Inputs::open(&project)?.layout());
+
+    // Problems are reported against the user's files, as validate would
+    let diagnostics = validate::check_shader(&shader_path, &frag, true);
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect();
+    if !errors.is_empty() {
+        for d in errors {
+            validate::print_human(d);
+        }
+        return Err(invalid(format!(
+            "{} doesn't compile",
+            shader_path.display()
+        )));
+    }
+
+    let module =
+        naga::front::wgsl::parse_str(&frag.wgsl).map_err(|e| invalid(e.message().to_owned()))?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| invalid(error_chain(&e)))?;
+    let entry_point = module
+        .entry_points
+        .iter()
+        .position(|e| e.name == "main" && e.stage == ShaderStage::Fragment)
+        .ok_or_else(|| invalid("no `@fragment fn main` entry point"))?;
+    let resources = resources(&module, &info, entry_point);
+
+    let output = match args.target {
+        Target::Glsl => write_glsl(&module, &info, &resources),
+        Target::Hlsl => write_hlsl(&module, &info, &resources, entry_point),
+        Target::Msl => write_msl(&module, &info, &resources, entry_point),
+        Target::Spv => write_spv(&module, &info, &resources),
+    }?;
+    fs::write(&args.out, &output.bytes)?;
+
+    println!(
+        "Wrote {}, entry point `{}`",
+        args.out.display(),
+        output.entry_point
+    );
+    print_report(&output.rows);
+    for note in &output.notes {
+        println!("{note}");
+    }
+    Ok(())
+}
+
+// Bound globals `main` uses, in group and binding order
+fn resources(module: &Module, info: &ModuleInfo, entry_point: usize) -> Vec<Resource> {
+    let used = info.get_entry_point(entry_point);
+    let mut resources: Vec<Resource> = module
+        .global_variables
+        .iter()
+        .filter(|(handle, _)| !used[*handle].is_empty())
+        .filter_map(|(handle, global)| {
+            let class = match (global.space, &module.types[global.ty].inner) {
+                (AddressSpace::Uniform, _) => Class::Uniform,
+                (AddressSpace::Storage { access }, _) => Class::Storage {
+                    writable: access.contains(StorageAccess::STORE),
+                },
+                (_, TypeInner::Image { class, .. }) => match class {
+                    ImageClass::Storage { .. } => Class::StorageTexture,
+                    _ => Class::Texture,
+                },
+                (_, TypeInner::Sampler { comparison }) => Class::Sampler {
+                    comparison: *comparison,
+                },
+                _ => return None,
+            };
+            Some(Resource {
+                handle,
+                binding: global.binding.clone()?,
+                name: global.name.clone().unwrap_or_default(),
+                ty: global.ty,
+                class,
+            })
+        })
+        .collect();
+    resources.sort_by(|a, b| a.binding.cmp(&b.binding));
+    resources
+}
+
+fn print_report(rows: &[Row]) {
+    let header = ["group", "binding", "name", "type", "slot"];
+    let cells: Vec<[String; 5]> = rows
+        .iter()
+        .map(|r| {
+            [
+                r.binding.group.to_string(),
+                r.binding.binding.to_string(),
+                r.name.clone(),
+                r.ty.clone(),
+                r.slot.clone(),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let line = |row: [&str; 5]| {
+        let padded: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("  {}", padded.join("  ").trim_end());
+    };
+    line(header);
+    for row in &cells {
+        line(row.each_ref().map(String::as_str));
+    }
+}
+
+fn scalar(kind: ScalarKind) -> &'static str {
+    match kind {
+        ScalarKind::Sint => "int",
+        ScalarKind::Uint => "uint",
+        ScalarKind::Float => "float",
+        ScalarKind::Bool => "bool",
+    }
+}
+
+fn wgsl_scalar(kind: ScalarKind) -> &'static str {
+    match kind {
+        ScalarKind::Sint => "i32",
+        ScalarKind::Uint => "u32",
+        ScalarKind::Float => "f32",
+        ScalarKind::Bool => "bool",
+    }
+}
+
+// A value type as the target spells it, or as WGSL does for SPIR-V. Structs keep their names.
+fn type_name(module: &Module, ty: Handle<Type>, target: Target) -> String {
+    let ty = &module.types[ty];
+    match ty.inner {
+        TypeInner::Scalar { kind, .. } | TypeInner::Atomic { kind, .. } => match target {
+            Target::Spv => wgsl_scalar(kind).to_owned(),
+            _ => scalar(kind).to_owned(),
+        },
+        TypeInner::Vector { size, kind, .. } => {
+            let n = size as u8;
+            match (target, kind) {
+                (Target::Glsl, ScalarKind::Float) => format!("vec{n}"),
+                (Target::Glsl, kind) => format!("{}vec{n}", &scalar(kind)[..1]),
+                (Target::Msl, kind) => format!("metal::{}{n}", scalar(kind)),
+                (Target::Spv, kind) => format!("vec{n}<{}>", wgsl_scalar(kind)),
+                (_, kind) => format!("{}{n}", scalar(kind)),
+            }
+        }
+        TypeInner::Matrix { columns, rows, .. } => {
+            let (c, r) = (columns as u8, rows as u8);
+            match target {
+                Target::Glsl => format!("mat{c}x{r}"),
+                Target::Msl => format!("metal::float{c}x{r}"),
+                Target::Spv => format!("mat{c}x{r}<f32>"),
+                _ => format!("float{c}x{r}"),
+            }
+        }
+        TypeInner::Array { base, size, .. } => {
+            let base = type_name(module, base, target);
+            match (target, size) {
+                (Target::Spv, ArraySize::Constant(n)) => format!("array<{base}, {n}>"),
+                (Target::Spv, ArraySize::Dynamic) => format!("array<{base}>"),
+                (_, ArraySize::Constant(n)) => format!("{base}[{n}]"),
+                (_, ArraySize::Dynamic) => format!("{base}[]"),
+            }
+        }
+        _ => ty.name.clone().unwrap_or_else(|| "?".to_owned()),
+    }
+}
+
+fn image_type(inner: &TypeInner, target: Target) -> String {
+    let TypeInner::Image {
+        dim,
+        arrayed,
+        class,
+    } = *inner
+    else {
+        return "?".to_owned();
+    };
+    let dim = match dim {
+        ImageDimension::D1 => "1D",
+        ImageDimension::D2 => "2D",
+        ImageDimension::D3 => "3D",
+        ImageDimension::Cube => "Cube",
+    };
+    let array = if arrayed { "Array" } else { "" };
+    let (kind, multi) = match class {
+        ImageClass::Sampled { kind, multi } => (kind, multi),
+        ImageClass::Depth { multi } => (ScalarKind::Float, multi),
+        ImageClass::Storage { .. } => (ScalarKind::Float, false),
+    };
+    let ms = if multi { "MS" } else { "" };
+    match (target, class) {
+        (Target::Glsl, ImageClass::Sampled { .. }) => {
+            let prefix = match kind {
+                ScalarKind::Sint => "i",
+                ScalarKind::Uint => "u",
+                _ => "",
+            };
+            format!("{prefix}sampler{dim}{ms}{array}")
+        }
+        (Target::Glsl, ImageClass::Depth { .. }) => format!("sampler{dim}{ms}{array}Shadow"),
+        (Target::Glsl, _) => format!("image{dim}{array}"),
+        (Target::Hlsl, ImageClass::Storage { .. }) => format!("RWTexture{dim}{array}<float4>"),
+        (Target::Hlsl, ImageClass::Depth { .. }) => format!("Texture{dim}{ms}{array}<float>"),
+        (Target::Hlsl, _) => format!("Texture{dim}{ms}{array}<{}4>", scalar(kind)),
+        (_, class) => {
+            let (texture, access) = match class {
+                ImageClass::Depth { .. } => ("depth", "sample"),
+                ImageClass::Storage { .. } => ("texture", "read_write"),
+                _ => ("texture", "sample"),
+            };
+            let ms = if multi { "_ms" } else { "" };
+            let array = if arrayed { "_array" } else { "" };
+            format!(
+                "metal::{texture}{}{ms}{array}<{}, metal::access::{access}>",
+                dim.to_lowercase(),
+                scalar(kind)
+            )
+        }
+    }
+}
+
+// Slots counted from 0 within each kind, in group and binding order
+#[derive(Default)]
+struct Counters(BTreeMap<&'static str, u8>);
+
+impl Counters {
+    fn next(&mut self, kind: &'static str) -> u8 {
+        let count = self.0.entry(kind).or_default();
+        *count += 1;
+        *count - 1
+    }
+}
+
+fn write_glsl(module: &Module, info: &ModuleInfo, resources: &[Resource]) -> io::Result<Output> {
+    let mut binding_map = glsl::BindingMap::new();
+    let mut units = BTreeMap::new();
+    let mut counters = Counters::default();
+    for r in resources {
+        let kind = match r.class {
+            Class::Uniform => "uniform buffer binding",
+            Class::Storage { .. } => "storage buffer binding",
+            Class::Texture => "texture unit",
+            Class::StorageTexture => "image unit",
+            Class::Sampler { .. } => continue,
+        };
+        let slot = counters.next(kind);
+        binding_map.insert(r.binding.clone(), slot);
+        units.insert(r.handle, format!("{kind} {slot}"));
+    }
+
+    let options = glsl::Options {
+        version: glsl::Version::Desktop(450),
+        binding_map,
+        ..Default::default()
+    };
+    let pipeline = glsl::PipelineOptions {
+        shader_stage: ShaderStage::Fragment,
+        entry_point: "main".to_owned(),
+        multiview: None,
+    };
+    let mut out = String::new();
+    let reflection = glsl::Writer::new(
+        &mut out,
+        module,
+        info,
+        &options,
+        &pipeline,
+        BoundsCheckPolicies::default(),
+    )
+    .and_then(|mut writer| writer.write())
+    .map_err(|e| invalid(format!("Can't write GLSL: {e}")))?;
+
+    let texture_name = |handle| {
+        reflection
+            .texture_mapping
+            .iter()
+            .find(|(_, mapping)| mapping.texture == handle)
+            .map(|(name, _)| name.clone())
+    };
+    let mut rows = vec![];
+    for r in resources {
+        let (name, ty, slot) = match r.class {
+            Class::Uniform | Class::Storage { .. } => {
+                let block = &reflection.uniforms[&r.handle];
+                let qualifier = match r.class {
+                    Class::Uniform => "uniform",
+                    _ => "buffer",
+                };
+                let ty = type_name(module, r.ty, Target::Glsl);
+                let name = format!(
+                    "_group_{}_binding_{}_fs",
+                    r.binding.group, r.binding.binding
+                );
+                (
+                    name,
+                    format!("{qualifier} {block} {{ {ty} }}"),
+                    units[&r.handle].clone(),
+                )
+            }
+            Class::Texture | Class::StorageTexture => (
+                texture_name(r.handle).unwrap_or_default(),
+                image_type(&module.types[r.ty].inner, Target::Glsl),
+                units[&r.handle].clone(),
+            ),
+            Class::Sampler { .. } => {
+                let textures: Vec<String> = reflection
+                    .texture_mapping
+                    .iter()
+                    .filter(|(_, mapping)| mapping.sampler == Some(r.handle))
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                (
+                    r.name.clone(),
+                    "sampler".to_owned(),
+                    format!("combined into {}", textures.join(", ")),
+                )
+            }
+        };
+        rows.push(Row {
+            binding: r.binding.clone(),
+            name,
+            ty,
+            slot,
+        });
+    }
+    Ok(Output {
+        bytes: out.into_bytes(),
+        entry_point: "main".to_owned(),
+        rows,
+        notes: vec![],
+    })
+}
+
+fn write_hlsl(
+    module: &Module,
+    info: &ModuleInfo,
+    resources: &[Resource],
+    entry_point: usize,
+) -> io::Result<Output> {
+    let mut out = String::new();
+    let reflection = hlsl::Writer::new(&mut out, &hlsl::Options::default())
+        .write(module, info)
+        .map_err(|e| invalid(format!("Can't write HLSL: {e}")))?;
+    let entry_point = match &reflection.entry_point_names[entry_point] {
+        Ok(name) => name.clone(),
+        Err(e) => return Err(invalid(format!("Can't write HLSL: {e}"))),
+    };
+
+    // Without a binding map, registers are bindings and spaces are groups
+    let rows = resources
+        .iter()
+        .map(|r| {
+            let (register, ty) = match r.class {
+                Class::Uniform => {
+                    let ty = type_name(module, r.ty, Target::Hlsl);
+                    ('b', format!("cbuffer {{ {ty} }}"))
+                }
+                Class::Storage { writable: false } => ('t', "ByteAddressBuffer".to_owned()),
+                Class::Storage { writable: true } => ('u', "RWByteAddressBuffer".to_owned()),
+                Class::Texture => ('t', image_type(&module.types[r.ty].inner, Target::Hlsl)),
+                Class::StorageTexture => ('u', image_type(&module.types[r.ty].inner, Target::Hlsl)),
+                Class::Sampler { comparison: false } => ('s', "SamplerState".to_owned()),
+                Class::Sampler { comparison: true } => ('s', "SamplerComparisonState".to_owned()),
+            };
+            Row {
+                binding: r.binding.clone(),
+                name: r.name.clone(),
+                ty,
+                slot: format!(
+                    "register({register}{}, space{})",
+                    r.binding.binding, r.binding.group
+                ),
+            }
+        })
+        .collect();
+    Ok(Output {
+        bytes: out.into_bytes(),
+        entry_point,
+        rows,
+        notes: vec![],
+    })
+}
+
+fn write_msl(
+    module: &Module,
+    info: &ModuleInfo,
+    resources: &[Resource],
+    entry_point: usize,
+) -> io::Result<Output> {
+    let mut targets = msl::EntryPointResources::default();
+    let mut counters = Counters::default();
+    let mut rows = vec![];
+    let mut has_runtime_array = false;
+    for r in resources {
+        let mut target = msl::BindTarget::default();
+        let (ty, slot) = match r.class {
+            Class::Uniform | Class::Storage { .. } => {
+                let slot = counters.next("buffer");
+                target.buffer = Some(slot);
+                let space = match r.class {
+                    Class::Storage { writable: true } => "device",
+                    Class::Storage { writable: false } => "const device",
+                    _ => "constant",
+                };
+                target.mutable = r.class == Class::Storage { writable: true };
+                has_runtime_array |=
+                    matches!(r.class, Class::Storage { .. }) && ends_in_runtime_array(module, r.ty);
+                let ty = type_name(module, r.ty, Target::Msl);
+                (format!("{space} {ty}&"), format!("[[buffer({slot})]]"))
+            }
+            Class::Texture | Class::StorageTexture => {
+                let slot = counters.next("texture");
+                target.texture = Some(slot);
+                target.mutable = r.class == Class::StorageTexture;
+                let ty = image_type(&module.types[r.ty].inner, Target::Msl);
+                (ty, format!("[[texture({slot})]]"))
+            }
+            Class::Sampler { .. } => {
+                let slot = counters.next("sampler");
+                target.sampler = Some(msl::BindSamplerTarget::Resource(slot));
+                ("metal::sampler".to_owned(), format!("[[sampler({slot})]]"))
+            }
+        };
+        targets.resources.insert(r.binding.clone(), target);
+        rows.push(Row {
+            binding: r.binding.clone(),
+            name: r.name.clone(),
+            ty,
+            slot,
+        });
+    }
+
+    // Metal buffers don't know their length, so runtime-sized arrays get theirs from another one
+    let mut notes = vec![];
+    if has_runtime_array {
+        let slot = counters.next("buffer");
+        targets.sizes_buffer = Some(slot);
+        notes.push(format!(
+            "Storage buffer sizes in bytes go in [[buffer({slot})]], as a uint array"
+        ));
+    }
+
+    let options = msl::Options {
+        per_entry_point_map: BTreeMap::from([("main".to_owned(), targets)]),
+        fake_missing_bindings: false,
+        ..Default::default()
+    };
+    let (out, translation) =
+        msl::write_string(module, info, &options, &msl::PipelineOptions::default())
+            .map_err(|e| invalid(format!("Can't write MSL: {e}")))?;
+    let entry_point = match &translation.entry_point_names[entry_point] {
+        Ok(name) => name.clone(),
+        Err(e) => return Err(invalid(format!("Can't write MSL: {e}"))),
+    };
+    Ok(Output {
+        bytes: out.into_bytes(),
+        entry_point,
+        rows,
+        notes,
+    })
+}
+
+fn ends_in_runtime_array(module: &Module, ty: Handle<Type>) -> bool {
+    match &module.types[ty].inner {
+        TypeInner::Array {
+            size: ArraySize::Dynamic,
+            ..
+        } => true,
+        TypeInner::Struct { members, .. } => members
+            .last()
+            .is_some_and(|m| ends_in_runtime_array(module, m.ty)),
+        _ => false,
+    }
+}
+
+fn write_spv(module: &Module, info: &ModuleInfo, resources: &[Resource]) -> io::Result<Output> {
+    let pipeline = spv::PipelineOptions {
+        shader_stage: ShaderStage::Fragment,
+        entry_point: "main".to_owned(),
+    };
+    let words = spv::write_vec(module, info, &spv::Options::default(), Some(&pipeline))
+        .map_err(|e| invalid(format!("Can't write SPIR-V: {e}")))?;
+
+    // As Vulkan descriptor types, with the WGSL type of buffers
+    let rows = resources
+        .iter()
+        .map(|r| {
+            let ty = match r.class {
+                Class::Uniform => {
+                    format!("uniform buffer of {}", type_name(module, r.ty, Target::Spv))
+                }
+                Class::Storage { .. } => {
+                    format!("storage buffer of {}", type_name(module, r.ty, Target::Spv))
+                }
+                Class::Texture => "sampled image".to_owned(),
+                Class::StorageTexture => "storage image".to_owned(),
+                Class::Sampler { .. } => "sampler".to_owned(),
+            };
+            Row {
+                binding: r.binding.clone(),
+                name: r.name.clone(),
+                ty,
+                slot: format!("set {}, binding {}", r.binding.group, r.binding.binding),
+            }
+        })
+        .collect();
+    Ok(Output {
+        bytes: bytemuck::cast_slice(&words).to_vec(),
+        entry_point: "main".to_owned(),
+        rows,
+        notes: vec![],
+    })
+}
@@ -0,0 +1,470 @@
+// Unit tests for WGSL functions. A spec names a shader file and calls into it,
+//
+//     file = "noise.wgsl"
+//
+//     [[case]]
+//     function = "hash21"
+//     args = [[0.5, 0.25]]
+//     expect = 0.2783
+//     tolerance = 0.0001
+//
+// and every case becomes a line of a generated compute shader that writes the result's bits to a
+// storage buffer, run once on the software adapter and read back.
+
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    Module, ScalarKind, TypeInner,
+};
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    cli::{GpuArgs, UnitArgs},
+    gpu,
+    shader::FragShader,
+};
+
+// Floats need some slack by default, integers and bools don't
+const FLOAT_TOLERANCE: f64 = 1e-5;
+
+const EXIT_FAILED: u8 = 1;
+const EXIT_UNREADABLE: u8 = 2;
+
+#[derive(Deserialize)]
+struct Spec {
+    // Relative to the spec. Defaults to the spec's name without `.test.toml`, plus `.wgsl`.
+    file: Option<PathBuf>,
+    // For every case that doesn't set its own
+    tolerance: Option<f64>,
+    #[serde(default, rename = "case")]
+    cases: Vec<Case>,
+}
+
+#[derive(Deserialize)]
+struct Case {
+    function: String,
+    // Numbers, bools, or arrays of them for vectors
+    #[serde(default)]
+    args: Vec<toml::Value>,
+    expect: toml::Value,
+    tolerance: Option<f64>,
+}
+
+// A scalar, or a vector of `components` of them. Everything else can't cross the result buffer.
+#[derive(Clone, Copy)]
+struct ValueType {
+    kind: ScalarKind,
+    components: usize,
+}
+
+impl ValueType {
+    fn of(module: &Module, ty: naga::Handle<naga::Type>) -> Option<Self> {
+        let (kind, width, components) = match module.types[ty].inner {
+            TypeInner::Scalar { kind, width } => (kind, width, 1),
+            TypeInner::Vector { size, kind, width } => (kind, width, size as usize),
+            _ => return None,
+        };
+        (width == 4 || kind == ScalarKind::Bool).then_some(Self { kind, components })
+    }
+
+    fn wgsl(self) -> String {
+        let scalar = match self.kind {
+            ScalarKind::Float => "f32",
+            ScalarKind::Sint => "i32",
+            ScalarKind::Uint => "u32",
+            ScalarKind::Bool => "bool",
+        };
+        match self.components {
+            1 => scalar.to_owned(),
+            n => format!("vec{n}<{scalar}>"),
+        }
+    }
+
+    // A constructor call for `values`, which has already been checked against this type
+    fn literal(self, values: &[f64]) -> String {
+        let parts: Vec<String> = values
+            .iter()
+            .map(|&v| match self.kind {
+                ScalarKind::Float => format!("{:?}", v as f32),
+                ScalarKind::Sint | ScalarKind::Uint => format!("{}", v as i64),
+                ScalarKind::Bool => format!("{}", v != 0.0),
+            })
+            .collect();
+        format!("{}({})", self.wgsl(), parts.join(", "))
+    }
+
+    // WGSL storing component `i` of `value` as a u32
+    fn store(self, value: &str, i: usize) -> String {
+        let component = match self.components {
+            1 => value.to_owned(),
+            _ => format!("{value}.{}", ["x", "y", "z", "w"][i]),
+        };
+        match self.kind {
+            ScalarKind::Float | ScalarKind::Sint => format!("bitcast<u32>({component})"),
+            ScalarKind::Uint => component,
+            ScalarKind::Bool => format!("select(0u, 1u, {component})"),
+        }
+    }
+
+    fn load(self, bits: u32) -> f64 {
+        match self.kind {
+            ScalarKind::Float => f32::from_bits(bits) as f64,
+            ScalarKind::Sint => bits as i32 as f64,
+            ScalarKind::Uint | ScalarKind::Bool => bits as f64,
+        }
+    }
+
+    fn format(self, values: &[f64]) -> String {
+        let parts: Vec<String> = values
+            .iter()
+            .map(|&v| match self.kind {
+                ScalarKind::Float => format!("{}", v as f32),
+                ScalarKind::Bool => format!("{}", v != 0.0),
+                _ => format!("{v}"),
+            })
+            .collect();
+        match self.components {
+            1 => parts.join(""),
+            _ => format!("[{}]", parts.join(", ")),
+        }
+    }
+}
+
+// A number, bool or array of them, flattened, if it has as many components as `ty`
+fn components(value: &toml::Value, ty: ValueType) -> Option<Vec<f64>> {
+    let scalar = |v: &toml::Value| match v {
+        toml::Value::Float(f) => Some(*f),
+        toml::Value::Integer(i) => Some(*i as f64),
+        toml::Value::Boolean(b) => Some(*b as u8 as f64),
+        _ => None,
+    };
+    let values = match value {
+        toml::Value::Array(items) => items.iter().map(scalar).collect::<Option<Vec<_>>>()?,
+        v => vec![scalar(v)?],
+    };
+    (values.len() == ty.components).then_some(values)
+}
+
+// A case ready to run: the call, and where its result lands in the buffer
+struct Prepared {
+    call: String,
+    result: ValueType,
+    expected: Vec<f64>,
+    tolerance: f64,
+    offset: usize,
+}
+
+fn prepare(module: &Module, spec: &Spec, case: &Case, offset: usize) -> Result<Prepared, String> {
+    let (_, function) = module
+        .functions
+        .iter()
+        .find(|(_, f)| f.name.as_deref() == Some(&case.function))
+        .ok_or_else(|| format!("no function `{}`", case.function))?;
+    if function.arguments.len() != case.args.len() {
+        return Err(format!(
+            "`{}` takes {} arguments, the case has {}",
+            case.function,
+            function.arguments.len(),
+            case.args.len()
+        ));
+    }
+
+    let mut args = vec![];
+    for (i, (arg, value)) in function.arguments.iter().zip(&case.args).enumerate() {
+        let ty = ValueType::of(module, arg.ty)
+            .ok_or_else(|| format!("argument {} isn't a scalar or vector", i + 1))?;
+        let values = components(value, ty)
+            .ok_or_else(|| format!("argument {} should be a {}", i + 1, ty.wgsl()))?;
+        args.push(ty.literal(&values));
+    }
+
+    let result = function
+        .result
+        .as_ref()
+        .and_then(|r| ValueType::of(module, r.ty))
+        .ok_or_else(|| format!("`{}` doesn't return a scalar or vector", case.function))?;
+    let expected = components(&case.expect, result)
+        .ok_or_else(|| format!("expect should be a {}", result.wgsl()))?;
+    let default = match result.kind {
+        ScalarKind::Float => FLOAT_TOLERANCE,
+        _ => 0.0,
+    };
+
+    Ok(Prepared {
+        call: format!("{}({})", case.function, args.join(", ")),
+        result,
+        expected,
+        tolerance: case.tolerance.or(spec.tolerance).unwrap_or(default),
+        offset,
+    })
+}
+
+// Group 3 binding 1, next to the prelude's debugLog at binding 0
+fn wrapper(cases: &[&Prepared]) -> String {
+    let mut wgsl = String::from(
+        "\n@group(3) @binding(1) var<storage, read_write> workbench_unit_results: array<u32>;\n\n\
+         @compute @workgroup_size(1)\nfn workbench_unit_main() {\n",
+    );
+    for case in cases {
+        wgsl += &format!("    {{\n        let r = {};\n", case.call);
+        for i in 0..case.result.components {
+            wgsl += &format!(
+                "        workbench_unit_results[{}] = {};\n",
+                case.offset + i,
+                case.result.store("r", i)
+            );
+        }
+        wgsl += "    }\n";
+    }
+    wgsl + "}\n"
+}
+
+// Run every case in the shader and return the result buffer
+async fn dispatch(gpu: &GpuArgs, wgsl: String, len: usize) -> io::Result<Vec<u32>> {
+    let (device, queue) = gpu::headless(gpu).await?;
+    let size = (len.max(1) * 4) as u64;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Unit Test Shader"),
+        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+    });
+    // Groups 0 to 2 stay empty, functions that read the prelude's other bindings can't be tested
+    let empty = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Unit Test Empty Layout"),
+        entries: &[],
+    });
+    let storage = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let results_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Unit Test Results Layout"),
+        entries: &[storage(0), storage(1)],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Unit Test Pipeline Layout"),
+        bind_group_layouts: &[&empty, &empty, &empty, &results_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Unit Test Pipeline"),
+        layout: Some(&layout),
+        module: &module,
+        entry_point: "workbench_unit_main",
+    });
+    let results = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Unit Test Results"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    // So functions that call debug_print can be tested. No pixel is picked, nothing is logged.
+    let debug_log = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Unit Test Debug Log"),
+        contents: bytemuck::cast_slice(&[u32::MAX, u32::MAX, 0, 0]),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Unit Test Readback"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let empty_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Unit Test Empty Bind Group"),
+        layout: &empty,
+        entries: &[],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Unit Test Bind Group"),
+        layout: &results_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: debug_log.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: results.as_entire_binding(),
+            },
+        ],
+    });
+    if let Some(e) = device.pop_error_scope().await {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Unit Test Encoder"),
+    });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Unit Test Pass"),
+        });
+        pass.set_pipeline(&pipeline);
+        for group in 0..3 {
+            pass.set_bind_group(group, &empty_group, &[]);
+        }
+        pass.set_bind_group(3, &bind_group, &[]);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+    encoder.copy_buffer_to_buffer(&results, 0, &readback, 0, size);
+    queue.submit([encoder.finish()]);
+
+    let slice = readback.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |r| {
+        let _ = tx.send(r);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()
+        .map_err(io::Error::other)?
+        .map_err(io::Error::other)?;
+    let values = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    readback.unmap();
+    Ok(values)
+}
+
+// Passed and total cases, or Err if the spec couldn't be run at all
+async fn run_spec(path: &Path, gpu: &GpuArgs) -> io::Result<(usize, usize)> {
+    let spec: Spec = toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let file = match &spec.file {
+        Some(file) => path.with_file_name(file),
+        None => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let stem = name.strip_suffix(".test.toml").unwrap_or(&name);
+            path.with_file_name(format!("{stem}.wgsl"))
+        }
+    };
+
+    let frag = FragShader::new(&file, fs::read_to_string(&file)?);
+    let module = naga::front::wgsl::parse_str(&frag.wgsl).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} doesn't compile: {}", file.display(), e.message()),
+        )
+    })?;
+
+    let mut prepared = vec![];
+    let mut offset = 0;
+    for case in &spec.cases {
+        let p = prepare(&module, &spec, case, offset);
+        if let Ok(p) = &p {
+            offset += p.result.components;
+        }
+        prepared.push(p);
+    }
+
+    let runnable: Vec<&Prepared> = prepared.iter().filter_map(|p| p.as_ref().ok()).collect();
+    let wgsl = frag.wgsl.clone() + &wrapper(&runnable);
+    // naga's errors are better than what comes back through wgpu's error scope
+    let checked = naga::front::wgsl::parse_str(&wgsl)
+        .map_err(|e| e.emit_to_string(&wgsl))
+        .and_then(|m| {
+            Validator::new(ValidationFlags::all(), Capabilities::empty())
+                .validate(&m)
+                .map_err(|e| e.emit_to_string(&wgsl))
+        });
+    if let Err(e) = checked {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+    let results = dispatch(gpu, wgsl, offset).await?;
+
+    let mut passed = 0;
+    for (case, p) in spec.cases.iter().zip(&prepared) {
+        let p = match p {
+            Ok(p) => p,
+            Err(e) => {
+                println!("  FAILED {}: {e}", case.function);
+                continue;
+            }
+        };
+        let got: Vec<f64> = results[p.offset..p.offset + p.result.components]
+            .iter()
+            .map(|&bits| p.result.load(bits))
+            .collect();
+        let ok = got
+            .iter()
+            .zip(&p.expected)
+            .all(|(g, e)| (g - e).abs() <= p.tolerance);
+        if ok {
+            passed += 1;
+            println!("  ok {} = {}", p.call, p.result.format(&got));
+        } else {
+            println!(
+                "  FAILED {} = {}, expected {} within {}",
+                p.call,
+                p.result.format(&got),
+                p.result.format(&p.expected),
+                p.tolerance
+            );
+        }
+    }
+    Ok((passed, spec.cases.len()))
+}
+
+pub async fn run(args: &UnitArgs) -> ExitCode {
+    // Software unless an adapter was picked, so results don't vary by GPU
+    let mut gpu = args.gpu.clone();
+    gpu.software |= gpu.adapter.is_none();
+
+    let (mut failed, mut unreadable) = (0, false);
+    for path in &args.paths {
+        println!("{}", path.display());
+        match run_spec(path, &gpu).await {
+            Ok((passed, total)) => {
+                failed += total - passed;
+                println!("  {passed} of {total} cases passed");
+            }
+            Err(e) => {
+                unreadable = true;
+                println!("  error: {e}");
+            }
+        }
+    }
+
+    if unreadable {
+        ExitCode::from(EXIT_UNREADABLE)
+    } else if failed > 0 {
+        ExitCode::from(EXIT_FAILED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_stay_clear_of_the_debug_log() {
+        let source =
+            "fn traced(x: f32) -> f32 {\n    debug_print(vec2(0.0), x);\n    return x;\n}\n";
+        let frag = FragShader::new(Path::new("traced.wgsl"), source.to_owned());
+        let module = naga::front::wgsl::parse_str(&frag.wgsl).unwrap();
+        let spec: Spec =
+            toml::from_str("[[case]]\nfunction = \"traced\"\nargs = [1.5]\nexpect = 1.5\n")
+                .unwrap();
+        let case = prepare(&module, &spec, &spec.cases[0], 0).unwrap();
+
+        let wgsl = frag.wgsl.clone() + &wrapper(&[&case]);
+        let module = naga::front::wgsl::parse_str(&wgsl).unwrap();
+        if let Err(e) =
+            Validator::new(ValidationFlags::all(), Capabilities::empty()).validate(&module)
+        {
+            panic!("{}", e.emit_to_string(&wgsl));
+        }
+    }
+}
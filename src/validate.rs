@@ -0,0 +1,325 @@
+// Checking shaders compile without a window or a GPU, by running naga over the same WGSL the
+// pipeline would get. Diagnostics point into the user's file rather than the composed shader.
+
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    Span,
+};
+
+use crate::{
+    cli::{DiagnosticFormat, ValidateArgs},
+    input::Inputs,
+    params,
+    project::Project,
+    shader::{self, error_chain, FragShader},
+};
+
+// Exit statuses, besides success
+const EXIT_INVALID: u8 = 1;
+const EXIT_UNREADABLE: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A marked stretch of the user's source
+#[derive(Debug, Serialize)]
+pub struct Label {
+    pub file: PathBuf,
+    // 1-based
+    pub line: u32,
+    pub column: u32,
+    // In bytes
+    pub length: u32,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    // Where the first label is, or the main shader if there are no labels
+    pub file: PathBuf,
+    pub severity: Severity,
+    pub message: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    fn error(file: &Path, message: String) -> Self {
+        Self {
+            file: file.to_owned(),
+            severity: Severity::Error,
+            message,
+            line: None,
+            column: None,
+            labels: vec![],
+        }
+    }
+
+    fn with_label(mut self, label: Label) -> Self {
+        if self.labels.is_empty() {
+            self.file = label.file.clone();
+            self.line = Some(label.line);
+            self.column = Some(label.column);
+        }
+        self.labels.push(label);
+        self
+    }
+}
+
+// The files making up a composed shader, with the main one under the name it was opened by
+struct Sources<'a> {
+    main: &'a Path,
+    frag: &'a FragShader,
+}
+
+impl Sources<'_> {
+    fn path(&self, file: usize) -> PathBuf {
+        match file {
+            0 => self.main.to_owned(),
+            _ => self.frag.files[file].path.clone(),
+        }
+    }
+
+    fn label(&self, file: usize, line: u32, column: u32, length: usize, message: &str) -> Label {
+        Label {
+            file: self.path(file),
+            line,
+            column,
+            length: length as u32,
+            message: message.to_owned(),
+        }
+    }
+
+    // Spans in generated code are dropped, the user can't do anything about them
+    fn labelled<'a>(
+        &self,
+        mut d: Diagnostic,
+        spans: impl Iterator<Item = (Span, &'a str)>,
+    ) -> Diagnostic {
+        for (span, message) in spans {
+            let Some(range) = span.to_range() else {
+                continue;
+            };
+            if let Some(loc) = self.frag.location(range.start) {
+                let label = self.label(loc.file, loc.line, loc.column, range.len(), message);
+                d = d.with_label(label);
+            }
+        }
+        d
+    }
+}
+
+// Everything wrong with the shader at `path`, a WGSL, GLSL or SPIR-V file or project. Errs if it
+// can't be read.
+pub fn check(path: &Path) -> Result<Vec<Diagnostic>, Diagnostic> {
+    let project = Project::open(path).map_err(|e| Diagnostic::error(path, e.to_string()))?;
+    let shader_path = project.shader_path();
+    let mut frag = shader::read_frag_shader(&shader_path)
+        .map_err(|e| Diagnostic::error(&shader_path, e.to_string()))?;
+    let inputs = Inputs::open(&project).map_err(|e| Diagnostic::error(path, e.to_string()))?;
+    frag.declare(&inputs.layout());
+    Ok(check_shader(&shader_path, &frag, true))
+}
+
+// `require_main` is off for files that are only ever included
+pub fn check_shader(main: &Path, frag: &FragShader, require_main: bool) -> Vec<Diagnostic> {
+    let sources = Sources { main, frag };
+    let mut diagnostics = param_warnings(&sources);
+
+    for e in &frag.include_errors {
+        let text = frag.files[e.file].text.lines().nth(e.line as usize - 1);
+        let length = text.map_or(0, |t| t.len());
+        let label = sources.label(e.file, e.line, 1, length, "");
+        diagnostics.push(Diagnostic::error(main, e.message.clone()).with_label(label));
+    }
+
+    // There's no WGSL to check when GLSL or SPIR-V didn't translate
+    if !frag.import_errors.is_empty() {
+        for e in &frag.import_errors {
+            let mut d = Diagnostic::error(main, e.message.clone());
+            if let Some(loc) = e.location {
+                d = d.with_label(sources.label(loc.file, loc.line, loc.column, e.length, ""));
+            }
+            diagnostics.push(d);
+        }
+        return diagnostics;
+    }
+
+    let module = match naga::front::wgsl::parse_str(&frag.wgsl) {
+        Ok(module) => module,
+        Err(e) => {
+            let d = Diagnostic::error(main, e.message().to_owned());
+            diagnostics.push(sources.labelled(d, e.labels()));
+            return diagnostics;
+        }
+    };
+
+    if let Err(e) = Validator::new(ValidationFlags::all(), Capabilities::empty()).validate(&module)
+    {
+        let spans = e.spans().map(|(span, label)| (*span, label.as_str()));
+        diagnostics.push(sources.labelled(Diagnostic::error(main, error_chain(&e)), spans));
+        return diagnostics;
+    }
+
+    // The pipeline needs this entry point, which naga alone doesn't check for
+    let has_main = module
+        .entry_points
+        .iter()
+        .any(|e| e.name == "main" && e.stage == naga::ShaderStage::Fragment);
+    if require_main && !has_main {
+        diagnostics.push(Diagnostic::error(
+            main,
+            "no `@fragment fn main` entry point".to_owned(),
+        ));
+    }
+    diagnostics
+}
+
+// Annotations that are silently skipped at runtime, so the param never shows up
+fn param_warnings(sources: &Sources) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for (file, source) in sources.frag.files.iter().enumerate() {
+        let lines: Vec<&str> = source.text.lines().collect();
+        for i in params::malformed_decls(&source.text) {
+            let text = lines[i];
+            let column = text.len() - text.trim_start().len() + 1;
+            let label = sources.label(
+                file,
+                i as u32 + 1,
+                column as u32,
+                text.trim().len(),
+                "expected `// @param name = default [min, max]`",
+            );
+            let mut d = Diagnostic::error(
+                sources.main,
+                "malformed param declaration, it will be ignored".to_owned(),
+            )
+            .with_label(label);
+            d.severity = Severity::Warning;
+            diagnostics.push(d);
+        }
+    }
+    diagnostics
+}
+
+pub fn run(args: &ValidateArgs) -> ExitCode {
+    let mut diagnostics = vec![];
+    let (mut failed, mut unreadable) = (0, false);
+    for path in &args.paths {
+        match check(path) {
+            Ok(found) => {
+                if found.iter().any(|d| d.severity == Severity::Error) {
+                    failed += 1;
+                }
+                diagnostics.extend(found);
+            }
+            Err(d) => {
+                failed += 1;
+                unreadable = true;
+                diagnostics.push(d);
+            }
+        }
+    }
+
+    match args.format {
+        DiagnosticFormat::Human => {
+            for d in &diagnostics {
+                print_human(d);
+            }
+            match failed {
+                0 => println!("{} shaders ok", args.paths.len()),
+                _ => println!("{failed} of {} shaders failed", args.paths.len()),
+            }
+        }
+        DiagnosticFormat::Gcc => {
+            for d in &diagnostics {
+                print_gcc(d);
+            }
+        }
+        DiagnosticFormat::Json => {
+            let report = serde_json::json!({
+                "checked": args.paths.len(),
+                "failed": failed,
+                "diagnostics": diagnostics,
+            });
+            println!("{report:#}");
+        }
+    }
+
+    if unreadable {
+        ExitCode::from(EXIT_UNREADABLE)
+    } else if failed > 0 {
+        ExitCode::from(EXIT_INVALID)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    }
+}
+
+// `file:line:col: error: message`, then a note line per extra label
+fn print_gcc(d: &Diagnostic) {
+    let file = d.file.display();
+    let severity = severity_name(d.severity);
+    match (d.line, d.column) {
+        (Some(line), Some(column)) => println!("{file}:{line}:{column}: {severity}: {}", d.message),
+        _ => println!("{file}: {severity}: {}", d.message),
+    }
+    for label in d.labels.iter().filter(|l| !l.message.is_empty()) {
+        println!(
+            "{}:{}:{}: note: {}",
+            label.file.display(),
+            label.line,
+            label.column,
+            label.message
+        );
+    }
+}
+
+// rustc style, with each label's line quoted and underlined
+pub fn print_human(d: &Diagnostic) {
+    println!("{}: {}", severity_name(d.severity), d.message);
+    match (d.line, d.column) {
+        (Some(line), Some(column)) => println!("  --> {}:{line}:{column}", d.file.display()),
+        _ => println!("  --> {}", d.file.display()),
+    }
+
+    let gutter = d
+        .labels
+        .iter()
+        .map(|l| l.line.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for label in &d.labels {
+        if label.file != d.file {
+            println!("  --> {}", label.file.display());
+        }
+        let source = fs::read_to_string(&label.file).unwrap_or_default();
+        let Some(text) = source.lines().nth(label.line as usize - 1) else {
+            continue;
+        };
+        let indent = " ".repeat(label.column as usize - 1);
+        let underline = "^".repeat((label.length as usize).clamp(1, text.len().max(1)));
+        println!("{:gutter$} |", "");
+        println!("{:gutter$} | {text}", label.line);
+        println!("{:gutter$} | {indent}{underline} {}", "", label.message);
+    }
+    println!();
+}
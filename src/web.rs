@@ -0,0 +1,160 @@
+// The browser build: the workbench on a <canvas>, with the shader coming from JavaScript or from a
+// <textarea id="shader"> on the page. Cameras, audio, file watching and includes are native only.
+//
+//     import init, { start } from "./pkg/wgsl_workbench.js";
+//     await init();
+//     const workbench = await start("canvas");
+//     workbench.set_shader(source);
+
+use parking_lot::RwLock;
+use std::{io, path::Path, rc::Rc};
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{HtmlCanvasElement, HtmlTextAreaElement};
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::EventLoop,
+    platform::web::{EventLoopExtWebSys, WindowBuilderExtWebSys, WindowExtWebSys},
+    window::WindowBuilder,
+};
+
+use crate::{
+    appstate::{App, RenderPipelineContext},
+    cli::{GpuArgs, PresentMode},
+    handle_event,
+    project::Project,
+    shader::FragShader,
+};
+
+// Stands in for a path on disk, which the project and presets are named after
+const SHADER_PATH: &str = "shader.wgsl";
+
+#[wasm_bindgen(start)]
+pub fn init() {
+    console_error_panic_hook::set_once();
+}
+
+#[wasm_bindgen]
+pub struct Workbench {
+    app: Rc<RwLock<App>>,
+}
+
+#[wasm_bindgen]
+impl Workbench {
+    // Throws the shader's errors if it doesn't compile, leaving the previous one running
+    pub fn set_shader(&self, source: String) -> Result<(), JsValue> {
+        compile(&self.app, source)
+    }
+
+    // False if the shader doesn't declare the param
+    pub fn set_param(&self, name: &str, value: f32) -> bool {
+        let mut app = self.app.write();
+        app.renderer.sync_params();
+        app.renderer.params.set(name, value)
+    }
+
+    pub fn time(&self) -> f32 {
+        self.app.read().clock.time()
+    }
+
+    pub fn set_time(&self, time: f32) {
+        self.app.write().clock.set_time(time);
+    }
+}
+
+// Opens the workbench on the canvas with id `canvas_id`, or on a new 1280x720 one added to the page
+#[wasm_bindgen]
+pub async fn start(canvas_id: Option<String>) -> Result<Workbench, JsValue> {
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("no document")?;
+    let canvas = match &canvas_id {
+        Some(id) => Some(
+            document
+                .get_element_by_id(id)
+                .ok_or_else(|| format!("no element with id {id}"))?
+                .dyn_into::<HtmlCanvasElement>()?,
+        ),
+        None => None,
+    };
+    let (width, height) = canvas
+        .as_ref()
+        .map_or((1280, 720), |c| (c.width(), c.height()));
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_canvas(canvas.clone())
+        .with_inner_size(PhysicalSize::new(width, height))
+        .build(&event_loop)
+        .map_err(|e| e.to_string())?;
+    if canvas.is_none() {
+        let body = document.body().ok_or("no body")?;
+        body.append_child(&window.canvas())?;
+    }
+
+    // There's no file to read, so this is the default project running the default shader
+    let project = Project::open(Path::new(SHADER_PATH)).map_err(|e| e.to_string())?;
+    let app = App::new(
+        window,
+        None,
+        project,
+        &GpuArgs::default(),
+        PresentMode::default(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let app = Rc::new(RwLock::new(app));
+
+    if let Some(textarea) = document
+        .get_element_by_id("shader")
+        .and_then(|e| e.dyn_into::<HtmlTextAreaElement>().ok())
+    {
+        watch_textarea(app.clone(), textarea)?;
+    }
+
+    let workbench = Workbench { app: app.clone() };
+    // Unlike run, spawn returns instead of throwing to escape the caller
+    event_loop.spawn(move |event, _, control_flow| handle_event(&app, &event, control_flow));
+    Ok(workbench)
+}
+
+fn compile(app: &RwLock<App>, source: String) -> Result<(), JsValue> {
+    let frag = FragShader::with_loader(Path::new(SHADER_PATH), source, |_| {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "includes need the native build",
+        ))
+    });
+    if !frag.include_errors.is_empty() {
+        let messages: Vec<String> = frag
+            .include_errors
+            .iter()
+            .map(|e| format!("line {}: {}", e.line, e.message))
+            .collect();
+        return Err(JsValue::from_str(&messages.join("\n")));
+    }
+
+    let rpctx = app.read().renderer.rpcontext.clone();
+    RenderPipelineContext::set_shader(&rpctx, frag).map_err(|errors| {
+        let descriptions: Vec<String> = errors.into_iter().map(|e| e.description).collect();
+        JsValue::from_str(&descriptions.join("\n"))
+    })
+}
+
+// Compiles the textarea now and on every edit, logging errors to the console
+fn watch_textarea(app: Rc<RwLock<App>>, textarea: HtmlTextAreaElement) -> Result<(), JsValue> {
+    if let Err(e) = compile(&app, textarea.value()) {
+        web_sys::console::error_1(&e);
+    }
+
+    let source = textarea.clone();
+    let on_input = Closure::<dyn FnMut(web_sys::Event)>::new(move |_| {
+        if let Err(e) = compile(&app, source.value()) {
+            web_sys::console::error_1(&e);
+        }
+    });
+    textarea.add_event_listener_with_callback("input", on_input.as_ref().unchecked_ref())?;
+    // The listener lives as long as the page
+    on_input.forget();
+    Ok(())
+}
@@ -0,0 +1,404 @@
+// The library surface: the workbench's renderer without its window, event loop or GUI, for other
+// apps to embed. Everything here is re-exported from the crate root.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use wgpu::{TextureFormat, TextureView};
+use winit::{event::WindowEvent, window::Window};
+
+use crate::{
+    appstate::RenderPipelineContext,
+    cli::GpuArgs,
+    gpu,
+    input::{InputSource, Inputs},
+    renderer::Renderer,
+    resize::ResizePolicy,
+    shader::{self, FragShader},
+};
+
+/// Where [`Workbench::render_frame`] draws to.
+pub enum Output {
+    /// A texture owned by the workbench, read back with [`Workbench::read_pixels`].
+    Offscreen { width: u32, height: u32 },
+    /// A view on the caller's device, in `format`. Replace it with [`Workbench::set_view`].
+    ///
+    /// The workbench installs its own uncaptured error handler on `device`.
+    View {
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        view: TextureView,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+    },
+    /// A window, presented to at the end of every frame. The caller runs the event loop.
+    Window(Window),
+}
+
+enum Shader {
+    Default,
+    File(PathBuf),
+    // Includes resolve against `path`'s directory
+    Source { path: PathBuf, source: String },
+}
+
+/// Configures a [`Workbench`]. Made with [`Workbench::builder`].
+pub struct WorkbenchBuilder {
+    shader: Shader,
+    inputs: Inputs,
+    params: BTreeMap<String, f32>,
+    output: Output,
+}
+
+impl WorkbenchBuilder {
+    /// Load the fragment shader from a file, following its `// @include`s.
+    pub fn shader_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shader = Shader::File(path.into());
+        self
+    }
+
+    /// Use fragment shader source directly. Includes are relative to the working directory.
+    pub fn shader_source(mut self, source: impl Into<String>) -> Self {
+        self.shader = Shader::Source {
+            path: PathBuf::from("shader.wgsl"),
+            source: source.into(),
+        };
+        self
+    }
+
+    /// Bind `source` under `name`, polled at the start of every [`Workbench::render_frame`].
+    pub fn input(mut self, name: &str, source: Box<dyn InputSource>) -> Self {
+        self.inputs.push(name, source);
+        self
+    }
+
+    /// Starting value for a param declared with `// @param`.
+    pub fn param(mut self, name: impl Into<String>, value: f32) -> Self {
+        self.params.insert(name.into(), value);
+        self
+    }
+
+    /// Where frames go. Defaults to a 1280x720 offscreen texture.
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Open a device if the output doesn't come with one, and compile the shader. Fails if the
+    /// shader can't be read or doesn't validate.
+    pub async fn build(self) -> io::Result<Workbench> {
+        let gpu = GpuArgs::default();
+        let layout = self.inputs.layout();
+        let (renderer, target) = match self.output {
+            Output::Offscreen { width, height } => {
+                let (device, queue) = gpu::headless(&gpu).await?;
+                let renderer = Renderer::new(
+                    device,
+                    queue,
+                    TextureFormat::Rgba8UnormSrgb,
+                    (width, height),
+                    &layout,
+                    self.shader.path(),
+                    None,
+                );
+                (renderer, Target::Offscreen)
+            }
+            Output::View {
+                device,
+                queue,
+                view,
+                format,
+                width,
+                height,
+            } => {
+                let size = (width, height);
+                let renderer = Renderer::new(
+                    device,
+                    queue,
+                    format,
+                    size,
+                    &layout,
+                    self.shader.path(),
+                    None,
+                );
+                (renderer, Target::View { view, size })
+            }
+            Output::Window(window) => {
+                let instance = gpu::instance(&gpu);
+                // Safety: the surface is dropped before the window, see Target::Window
+                let surface =
+                    unsafe { instance.create_surface(&window) }.map_err(io::Error::other)?;
+                let adapter = gpu::adapter(&instance, &gpu, Some(&surface)).await?;
+                let (device, queue) = gpu::device(&adapter).await?;
+
+                let caps = surface.get_capabilities(&adapter);
+                let format = caps
+                    .formats
+                    .iter()
+                    .copied()
+                    .find(|f| f.is_srgb())
+                    .unwrap_or(caps.formats[0]);
+                let size = window.inner_size();
+                let config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format,
+                    width: size.width.max(1),
+                    height: size.height.max(1),
+                    present_mode: wgpu::PresentMode::AutoVsync,
+                    alpha_mode: caps.alpha_modes[0],
+                    view_formats: vec![],
+                };
+                surface.configure(&device, &config);
+
+                let renderer = Renderer::new(
+                    device,
+                    queue,
+                    format,
+                    (config.width, config.height),
+                    &layout,
+                    self.shader.path(),
+                    None,
+                );
+                let target = Target::Window {
+                    surface,
+                    config,
+                    window: Box::new(window),
+                };
+                (renderer, target)
+            }
+        };
+
+        let mut workbench = Workbench {
+            renderer,
+            target,
+            inputs: self.inputs,
+            shader_file: None,
+        };
+        match self.shader {
+            Shader::Default => {}
+            Shader::File(path) => {
+                workbench.set_shader(shader::read_frag_shader(&path)?)?;
+                workbench.shader_file = Some(path);
+            }
+            Shader::Source { path, source } => {
+                workbench.set_shader(FragShader::new(&path, source))?;
+            }
+        }
+        workbench.renderer.params.apply(&self.params);
+        Ok(workbench)
+    }
+}
+
+impl Shader {
+    fn path(&self) -> &Path {
+        match self {
+            Shader::Default => Path::new(""),
+            Shader::File(path) | Shader::Source { path, .. } => path,
+        }
+    }
+}
+
+enum Target {
+    Offscreen,
+    View {
+        view: TextureView,
+        size: (u32, u32),
+    },
+    // Field order matters, the surface has to go before the window it was made from
+    Window {
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+        window: Box<Window>,
+    },
+}
+
+/// A fragment shader with the workbench's prelude, uniforms and feedback buffer, rendering to an
+/// [`Output`].
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// let mut workbench = wgsl_workbench::Workbench::builder()
+///     .shader_file("shader.wgsl")
+///     .param("speed", 2.0)
+///     .build()
+///     .await?;
+/// workbench.render_frame(0.5)?;
+/// let rgba = workbench.read_pixels()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Workbench {
+    renderer: Renderer,
+    target: Target,
+    inputs: Inputs,
+    shader_file: Option<PathBuf>,
+}
+
+impl Workbench {
+    pub fn builder() -> WorkbenchBuilder {
+        WorkbenchBuilder {
+            shader: Shader::Default,
+            inputs: Inputs::default(),
+            params: BTreeMap::new(),
+            output: Output::Offscreen {
+                width: 1280,
+                height: 720,
+            },
+        }
+    }
+
+    /// Draw the next frame at `time` seconds. Advances `frame`, and becomes the next frame's
+    /// `backBuffer`.
+    pub fn render_frame(&mut self, time: f32) -> io::Result<()> {
+        self.renderer.sync_params();
+        self.renderer.time = time;
+        self.inputs.update(&mut self.renderer);
+
+        let mut encoder = self
+            .renderer
+            .rpcontext
+            .read()
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Workbench Encoder"),
+            });
+        self.renderer.render(&mut encoder);
+
+        let policy = ResizePolicy::default();
+        match &self.target {
+            Target::Offscreen => {
+                self.renderer.queue.submit([encoder.finish()]);
+            }
+            Target::View { view, size } => {
+                self.renderer.present(&mut encoder, view, *size, &policy);
+                self.renderer.queue.submit([encoder.finish()]);
+            }
+            Target::Window {
+                surface, config, ..
+            } => {
+                let output = surface.get_current_texture().map_err(io::Error::other)?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                let size = (config.width, config.height);
+                self.renderer.present(&mut encoder, &view, size, &policy);
+                self.renderer.queue.submit([encoder.finish()]);
+                output.present();
+            }
+        }
+        Ok(())
+    }
+
+    /// Set a param declared with `// @param`. False if the shader doesn't declare it.
+    pub fn set_param(&mut self, name: &str, value: f32) -> bool {
+        self.renderer.sync_params();
+        self.renderer.params.set(name, value)
+    }
+
+    pub fn param(&self, name: &str) -> Option<f32> {
+        self.renderer.params.get(name)
+    }
+
+    /// Every declared param and its current value.
+    pub fn params(&self) -> BTreeMap<String, f32> {
+        self.renderer.params.values()
+    }
+
+    /// The number of frames rendered so far, as the shader sees it in `frame`.
+    pub fn frame(&self) -> u32 {
+        self.renderer.frame
+    }
+
+    /// Upload an RGBA8 frame as `videoBuffer`, which is a single black pixel until then.
+    pub fn set_video_frame(&mut self, width: u32, height: u32, rgba: &[u8]) {
+        self.renderer.upload_video(width, height, rgba);
+    }
+
+    /// Swap in new shader source, keeping the current shader if it doesn't validate.
+    pub fn set_shader_source(&mut self, source: impl Into<String>) -> io::Result<()> {
+        let path = self.renderer.shader_path.clone();
+        self.set_shader(FragShader::new(&path, source.into()))
+    }
+
+    /// Read the shader file given to [`WorkbenchBuilder::shader_file`] again.
+    pub fn reload_shader(&mut self) -> io::Result<()> {
+        let Some(path) = &self.shader_file else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the shader wasn't loaded from a file",
+            ));
+        };
+        let frag = shader::read_frag_shader(path)?;
+        self.set_shader(frag)
+    }
+
+    fn set_shader(&mut self, frag: FragShader) -> io::Result<()> {
+        RenderPipelineContext::set_shader(&self.renderer.rpcontext, frag).map_err(|errors| {
+            let descriptions: Vec<String> = errors.into_iter().map(|e| e.description).collect();
+            io::Error::new(io::ErrorKind::InvalidData, descriptions.join("\n"))
+        })?;
+        self.renderer.sync_params();
+        Ok(())
+    }
+
+    /// Pass on a window event, for inputs that follow the mouse or keyboard.
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        self.inputs.window_event(event);
+    }
+
+    /// Change the render size. For [`Output::View`], use [`Workbench::set_view`] instead.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        if let Target::Window {
+            surface, config, ..
+        } = &mut self.target
+        {
+            config.width = width;
+            config.height = height;
+            surface.configure(&self.renderer.rpcontext.read().device, config);
+        }
+        self.renderer.resize((width, height), true);
+    }
+
+    /// Render into a different view from now on, for [`Output::View`].
+    pub fn set_view(&mut self, view: TextureView, width: u32, height: u32) {
+        self.renderer.resize((width, height), true);
+        self.target = Target::View {
+            view,
+            size: (width, height),
+        };
+    }
+
+    /// The last frame as tightly packed RGBA8 rows at render size. BGRA8 targets are swizzled to
+    /// RGBA8, and an [`Output::View`] in any other format is an error.
+    pub fn read_pixels(&self) -> io::Result<Vec<u8>> {
+        self.renderer.read_target()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.renderer.size()
+    }
+
+    pub fn device(&self) -> Arc<wgpu::Device> {
+        self.renderer.rpcontext.read().device.clone()
+    }
+
+    pub fn queue(&self) -> Arc<wgpu::Queue> {
+        self.renderer.queue.clone()
+    }
+
+    /// The window from [`Output::Window`].
+    pub fn window(&self) -> Option<&Window> {
+        match &self.target {
+            Target::Window { window, .. } => Some(window.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::{env, fs};
+
+use wgsl_workbench::GoldenTest;
+
+fn golden(name: &str) -> String {
+    format!("{}/tests/golden/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+// Rewrite the reference with WORKBENCH_UPDATE_GOLDEN=1 after changing the shader
+#[test]
+fn gradient_matches_its_reference() {
+    GoldenTest::new(golden("gradient.wgsl"), golden("gradient.png"))
+        .time(1.0)
+        .assert();
+}
+
+#[test]
+fn gradient_differs_at_another_time() {
+    // A copy, so the failure's actual and diff images aren't left beside the real one
+    let reference = env::temp_dir().join("wgsl_workbench_gradient.png");
+    fs::copy(golden("gradient.png"), &reference).unwrap();
+
+    let report = GoldenTest::new(golden("gradient.wgsl"), &reference)
+        .time(2.5)
+        .run()
+        .unwrap();
+    assert!(!report.passed, "{report}");
+    assert!(report.failing_pixels > 0);
+    assert!(report.diff.is_some_and(|diff| diff.exists()));
+}
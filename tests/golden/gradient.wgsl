@@ -0,0 +1,8 @@
+// A gradient with a disc that moves with time, for tests/golden.rs
+@fragment
+fn main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = fragCoord(pos) / res;
+    let centre = vec2<f32>(0.5 + 0.25 * sin(time), 0.5);
+    let disc = 1.0 - smoothstep(0.2, 0.22, distance(uv, centre));
+    return vec4<f32>(mix(vec3<f32>(uv, 0.25), vec3<f32>(1.0, 0.9, 0.2), disc), 1.0);
+}